@@ -1,8 +1,12 @@
+use pdf::backend::{Backend, ReaderBackend};
+use pdf::build::{FieldCollision, FormMerger};
+use pdf::crypt::Permissions;
 use pdf::error::PdfError;
-use pdf::file::{CachedFile, FileOptions};
-use pdf::object::{FieldDictionary, FieldType, RcRef, Updater, Annot};
+use pdf::file::{CachedFile, FileOptions, SaveOptions};
+use pdf::object::{FieldDictionary, FieldType, ImageXObject, PlainRef, RcRef, Resolve, Updater, Annot};
 use pdf::primitive::{Primitive, PdfString, Dictionary};
 use std::collections::HashMap;
+use std::io::{BufWriter, Read, Seek, Write};
 use std::path::Path;
 
 use crate::field::{FieldDictionaryExt, InteractiveFormDictionaryExt};
@@ -41,6 +45,12 @@ pub enum FieldValue {
     Choice(String),
     /// Integer value (used for numeric fields)
     Integer(i32),
+    /// The bytes of a JPEG or PNG file (used for push buttons that show a picture)
+    ///
+    /// The image becomes the icon and appearance of the button, see
+    /// `AcroFormDocument::set_button_image()`. Buttons have no value, so this is never read
+    /// from a PDF.
+    Image(Vec<u8>),
 }
 
 impl FieldValue {
@@ -65,6 +75,8 @@ impl FieldValue {
     /// This method converts a typed `FieldValue` into the corresponding PDF primitive
     /// that can be written to a PDF file.
     ///
+    /// Images are not stored in the value and give `Primitive::Null`.
+    ///
     /// This is primarily an internal method used when writing field values to PDFs.
     pub fn to_primitive(&self) -> Primitive {
         match self {
@@ -84,6 +96,7 @@ impl FieldValue {
             FieldValue::Integer(i) => Primitive::Integer(*i),
             FieldValue::Choice(s) => Primitive::Name(s.as_str().into()),
             FieldValue::Boolean(b) => Primitive::Boolean(*b),
+            FieldValue::Image(_) => Primitive::Null,
         }
     }
 }
@@ -111,8 +124,8 @@ impl FieldValue {
 /// values.insert("name".to_string(), FieldValue::Text("John".to_string()));
 /// doc.fill_and_save(values, "filled.pdf").unwrap();
 /// ```
-pub struct AcroFormDocument {
-    file: CachedFile<Vec<u8>>,
+pub struct AcroFormDocument<B = Vec<u8>> {
+    pub(crate) file: CachedFile<B>,
 }
 
 impl AcroFormDocument {
@@ -166,6 +179,115 @@ impl AcroFormDocument {
         Ok(AcroFormDocument { file })
     }
     
+    /// Load a password-protected PDF file from the given path
+    ///
+    /// Either the user or the owner password may be given.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the PDF file to load
+    /// * `password` - The user or owner password of the document
+    ///
+    /// # Errors
+    ///
+    /// Returns `PdfError::PasswordRequired` if the document needs a password and an empty one
+    /// was given, `PdfError::InvalidPassword` if the password is wrong, or another `PdfError`
+    /// if the file cannot be opened or parsed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use acroform::AcroFormDocument;
+    ///
+    /// let doc = AcroFormDocument::from_pdf_with_password("form.pdf", "secret").unwrap();
+    /// ```
+    pub fn from_pdf_with_password(path: impl AsRef<Path>, password: &str) -> Result<Self, PdfError> {
+        let file = FileOptions::cached().password(password.as_bytes()).open(path)?;
+        Ok(AcroFormDocument { file })
+    }
+
+    /// Load a password-protected PDF from a byte vector
+    ///
+    /// Either the user or the owner password may be given.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - A byte vector containing the PDF data
+    /// * `password` - The user or owner password of the document
+    ///
+    /// # Errors
+    ///
+    /// Returns `PdfError::PasswordRequired` if the document needs a password and an empty one
+    /// was given, `PdfError::InvalidPassword` if the password is wrong, or another `PdfError`
+    /// if the data cannot be parsed as a valid PDF.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use acroform::AcroFormDocument;
+    /// use std::fs;
+    ///
+    /// let data = fs::read("form.pdf").unwrap();
+    /// let doc = AcroFormDocument::from_bytes_with_password(data, "secret").unwrap();
+    /// ```
+    pub fn from_bytes_with_password(data: Vec<u8>, password: &str) -> Result<Self, PdfError> {
+        let file = FileOptions::cached().password(password.as_bytes()).load(data)?;
+        Ok(AcroFormDocument { file })
+    }
+}
+
+impl<R: Read + Seek> AcroFormDocument<ReaderBackend<R>> {
+    /// Load a PDF from any `Read + Seek` source without reading all of it into memory
+    ///
+    /// Only a bounded number of blocks of the source are cached. Filling the form copies
+    /// the unchanged bytes from the source into the output.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PdfError` if the source cannot be read or parsed as a valid PDF.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use acroform::AcroFormDocument;
+    /// use std::fs::File;
+    ///
+    /// let doc = AcroFormDocument::from_reader(File::open("form.pdf").unwrap()).unwrap();
+    /// ```
+    pub fn from_reader(reader: R) -> Result<Self, PdfError> {
+        let file = FileOptions::cached().load(ReaderBackend::new(reader)?)?;
+        Ok(AcroFormDocument { file })
+    }
+
+    /// Load a password-protected PDF from any `Read + Seek` source
+    ///
+    /// Like `from_reader()`, with the user or owner password of the document.
+    pub fn from_reader_with_password(reader: R, password: &str) -> Result<Self, PdfError> {
+        let file = FileOptions::cached().password(password.as_bytes()).load(ReaderBackend::new(reader)?)?;
+        Ok(AcroFormDocument { file })
+    }
+}
+
+impl<B: Backend> AcroFormDocument<B> {
+    /// Get the access permissions of the document
+    ///
+    /// The permissions are decoded from the `/P` entry of the encryption dictionary.
+    /// Unencrypted documents permit all operations.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use acroform::AcroFormDocument;
+    ///
+    /// let doc = AcroFormDocument::from_pdf_with_password("form.pdf", "secret").unwrap();
+    /// if !doc.permissions().can_fill_forms() {
+    ///     println!("filling this form is not permitted");
+    /// }
+    /// ```
+    pub fn permissions(&self) -> Permissions {
+        self.file.permissions()
+    }
+    
     /// Get all form fields in the PDF
     ///
     /// Returns a vector of all fillable form fields in the document.
@@ -250,6 +372,82 @@ impl AcroFormDocument {
         &mut self,
         values: HashMap<String, FieldValue>,
     ) -> Result<Vec<u8>, PdfError> {
+        self.fill_with(values, &SaveOptions::default())
+    }
+
+    /// Fill form fields and return the PDF written according to `options`
+    ///
+    /// Like `fill()`, but e.g. allows the updated objects to be packed into compressed
+    /// object streams, so that PDF 1.5+ forms don't grow with every fill.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use acroform::{AcroFormDocument, FieldValue, SaveOptions};
+    /// use std::collections::HashMap;
+    ///
+    /// let mut doc = AcroFormDocument::from_pdf("form.pdf").unwrap();
+    /// let mut values = HashMap::new();
+    /// values.insert("firstName".to_string(), FieldValue::Text("John".to_string()));
+    /// let filled_pdf = doc.fill_with(values, &SaveOptions::new().object_streams(100)).unwrap();
+    /// ```
+    pub fn fill_with(
+        &mut self,
+        values: HashMap<String, FieldValue>,
+        options: &SaveOptions,
+    ) -> Result<Vec<u8>, PdfError> {
+        let mut out = Vec::new();
+        self.fill_to_writer_with(values, options, &mut out)?;
+        Ok(out)
+    }
+
+    /// Fill form fields and write the PDF to `out`
+    ///
+    /// Like `fill()`, but the original bytes and the new revision are written straight
+    /// to `out`, e.g. a file or socket, without building the whole document in memory.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use acroform::{AcroFormDocument, FieldValue};
+    /// use std::collections::HashMap;
+    /// use std::io::BufWriter;
+    ///
+    /// let mut doc = AcroFormDocument::from_pdf("form.pdf").unwrap();
+    /// let mut values = HashMap::new();
+    /// values.insert("firstName".to_string(), FieldValue::Text("John".to_string()));
+    /// let out = BufWriter::new(std::fs::File::create("filled.pdf").unwrap());
+    /// doc.fill_to_writer(values, out).unwrap();
+    /// ```
+    pub fn fill_to_writer(
+        &mut self,
+        values: HashMap<String, FieldValue>,
+        out: impl Write,
+    ) -> Result<(), PdfError> {
+        self.fill_to_writer_with(values, &SaveOptions::default(), out)
+    }
+
+    /// Fill form fields and write the PDF to `out` according to `options`
+    ///
+    /// Combines `fill_to_writer()` with the options of `fill_with()`.
+    pub fn fill_to_writer_with(
+        &mut self,
+        values: HashMap<String, FieldValue>,
+        options: &SaveOptions,
+        mut out: impl Write,
+    ) -> Result<(), PdfError> {
+        // Images go into the appearance of the buttons instead of their value
+        let (images, values): (HashMap<_, _>, HashMap<_, _>) = values.into_iter()
+            .partition(|(_, value)| matches!(value, FieldValue::Image(_)));
+        for (name, value) in images {
+            if let FieldValue::Image(data) = value {
+                let widgets = self.button_widgets(&name)?;
+                if !widgets.is_empty() {
+                    self.set_widget_image(&widgets, &data)?;
+                }
+            }
+        }
+
         // Collect field references and their values to update
         let mut field_updates: Vec<(pdf::object::PlainRef, FieldDictionary)> = Vec::new();
         let mut annotation_updates: Vec<(pdf::object::PlainRef, Annot)> = Vec::new();
@@ -320,8 +518,9 @@ impl AcroFormDocument {
             self.file.update(annot_ref, updated_annot)?;
         }
         
-        // Return the file as bytes instead of saving to disk
-        Ok(self.file.save()?)
+        self.file.save_to_writer(options, &mut out)?;
+        out.flush()?;
+        Ok(())
     }
     
     /// Fill form fields with provided values and save to a new file
@@ -329,7 +528,7 @@ impl AcroFormDocument {
     /// Updates the specified form fields with new values and writes the modified
     /// PDF to the output path. Fields not specified in the `values` map remain unchanged.
     ///
-    /// This is a convenience method that combines `fill()` with writing to disk.
+    /// This is a convenience method that combines `fill_to_writer()` with creating the file.
     /// For in-memory operations, use `fill()` directly.
     ///
     /// # Arguments
@@ -361,10 +560,132 @@ impl AcroFormDocument {
         values: HashMap<String, FieldValue>,
         output: impl AsRef<Path>,
     ) -> Result<(), PdfError> {
-        let bytes = self.fill(values)?;
-        std::fs::write(output, bytes)?;
+        let out = BufWriter::new(std::fs::File::create(output)?);
+        self.fill_to_writer(values, out)
+    }
+
+    /// Show a picture on the push button field `name`
+    ///
+    /// `image` is the content of a JPEG or PNG file. It becomes the icon (`/MK /I`) of
+    /// each widget of the button, and the normal appearance shows it fitted into the
+    /// widget as the icon fit settings (`/MK /IF`) of the widget say: scaled always, only
+    /// when it is bigger or smaller than the widget, or never, proportionally or not,
+    /// and aligned in the space that is left.
+    ///
+    /// `fill()` does the same for `FieldValue::Image` values.
+    ///
+    /// # Errors
+    ///
+    /// Returns `PdfError` if there is no field `name`, it is not a push button, or the
+    /// image can't be read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use acroform::AcroFormDocument;
+    ///
+    /// let mut doc = AcroFormDocument::from_pdf("form.pdf").unwrap();
+    /// doc.set_button_image("photo", &std::fs::read("photo.jpg").unwrap()).unwrap();
+    /// std::fs::write("filled.pdf", doc.fill(Default::default()).unwrap()).unwrap();
+    /// ```
+    pub fn set_button_image(&mut self, name: &str, image: &[u8]) -> Result<(), PdfError> {
+        let widgets = self.button_widgets(name)?;
+        if widgets.is_empty() {
+            return Err(PdfError::Other { msg: format!("there is no field {:?}", name) });
+        }
+        self.set_widget_image(&widgets, image)
+    }
+
+    /// Show the image file `data` on all `widgets`, which share one image XObject
+    fn set_widget_image(&mut self, widgets: &[PlainRef], data: &[u8]) -> Result<(), PdfError> {
+        let image = image_xobject(data, &mut self.file)?;
+        let image = self.file.create(image)?.get_ref().get_inner();
+        for &widget in widgets {
+            self.file.set_button_image(widget, image)?;
+        }
         Ok(())
     }
+
+    /// The widget annotations of the push button `name`, none if there is no such field
+    fn button_widgets(&self, name: &str) -> Result<Vec<PlainRef>, PdfError> {
+        const PUSH_BUTTON: u32 = 1 << 16;
+        let resolver = self.file.resolver();
+        let is_widget = |dict: &Dictionary| matches!(dict.get("Subtype"), Some(Primitive::Name(n)) if n == "Widget");
+        let mut widgets = vec![];
+
+        if let Some(ref forms) = self.file.get_root().forms {
+            if let Some(field) = forms.find_field_by_name(name, &resolver)? {
+                if field.typ != Some(FieldType::Button) || field.flags & PUSH_BUTTON == 0 {
+                    return Err(PdfError::Other { msg: format!("field {} is not a push button", name) });
+                }
+                // the field is its only widget, or the widgets are its kids
+                let r = field.get_ref().get_inner();
+                if is_widget(&resolver.resolve(r)?.into_dictionary()?) {
+                    widgets.push(r);
+                }
+                for kid in field.kids.iter() {
+                    if is_widget(&resolver.resolve(kid.get_inner())?.into_dictionary()?) {
+                        widgets.push(kid.get_inner());
+                    }
+                }
+            }
+        }
+
+        // widget annotations that carry the field name themselves
+        for page in self.file.pages() {
+            for annot in page?.annotations.load(&resolver)?.data().iter() {
+                if let (Some(Primitive::String(t)), Some(r)) = (annot.data().other.get("T"), annot.as_ref()) {
+                    if t.to_string_lossy() == name && annot.subtype.as_str() == "Widget" && !widgets.contains(&r.get_inner()) {
+                        widgets.push(r.get_inner());
+                    }
+                }
+            }
+        }
+        Ok(widgets)
+    }
+
+    /// Combine several forms into one PDF
+    ///
+    /// The pages of all documents are appended in order, and their fields are joined in
+    /// one form. `collisions` decides what happens to a field whose name is taken by a
+    /// field of an earlier document: it is either renamed to `doc{n}_{name}`, where `n`
+    /// counts the documents from 1, or merged with the earlier field into one field
+    /// with a shared value.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use acroform::{AcroFormDocument, FieldCollision};
+    ///
+    /// let a = AcroFormDocument::from_pdf("a.pdf").unwrap();
+    /// let b = AcroFormDocument::from_pdf("b.pdf").unwrap();
+    /// let merged = AcroFormDocument::merge([&a, &b], FieldCollision::Rename).unwrap();
+    /// std::fs::write("merged.pdf", merged).unwrap();
+    /// ```
+    pub fn merge<'a>(
+        documents: impl IntoIterator<Item = &'a AcroFormDocument<B>>,
+        collisions: FieldCollision,
+    ) -> Result<Vec<u8>, PdfError>
+    where
+        B: 'a,
+    {
+        let mut merger = FormMerger::new(FileOptions::uncached()).collisions(collisions);
+        for doc in documents {
+            merger.add(doc.file.resolver(), &doc.file.trailer.root)?;
+        }
+        merger.build()
+    }
+}
+
+/// An image XObject from the content of a JPEG or PNG file
+fn image_xobject(data: &[u8], update: &mut impl Updater) -> Result<ImageXObject, PdfError> {
+    if data.starts_with(b"\x89PNG") {
+        ImageXObject::from_png(data, update)
+    } else if data.starts_with(&[0xFF, 0xD8]) {
+        ImageXObject::from_jpeg(data)
+    } else {
+        Err(PdfError::Other { msg: "images have to be JPEG or PNG files".into() })
+    }
 }
 
 #[cfg(test)]
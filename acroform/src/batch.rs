@@ -0,0 +1,290 @@
+use datasize::DataSize;
+use pdf::backend::Backend;
+use pdf::build::{FieldCollision, FormMerger};
+use pdf::enc::{Decoders, StreamFilter};
+use pdf::error::PdfError;
+use pdf::file::{FileOptions, SaveOptions};
+use pdf::object::{FieldType, Object, ParseOptions, PlainRef, RcRef, Ref, Resolve, Shared};
+use pdf::parser::ParseFlags;
+use pdf::primitive::{Dictionary, Primitive};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::api::{FieldValue, FormField};
+use crate::template::{FillSession, FormTemplate};
+
+/// One row of a mail-merge: values by column name
+pub type Record = HashMap<String, String>;
+
+/// A record that could not be filled
+#[derive(Debug)]
+pub struct RecordError {
+    /// The position of the record, counting from 0
+    pub index: usize,
+    pub error: PdfError,
+}
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "record {}: {}", self.index, self.error)
+    }
+}
+impl std::error::Error for RecordError {}
+
+/// Fills a [`FormTemplate`] once for every record of a mail-merge
+///
+/// Columns are matched to fields of the same name, unless columns are mapped to fields
+/// explicitly with `column()`. Columns without a field are ignored. The text of a cell is
+/// converted according to the field type: check boxes accept `true`/`false`, `yes`/`no`,
+/// `on`/`off`, `1`/`0` and `x`, other values select the export value of radio buttons,
+/// check boxes and choice fields.
+///
+/// A record that can't be filled is reported as a [`RecordError`] and the batch goes on.
+///
+/// # Examples
+///
+/// ```no_run
+/// use acroform::{Batch, FormTemplate};
+///
+/// let template = FormTemplate::from_pdf("form.pdf").unwrap();
+/// let records = acroform::csv_records(std::fs::File::open("people.csv").unwrap()).unwrap();
+/// let batch = Batch::new(&template).column("first_name", "firstName");
+/// for (i, filled) in batch.fill_each(records).unwrap().enumerate() {
+///     match filled {
+///         Ok(data) => std::fs::write(format!("filled_{}.pdf", i), data).unwrap(),
+///         Err(e) => eprintln!("{}", e),
+///     }
+/// }
+/// ```
+pub struct Batch<'a, B = Vec<u8>> {
+    template: &'a FormTemplate<B>,
+    fields: HashMap<&'a str, &'a FormField>,
+    // column -> field name, empty to match columns by name
+    columns: HashMap<String, String>,
+    options: SaveOptions,
+}
+
+impl<'a, B: Backend> Batch<'a, B> {
+    pub fn new(template: &'a FormTemplate<B>) -> Self {
+        let mut fields = HashMap::new();
+        for field in template.fields() {
+            fields.entry(field.name.as_str()).or_insert(field);
+        }
+        Batch { template, fields, columns: HashMap::new(), options: SaveOptions::default() }
+    }
+
+    /// Fill `field` with the values of `column`
+    ///
+    /// Once a column is mapped, only mapped columns are used.
+    pub fn column(mut self, column: impl Into<String>, field: impl Into<String>) -> Self {
+        self.columns.insert(column.into(), field.into());
+        self
+    }
+
+    /// How the PDFs of `fill_each()` are written
+    pub fn save_options(mut self, options: SaveOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Fill the template for every record and return one PDF per record
+    ///
+    /// The records are filled lazily, as the returned iterator is advanced.
+    ///
+    /// # Errors
+    ///
+    /// Fails before any record is filled if a column is mapped to a field that doesn't exist.
+    pub fn fill_each<'b, I>(&'b self, records: I) -> Result<impl Iterator<Item = Result<Vec<u8>, RecordError>> + 'b, PdfError>
+    where
+        I: IntoIterator<Item = Result<Record, PdfError>>,
+        I::IntoIter: 'b,
+    {
+        self.check_columns()?;
+        Ok(records.into_iter().enumerate().map(move |(index, record)| {
+            self.session(record)
+                .and_then(|session| session.save_with(&self.options))
+                .map_err(|error| RecordError { index, error })
+        }))
+    }
+
+    /// Fill the template for every record and write all copies into one PDF
+    ///
+    /// The top-level fields of every copy but the first are renamed to `record{index}_{name}`,
+    /// so that the copies keep their own values. Resources like fonts and images are shared
+    /// between the copies. The merged document is built in memory before it is written to
+    /// `out` and is not encrypted.
+    ///
+    /// Returns the errors of the records that were left out.
+    pub fn fill_merged<I>(&self, records: I, mut out: impl Write) -> Result<Vec<RecordError>, PdfError>
+    where
+        I: IntoIterator<Item = Result<Record, PdfError>>,
+    {
+        self.check_columns()?;
+        let file = &self.template.doc.file;
+        let mut merger = FormMerger::new(FileOptions::uncached())
+            .collisions(FieldCollision::Rename)
+            .share_imports(true);
+        let mut errors = Vec::new();
+        for (index, record) in records.into_iter().enumerate() {
+            let added = self.session(record).and_then(|session| {
+                let resolver = SessionResolver { resolver: file.resolver(), changes: &session.changes };
+                merger.add_with_prefix(resolver, &file.trailer.root, &format!("record{}_", index))
+            });
+            if let Err(error) = added {
+                errors.push(RecordError { index, error });
+            }
+        }
+        out.write_all(&merger.build()?)?;
+        out.flush()?;
+        Ok(errors)
+    }
+
+    fn check_columns(&self) -> Result<(), PdfError> {
+        match self.columns.values().find(|field| !self.fields.contains_key(field.as_str())) {
+            Some(field) => Err(PdfError::Other { msg: format!("there is no field {:?} in the template", field) }),
+            None => Ok(()),
+        }
+    }
+
+    fn session(&self, record: Result<Record, PdfError>) -> Result<FillSession<'a, B>, PdfError> {
+        let mut values = HashMap::new();
+        for (column, text) in record? {
+            let name = match self.columns.is_empty() {
+                true => column,
+                false => match self.columns.get(&column) {
+                    Some(field) => field.clone(),
+                    None => continue,
+                },
+            };
+            if let Some(field) = self.fields.get(name.as_str()) {
+                values.insert(name, field_value(field, text)?);
+            }
+        }
+        let mut session = self.template.session();
+        session.set_values(values);
+        Ok(session)
+    }
+}
+
+fn field_value(field: &FormField, text: String) -> Result<FieldValue, PdfError> {
+    const RADIO: u32 = 1 << 15;
+    const PUSH_BUTTON: u32 = 1 << 16;
+    match field.field_type {
+        FieldType::Text => Ok(FieldValue::Text(text)),
+        FieldType::Choice => Ok(FieldValue::Choice(text)),
+        FieldType::Button if field.flags & PUSH_BUTTON != 0 => {
+            Err(PdfError::Other { msg: format!("push button {} has no value", field.name) })
+        }
+        FieldType::Button if field.flags & RADIO != 0 => Ok(FieldValue::Choice(text)),
+        FieldType::Button => match text.trim().to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" | "x" => Ok(FieldValue::Boolean(true)),
+            "false" | "no" | "off" | "0" | "" => Ok(FieldValue::Boolean(false)),
+            _ => Ok(FieldValue::Choice(text)),
+        },
+        _ => Err(PdfError::Other { msg: format!("signature field {} can't be filled", field.name) }),
+    }
+}
+
+/// The objects of the template with the changes of a session
+struct SessionResolver<'s, R> {
+    resolver: R,
+    changes: &'s HashMap<PlainRef, Dictionary>,
+}
+
+impl<R: Resolve> Resolve for SessionResolver<'_, R> {
+    fn resolve_flags(&self, r: PlainRef, flags: ParseFlags, depth: usize) -> Result<Primitive, PdfError> {
+        match self.changes.get(&r) {
+            Some(dict) => Ok(Primitive::Dictionary(dict.clone())),
+            None => self.resolver.resolve_flags(r, flags, depth),
+        }
+    }
+    fn get<T: Object + DataSize>(&self, r: Ref<T>) -> Result<RcRef<T>, PdfError> {
+        match self.changes.get(&r.get_inner()) {
+            Some(dict) => Ok(RcRef::new(r.get_inner(), Shared::new(T::from_primitive(Primitive::Dictionary(dict.clone()), self)?))),
+            None => self.resolver.get(r),
+        }
+    }
+    fn options(&self) -> &ParseOptions {
+        self.resolver.options()
+    }
+    fn stream_data(&self, id: PlainRef, range: Range<usize>) -> Result<Arc<[u8]>, PdfError> {
+        self.resolver.stream_data(id, range)
+    }
+    fn get_data_or_decode(&self, id: PlainRef, range: Range<usize>, filters: &[StreamFilter]) -> Result<Arc<[u8]>, PdfError> {
+        self.resolver.get_data_or_decode(id, range, filters)
+    }
+    fn decoders(&self) -> &Decoders {
+        self.resolver.decoders()
+    }
+    fn count_decoded(&self, len: usize) -> Result<(), PdfError> {
+        self.resolver.count_decoded(len)
+    }
+}
+
+/// Read records from CSV data with a header row
+///
+/// A row that can't be read is returned as an error and the following rows are still read.
+///
+/// # Errors
+///
+/// Fails if the header row can't be read.
+#[cfg(feature = "csv")]
+pub fn csv_records(reader: impl std::io::Read) -> Result<impl Iterator<Item = Result<Record, PdfError>>, PdfError> {
+    let csv_error = |e: csv::Error| PdfError::Other { msg: format!("invalid CSV: {}", e) };
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers().map_err(csv_error)?.clone();
+    Ok(reader.into_records().map(move |row| {
+        let row = row.map_err(csv_error)?;
+        Ok(headers.iter().zip(row.iter()).map(|(column, value)| (column.to_string(), value.to_string())).collect())
+    }))
+}
+
+/// Read records from JSON objects, either in an array or one after another (JSON Lines)
+///
+/// Strings, numbers and booleans become the text of a column, `null` leaves it out.
+/// Reading stops at the first syntax error, which is returned as the last record.
+#[cfg(feature = "json")]
+pub fn json_records(reader: impl std::io::Read) -> impl Iterator<Item = Result<Record, PdfError>> {
+    use serde_json::Value;
+
+    fn record(value: Value) -> Result<Record, PdfError> {
+        let object = match value {
+            Value::Object(object) => object,
+            v => return Err(PdfError::Other { msg: format!("expected a JSON object as record, found {}", v) }),
+        };
+        let mut record = Record::new();
+        for (column, value) in object {
+            let text = match value {
+                Value::Null => continue,
+                Value::String(s) => s,
+                Value::Bool(b) => b.to_string(),
+                Value::Number(n) => n.to_string(),
+                v => return Err(PdfError::Other { msg: format!("column {} has the nested value {}", column, v) }),
+            };
+            record.insert(column, text);
+        }
+        Ok(record)
+    }
+
+    let mut values = serde_json::Deserializer::from_reader(reader).into_iter::<Value>();
+    let mut items = Vec::new().into_iter();
+    let mut failed = false;
+    std::iter::from_fn(move || loop {
+        if let Some(item) = items.next() {
+            return Some(record(item));
+        }
+        if failed {
+            return None;
+        }
+        match values.next()? {
+            Ok(Value::Array(array)) => items = array.into_iter(),
+            Ok(value) => return Some(record(value)),
+            Err(e) => {
+                failed = true;
+                return Some(Err(PdfError::Other { msg: format!("invalid JSON: {}", e) }));
+            }
+        }
+    })
+}
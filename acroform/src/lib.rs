@@ -47,6 +47,76 @@ let filled_pdf_bytes = doc.fill(values).unwrap();
 // Now you can send filled_pdf_bytes over HTTP, store in a database, etc.
 ```
 
+### Large Files
+
+`from_reader` works on any `Read + Seek` source and keeps only a bounded block cache
+in memory. `fill_to_writer` and `fill_and_save` copy the unchanged bytes from the source
+straight into the output:
+
+```rust,no_run
+use acroform::{AcroFormDocument, FieldValue};
+use std::collections::HashMap;
+
+let file = std::fs::File::open("scanned_form.pdf").unwrap();
+let mut doc = AcroFormDocument::from_reader(file).unwrap();
+
+let mut values = HashMap::new();
+values.insert("firstName".to_string(), FieldValue::Text("John".to_string()));
+doc.fill_and_save(values, "filled_form.pdf").unwrap();
+```
+
+### Filling One Form Many Times
+
+A `FormTemplate` is parsed once and can be shared between threads. Every fill happens
+in its own cheap session that only keeps its own changes:
+
+```rust,no_run
+use acroform::{FormTemplate, FieldValue};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+let template = Arc::new(FormTemplate::from_pdf("form.pdf").unwrap());
+let mut session = template.session();
+let mut values = HashMap::new();
+values.insert("firstName".to_string(), FieldValue::Text("John".to_string()));
+session.set_values(values);
+let filled_pdf_bytes = session.save().unwrap();
+```
+
+### Mail Merge
+
+`Batch` fills a template once for every record, e.g. every row of a CSV file, either
+into one PDF per record or into a single PDF with a copy of the form per record:
+
+```rust,no_run
+use acroform::{Batch, FormTemplate};
+
+let template = FormTemplate::from_pdf("form.pdf").unwrap();
+let records = acroform::csv_records(std::fs::File::open("people.csv").unwrap()).unwrap();
+let out = std::fs::File::create("all_forms.pdf").unwrap();
+for error in Batch::new(&template).fill_merged(records, out).unwrap() {
+    eprintln!("{}", error);
+}
+```
+
+### Encrypted Documents
+
+Password-protected forms are opened with the user or owner password.
+A missing password is reported as `PdfError::PasswordRequired`, a wrong one as
+`PdfError::InvalidPassword`:
+
+```rust,no_run
+use acroform::{AcroFormDocument, PdfError};
+
+match AcroFormDocument::from_pdf("form.pdf") {
+    Err(PdfError::PasswordRequired) => {
+        let doc = AcroFormDocument::from_pdf_with_password("form.pdf", "secret").unwrap();
+        println!("may fill: {}", doc.permissions().can_fill_forms());
+    }
+    other => { other.unwrap(); }
+}
+```
+
 ## Working with Form Fields
 
 This library supports the following field types:
@@ -54,6 +124,7 @@ This library supports the following field types:
 - **Checkboxes** - Use `FieldValue::Boolean(bool)`
 - **Radio buttons and dropdowns** - Use `FieldValue::Choice(String)`
 - **Number fields** - Use `FieldValue::Integer(i32)`
+- **Image buttons** - Use `FieldValue::Image(Vec<u8>)` with the bytes of a JPEG or PNG file
 
 Field names are fully qualified (e.g., `"parent.child.field"`) and automatically
 resolved for you, even in forms with nested field hierarchies.
@@ -61,10 +132,22 @@ resolved for you, even in forms with nested field hierarchies.
 
 mod field;
 mod api;
+mod template;
+mod batch;
 
 pub use api::{AcroFormDocument, FormField, FieldValue};
+pub use template::{FormTemplate, FillSession};
+pub use batch::{Batch, Record, RecordError};
+#[cfg(feature = "csv")]
+pub use batch::csv_records;
+#[cfg(feature = "json")]
+pub use batch::json_records;
 pub use field::{FieldDictionaryExt, InteractiveFormDictionaryExt};
 
 // Re-export commonly used types from pdf crate
 pub use pdf::error::PdfError;
 pub use pdf::object::FieldType;
+pub use pdf::crypt::Permissions;
+pub use pdf::file::SaveOptions;
+pub use pdf::backend::ReaderBackend;
+pub use pdf::build::FieldCollision;
@@ -0,0 +1,173 @@
+use pdf::backend::{Backend, ReaderBackend};
+use pdf::error::PdfError;
+use pdf::file::SaveOptions;
+use pdf::object::{PlainRef, Resolve};
+use pdf::primitive::{Dictionary, Primitive};
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+use crate::api::{AcroFormDocument, FieldValue, FormField};
+use crate::field::{FieldDictionaryExt, InteractiveFormDictionaryExt};
+
+/// A form that is parsed once and filled many times
+///
+/// The template is immutable and `Send + Sync`, so it can be shared between threads,
+/// e.g. in an `Arc`. It holds the parsed document with its object cache and an index
+/// from field names to the objects that receive the values. Each fill happens in a
+/// [`FillSession`], which only keeps its own changes.
+///
+/// # Examples
+///
+/// ```no_run
+/// use acroform::{FormTemplate, FieldValue};
+/// use std::collections::HashMap;
+///
+/// let template = FormTemplate::from_pdf("form.pdf").unwrap();
+/// std::thread::scope(|s| {
+///     for name in ["John", "Jane"] {
+///         let template = &template;
+///         s.spawn(move || {
+///             let mut values = HashMap::new();
+///             values.insert("firstName".to_string(), FieldValue::Text(name.to_string()));
+///             template.fill(values).unwrap()
+///         });
+///     }
+/// });
+/// ```
+pub struct FormTemplate<B = Vec<u8>> {
+    pub(crate) doc: AcroFormDocument<B>,
+    fields: Vec<FormField>,
+    // field name -> fields and widget annotations that get the value
+    targets: HashMap<String, Vec<(PlainRef, Dictionary)>>,
+}
+
+impl FormTemplate {
+    /// Load a form template from the given path
+    pub fn from_pdf(path: impl AsRef<Path>) -> Result<Self, PdfError> {
+        Self::from_document(AcroFormDocument::from_pdf(path)?)
+    }
+
+    /// Load a form template from a byte vector
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, PdfError> {
+        Self::from_document(AcroFormDocument::from_bytes(data)?)
+    }
+}
+
+impl<R: Read + Seek> FormTemplate<ReaderBackend<R>> {
+    /// Load a form template from any `Read + Seek` source, see `AcroFormDocument::from_reader()`
+    pub fn from_reader(reader: R) -> Result<Self, PdfError> {
+        Self::from_document(AcroFormDocument::from_reader(reader)?)
+    }
+}
+
+impl<B: Backend> FormTemplate<B> {
+    /// Build a template from a loaded document, e.g. one that was opened with a password
+    ///
+    /// Changes that were already made to the document are part of every filled form.
+    pub fn from_document(doc: AcroFormDocument<B>) -> Result<Self, PdfError> {
+        let fields = doc.fields()?;
+        let mut targets: HashMap<String, Vec<(PlainRef, Dictionary)>> = HashMap::new();
+        {
+            let file = &doc.file;
+            let resolver = file.resolver();
+            let mut add = |name: String, r: PlainRef| -> Result<(), PdfError> {
+                let objects = targets.entry(name).or_default();
+                if !objects.iter().any(|&(old, _)| old == r) {
+                    objects.push((r, resolver.resolve(r)?.into_dictionary()?));
+                }
+                Ok(())
+            };
+
+            if let Some(ref forms) = file.get_root().forms {
+                for field in forms.all_fields(&resolver)? {
+                    if field.typ.is_some() {
+                        let name = field.get_full_name(&resolver)?;
+                        add(name, field.get_ref().get_inner())?;
+                    }
+                }
+            }
+
+            // widget annotations that carry the field name themselves
+            for page in file.pages() {
+                let page = page?;
+                for annot in page.annotations.load(&resolver)?.data().iter() {
+                    if let (Some(Primitive::String(name)), Some(r)) = (annot.data().other.get("T"), annot.as_ref()) {
+                        add(name.to_string_lossy().to_string(), r.get_inner())?;
+                    }
+                }
+            }
+        }
+        Ok(FormTemplate { doc, fields, targets })
+    }
+
+    /// The form fields of the template, with their values in the template
+    pub fn fields(&self) -> &[FormField] {
+        &self.fields
+    }
+
+    /// Start filling the template
+    ///
+    /// Sessions are cheap to create and don't change the template.
+    pub fn session(&self) -> FillSession<'_, B> {
+        FillSession { template: self, changes: HashMap::new() }
+    }
+
+    /// Fill the template in a new session and return the PDF as a byte vector
+    pub fn fill(&self, values: HashMap<String, FieldValue>) -> Result<Vec<u8>, PdfError> {
+        let mut session = self.session();
+        session.set_values(values);
+        session.save()
+    }
+
+    /// Fill the template in a new session and write the PDF to `out`
+    pub fn fill_to_writer(&self, values: HashMap<String, FieldValue>, out: impl Write) -> Result<(), PdfError> {
+        let mut session = self.session();
+        session.set_values(values);
+        session.save_to_writer(&SaveOptions::default(), out)
+    }
+}
+
+/// The changes of one fill of a [`FormTemplate`]
+pub struct FillSession<'a, B = Vec<u8>> {
+    template: &'a FormTemplate<B>,
+    pub(crate) changes: HashMap<PlainRef, Dictionary>,
+}
+
+impl<B: Backend> FillSession<'_, B> {
+    /// Set the values of fields
+    ///
+    /// Like with `AcroFormDocument::fill()`, names that are not in the form are ignored,
+    /// and later values replace earlier ones. `FieldValue::Image` values are ignored as
+    /// well, as they need new objects; fill image buttons with `AcroFormDocument`.
+    pub fn set_values(&mut self, values: HashMap<String, FieldValue>) {
+        for (name, value) in values {
+            if let FieldValue::Image(_) = value {
+                continue;
+            }
+            for (r, dict) in self.template.targets.get(&name).into_iter().flatten() {
+                self.changes.entry(*r).or_insert_with(|| dict.clone()).insert("V", value.to_primitive());
+            }
+        }
+    }
+
+    /// The template with the values of this session as a byte vector
+    pub fn save(&self) -> Result<Vec<u8>, PdfError> {
+        self.save_with(&SaveOptions::default())
+    }
+
+    /// Like `save()`, written according to `options`
+    pub fn save_with(&self, options: &SaveOptions) -> Result<Vec<u8>, PdfError> {
+        let mut out = Vec::new();
+        self.save_to_writer(options, &mut out)?;
+        Ok(out)
+    }
+
+    /// Write the template with the values of this session to `out`
+    pub fn save_to_writer(&self, options: &SaveOptions, mut out: impl Write) -> Result<(), PdfError> {
+        let changes = self.changes.iter().map(|(&r, dict)| (r, Primitive::Dictionary(dict.clone())));
+        self.template.doc.file.save_changes_to_writer(changes, options, &mut out)?;
+        out.flush()?;
+        Ok(())
+    }
+}
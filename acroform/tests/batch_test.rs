@@ -0,0 +1,122 @@
+use acroform::{AcroFormDocument, Batch, FieldValue, FormTemplate, Record};
+
+const FIELD: &str = "topmostSubform[0].Page1[0].P[0].MbrName[1]";
+
+fn value_of(data: &[u8], name: &str) -> Option<FieldValue> {
+    let doc = AcroFormDocument::from_bytes(data.to_vec()).expect("Failed to load filled PDF");
+    let fields = doc.fields().expect("Failed to get fields");
+    fields.into_iter().find(|f| f.name == name).expect("Field not found").current_value
+}
+
+fn record(column: &str, value: &str) -> Result<Record, acroform::PdfError> {
+    Ok([(column.to_string(), value.to_string())].into_iter().collect())
+}
+
+#[test]
+fn test_fill_each_from_csv() {
+    let template = FormTemplate::from_pdf("../acroform_files/af8.pdf").expect("Failed to load template");
+    let csv = "name,other\nALICE,x\n\"BOB\nunterminated\nCAROL,y\n";
+    let records = acroform::csv_records(csv.as_bytes()).expect("Failed to read CSV header");
+    let batch = Batch::new(&template).column("name", FIELD);
+    let filled: Vec<_> = batch.fill_each(records).expect("Failed to start batch").collect();
+
+    assert_eq!(filled.len(), 2);
+    let data = filled[0].as_ref().expect("first record failed");
+    assert_eq!(value_of(data, FIELD), Some(FieldValue::Text("ALICE".to_string())));
+    // the quoted field swallows the rest of the data, which leaves a row of the wrong length
+    let error = filled[1].as_ref().unwrap_err();
+    assert_eq!(error.index, 1);
+}
+
+#[test]
+fn test_fill_each_from_json() {
+    let template = FormTemplate::from_pdf("../acroform_files/af8.pdf").expect("Failed to load template");
+    let json = format!(r#"[{{"{0}": "ALICE"}}, {{"{0}": 42}}] {{"{0}": ["nested"]}}"#, FIELD);
+    let records = acroform::json_records(json.as_bytes());
+    let batch = Batch::new(&template);
+    let filled: Vec<_> = batch.fill_each(records).expect("Failed to start batch").collect();
+
+    assert_eq!(filled.len(), 3);
+    assert_eq!(value_of(filled[0].as_ref().unwrap(), FIELD), Some(FieldValue::Text("ALICE".to_string())));
+    assert_eq!(value_of(filled[1].as_ref().unwrap(), FIELD), Some(FieldValue::Text("42".to_string())));
+    assert_eq!(filled[2].as_ref().unwrap_err().index, 2);
+}
+
+#[test]
+fn test_unknown_mapped_field() {
+    let template = FormTemplate::from_pdf("../acroform_files/af8.pdf").expect("Failed to load template");
+    let batch = Batch::new(&template).column("name", "no such field");
+    assert!(batch.fill_each(vec![record("name", "ALICE")]).is_err());
+}
+
+#[test]
+fn test_fill_merged() {
+    let template = FormTemplate::from_pdf("../acroform_files/af8.pdf").expect("Failed to load template");
+    let records = vec![
+        record(FIELD, "ALICE"),
+        Err(acroform::PdfError::Other { msg: "broken record".into() }),
+        record(FIELD, "CAROL"),
+    ];
+    let mut out = Vec::new();
+    let errors = Batch::new(&template).fill_merged(records, &mut out).expect("Failed to merge");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].index, 1);
+
+    let merged = AcroFormDocument::from_bytes(out.clone()).expect("Failed to load merged PDF");
+    let fields = merged.fields().expect("Failed to get fields");
+    assert_eq!(fields.len(), 2 * template.fields().len());
+
+    // af8.pdf has a single top-level field, which is renamed in all copies but the first
+    assert_eq!(value_of(&out, FIELD), Some(FieldValue::Text("ALICE".to_string())));
+    assert_eq!(value_of(&out, &format!("record2_{}", FIELD)), Some(FieldValue::Text("CAROL".to_string())));
+
+    // fonts and appearance streams are shared by the copies
+    let mut single = Vec::new();
+    Batch::new(&template).fill_merged(vec![record(FIELD, "ALICE")], &mut single).expect("Failed to merge");
+    assert!(out.len() < single.len() * 3 / 2, "{} vs. {}", out.len(), single.len());
+
+    let original = pdf::file::FileOptions::cached().load(std::fs::read("../acroform_files/af8.pdf").unwrap()).unwrap();
+    let copy = pdf::file::FileOptions::cached().load(out).unwrap();
+    assert_eq!(copy.num_pages(), 2 * original.num_pages());
+    for page in copy.pages() {
+        page.expect("Failed to load page");
+    }
+}
+
+
+#[test]
+fn test_fill_merged_drops_failed_copies() {
+    use pdf::object::{Resolve, Updater};
+    use pdf::primitive::{Dictionary, PdfString, Primitive};
+
+    // a second top-level field takes the name that the first one gets in the copy of record 1,
+    // so that copy fails after its pages have been merged
+    let mut file = pdf::file::FileOptions::cached().open("../acroform_files/af8.pdf").unwrap();
+    let catalog = file.resolver().resolve(file.trailer.root.get_ref().get_inner()).unwrap().into_dictionary().unwrap();
+    let form_ref = catalog.get("AcroForm").unwrap().clone().into_reference().unwrap();
+    let mut form = file.resolver().resolve(form_ref).unwrap().into_dictionary().unwrap();
+    let mut fields = form.get("Fields").unwrap().clone().resolve(&file.resolver()).unwrap().into_array().unwrap();
+    let first = file.resolver().resolve(fields[0].clone().into_reference().unwrap()).unwrap().into_dictionary().unwrap();
+    let name = first.get("T").unwrap().as_string().unwrap().to_string_lossy();
+    let mut field = Dictionary::new();
+    field.insert("FT", Primitive::name("Tx"));
+    field.insert("T", PdfString::from(format!("record1_{}", name).as_str()));
+    fields.push(file.create(Primitive::Dictionary(field)).unwrap().get_ref().get_inner().into());
+    form.insert("Fields", fields);
+    file.update(form_ref, Primitive::Dictionary(form)).unwrap();
+    let template = FormTemplate::from_bytes(file.save().unwrap()).expect("Failed to load template");
+
+    let mut out = Vec::new();
+    let records = vec![record(FIELD, "ALICE"), record(FIELD, "BOB"), record(FIELD, "CAROL")];
+    let errors = Batch::new(&template).fill_merged(records, &mut out).expect("Failed to merge");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].index, 1);
+    assert_eq!(value_of(&out, FIELD), Some(FieldValue::Text("ALICE".to_string())));
+    assert_eq!(value_of(&out, &format!("record2_{}", FIELD)), Some(FieldValue::Text("CAROL".to_string())));
+
+    // nothing of the failed copy is left
+    let mut expected = Vec::new();
+    let records = vec![record(FIELD, "ALICE"), Err(acroform::PdfError::Other { msg: "skipped".into() }), record(FIELD, "CAROL")];
+    Batch::new(&template).fill_merged(records, &mut expected).expect("Failed to merge");
+    assert!(out == expected);
+}
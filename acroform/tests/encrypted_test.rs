@@ -0,0 +1,47 @@
+use acroform::{AcroFormDocument, PdfError};
+
+const PDF: &str = "../files/password_protected/passwords_aes_128.pdf";
+
+#[test]
+fn test_missing_password() {
+    match AcroFormDocument::from_pdf(PDF) {
+        Err(PdfError::PasswordRequired) => {}
+        Err(e) => panic!("expected PasswordRequired, got {}", e),
+        Ok(_) => panic!("encrypted PDF opened without a password"),
+    }
+}
+
+#[test]
+fn test_wrong_password() {
+    match AcroFormDocument::from_pdf_with_password(PDF, "wrong") {
+        Err(PdfError::InvalidPassword) => {}
+        Err(e) => panic!("expected InvalidPassword, got {}", e),
+        Ok(_) => panic!("encrypted PDF opened with a wrong password"),
+    }
+}
+
+#[test]
+fn test_user_and_owner_password() {
+    for password in ["userpassword", "ownerpassword"] {
+        let doc = AcroFormDocument::from_pdf_with_password(PDF, password)
+            .expect("Failed to open encrypted PDF");
+        doc.fields().expect("Failed to get fields");
+    }
+
+    let data = std::fs::read(PDF).expect("Failed to read PDF");
+    AcroFormDocument::from_bytes_with_password(data, "userpassword")
+        .expect("Failed to load encrypted PDF from bytes");
+}
+
+#[test]
+fn test_permissions() {
+    let doc = AcroFormDocument::from_pdf("../acroform_files/af8.pdf")
+        .expect("Failed to load PDF");
+    assert!(doc.permissions().can_fill_forms(), "Unencrypted PDFs permit everything");
+
+    let doc = AcroFormDocument::from_pdf_with_password(PDF, "userpassword")
+        .expect("Failed to open encrypted PDF");
+    // the permission bits round-trip through /P
+    let p = doc.permissions();
+    assert_eq!(acroform::Permissions::from_p(p.to_p()), p);
+}
@@ -0,0 +1,118 @@
+use acroform::{AcroFormDocument, FieldValue};
+use pdf::enc::{encode, LZWFlateParams, StreamFilter};
+use pdf::file::FileOptions;
+use pdf::object::{PlainRef, Resolve};
+use pdf::primitive::{Dictionary, Primitive};
+use std::collections::HashMap;
+
+/// A page with the push button "photo" and the checkbox "check"
+fn form() -> Vec<u8> {
+    build(&[
+        "<< /Type /Catalog /Pages 2 0 R /AcroForm << /Fields [4 0 R 5 0 R] >> >>",
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>",
+        "<< /Type /Page /Parent 2 0 R /Annots [4 0 R 5 0 R] >>",
+        "<< /Type /Annot /Subtype /Widget /FT /Btn /Ff 65536 /T (photo) /Rect [10 10 110 60] /P 3 0 R \
+            /BS << /W 2 >> /MK << /IF << /SW /B /A [0 0] >> >> >>",
+        "<< /Type /Annot /Subtype /Widget /FT /Btn /T (check) /Rect [10 100 20 110] /P 3 0 R >>",
+    ])
+}
+
+/// A file with the given objects, numbered from 1, and object 1 as the catalog
+fn build(objects: &[&str]) -> Vec<u8> {
+    let mut out = b"%PDF-1.7\n".to_vec();
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+    }
+    let xref = out.len();
+    out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    out.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes());
+    out
+}
+
+/// A PNG file of 4x2 pixels with an alpha channel, with the CRCs left at 0
+fn png() -> Vec<u8> {
+    fn chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        out.extend_from_slice(&[0; 4]);
+    }
+    let rows: Vec<u8> = (0 .. 2).flat_map(|_| [&[0][..], &[200; 16][..]].concat()).collect();
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &[0, 0, 0, 4, 0, 0, 0, 2, 8, 6, 0, 0, 0]);
+    chunk(&mut out, b"IDAT", &encode(&rows, &StreamFilter::FlateDecode(LZWFlateParams::default())).unwrap());
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[test]
+fn test_fill_image_button() {
+    let mut doc = AcroFormDocument::from_bytes(form()).expect("Failed to load PDF");
+    let mut values = HashMap::new();
+    values.insert("photo".to_string(), FieldValue::Image(png()));
+    let data = doc.fill(values).expect("Failed to fill image");
+
+    let file = FileOptions::cached().load(data).unwrap();
+    let resolver = file.resolver();
+    let dict = |p: &Primitive| -> Dictionary { p.clone().resolve(&resolver).unwrap().into_dictionary().unwrap() };
+    let page = file.get_page(0).unwrap();
+    let widget = page.annotations.load(&resolver).unwrap().data()[0].as_ref().unwrap().get_inner();
+    let widget = resolver.resolve(widget).unwrap().into_dictionary().unwrap();
+    assert!(widget.get("V").is_none());
+
+    let mk = dict(widget.get("MK").unwrap());
+    assert_eq!(mk.get("TP"), Some(&Primitive::Integer(1)));
+    let icon = resolver.resolve(mk.get("I").unwrap().clone().into_reference().unwrap()).unwrap().into_stream(&resolver).unwrap();
+    assert_eq!(icon.info.get("BBox"), Some(&Primitive::Array(vec![0.into(), 0.into(), 4.into(), 2.into()])));
+
+    // the icon is smaller than the widget, so it isn't scaled, and sits in the lower left
+    // corner inside the border
+    let ap = dict(widget.get("AP").unwrap());
+    let appearance = resolver.resolve(ap.get("N").unwrap().clone().into_reference().unwrap()).unwrap().into_stream(&resolver).unwrap();
+    let content = String::from_utf8(appearance.raw_data(&resolver).unwrap().to_vec()).unwrap();
+    assert!(content.contains("1 0 0 1 2 2 cm\n/Icon Do"), "{}", content);
+    assert_eq!(appearance.info.get("BBox"), Some(&Primitive::Array(vec![0.into(), 0.into(), 100.into(), 50.into()])));
+}
+
+#[test]
+fn test_widgets_share_image() {
+    // the push button "photo" with two widgets
+    let mut doc = AcroFormDocument::from_bytes(build(&[
+        "<< /Type /Catalog /Pages 2 0 R /AcroForm << /Fields [4 0 R] >> >>",
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>",
+        "<< /Type /Page /Parent 2 0 R /Annots [5 0 R 6 0 R] >>",
+        "<< /FT /Btn /Ff 65536 /T (photo) /Kids [5 0 R 6 0 R] >>",
+        "<< /Type /Annot /Subtype /Widget /Parent 4 0 R /Rect [10 10 110 60] /P 3 0 R >>",
+        "<< /Type /Annot /Subtype /Widget /Parent 4 0 R /Rect [10 100 60 150] /P 3 0 R >>",
+    ])).expect("Failed to load PDF");
+    let mut values = HashMap::new();
+    values.insert("photo".to_string(), FieldValue::Image(png()));
+    let data = doc.fill(values).expect("Failed to fill image");
+
+    let file = FileOptions::cached().load(data).unwrap();
+    let resolver = file.resolver();
+    let dict = |p: &Primitive| -> Dictionary { p.clone().resolve(&resolver).unwrap().into_dictionary().unwrap() };
+    // the icons of both widgets draw the same image
+    let images: Vec<_> = [5, 6].into_iter().map(|id| {
+        let widget = dict(&Primitive::Reference(PlainRef { id, gen: 0 }));
+        let icon = dict(widget.get("MK").unwrap()).get("I").unwrap().clone().into_reference().unwrap();
+        let icon = resolver.resolve(icon).unwrap().into_stream(&resolver).unwrap();
+        let xobjects = dict(dict(icon.info.get("Resources").unwrap()).get("XObject").unwrap());
+        xobjects.get("Im0").unwrap().clone().into_reference().unwrap()
+    }).collect();
+    assert_eq!(images[0], images[1]);
+}
+
+#[test]
+fn test_set_button_image_errors() {
+    let mut doc = AcroFormDocument::from_bytes(form()).expect("Failed to load PDF");
+    assert!(doc.set_button_image("check", &png()).is_err());
+    assert!(doc.set_button_image("missing", &png()).is_err());
+    assert!(doc.set_button_image("photo", b"GIF89a").is_err());
+    doc.set_button_image("photo", &png()).expect("Failed to set image");
+}
@@ -171,6 +171,68 @@ fn test_round_trip_in_memory() {
     );
 }
 
+#[test]
+fn test_fill_to_writer() {
+    let mut values = HashMap::new();
+    values.insert(
+        "topmostSubform[0].Page1[0].P[0].MbrName[1]".to_string(),
+        FieldValue::Text("WRITER_VALUE".to_string()),
+    );
+
+    let mut doc = AcroFormDocument::from_pdf("../acroform_files/af8.pdf")
+        .expect("Failed to load PDF");
+    let mut written = Vec::new();
+    doc.fill_to_writer(values.clone(), &mut written)
+        .expect("Failed to fill PDF");
+
+    let mut doc = AcroFormDocument::from_pdf("../acroform_files/af8.pdf")
+        .expect("Failed to load PDF");
+    assert_eq!(written, doc.fill(values).expect("Failed to fill PDF"));
+
+    let final_doc = AcroFormDocument::from_bytes(written)
+        .expect("Failed to load filled PDF from bytes");
+    let fields = final_doc.fields().expect("Failed to get fields");
+    let updated_field = fields.iter()
+        .find(|f| f.name == "topmostSubform[0].Page1[0].P[0].MbrName[1]")
+        .expect("Field not found");
+    assert_eq!(
+        updated_field.current_value,
+        Some(FieldValue::Text("WRITER_VALUE".to_string()))
+    );
+}
+
+#[test]
+fn test_fill_from_reader() {
+    let file = std::fs::File::open("../acroform_files/af8.pdf")
+        .expect("Failed to open PDF file");
+    let mut doc = AcroFormDocument::from_reader(file)
+        .expect("Failed to load PDF from reader");
+
+    let mut values = HashMap::new();
+    values.insert(
+        "topmostSubform[0].Page1[0].P[0].MbrName[1]".to_string(),
+        FieldValue::Text("STREAMED_VALUE".to_string()),
+    );
+    let filled_bytes = doc.fill(values.clone())
+        .expect("Failed to fill PDF");
+
+    // the same bytes as filling the document in memory
+    let mut in_memory = AcroFormDocument::from_pdf("../acroform_files/af8.pdf")
+        .expect("Failed to load PDF");
+    assert_eq!(filled_bytes, in_memory.fill(values).expect("Failed to fill PDF"));
+
+    let final_doc = AcroFormDocument::from_bytes(filled_bytes)
+        .expect("Failed to load filled PDF from bytes");
+    let fields = final_doc.fields().expect("Failed to get fields");
+    let updated_field = fields.iter()
+        .find(|f| f.name == "topmostSubform[0].Page1[0].P[0].MbrName[1]")
+        .expect("Field not found");
+    assert_eq!(
+        updated_field.current_value,
+        Some(FieldValue::Text("STREAMED_VALUE".to_string()))
+    );
+}
+
 #[test]
 fn test_field_tooltip_extraction() {
     let doc = AcroFormDocument::from_pdf("../acroform_files/af8.pdf")
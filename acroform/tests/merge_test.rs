@@ -0,0 +1,58 @@
+use acroform::{AcroFormDocument, FieldCollision, FieldValue};
+use pdf::object::Resolve;
+use std::collections::HashMap;
+
+const FIELD: &str = "topmostSubform[0].Page1[0].P[0].MbrName[1]";
+
+fn filled(value: &str) -> AcroFormDocument {
+    let mut doc = AcroFormDocument::from_pdf("../acroform_files/af8.pdf").expect("Failed to load PDF");
+    let mut values = HashMap::new();
+    values.insert(FIELD.to_string(), FieldValue::Text(value.to_string()));
+    AcroFormDocument::from_bytes(doc.fill(values).expect("Failed to fill")).expect("Failed to load filled PDF")
+}
+
+fn page_count(data: &[u8]) -> u32 {
+    pdf::file::FileOptions::cached().load(data.to_vec()).expect("Failed to load merged PDF").num_pages()
+}
+
+#[test]
+fn test_merge_renames_fields() {
+    let (a, b) = (filled("ALICE"), filled("BOB"));
+    let merged = AcroFormDocument::merge([&a, &b], FieldCollision::Rename).expect("Failed to merge");
+    assert_eq!(page_count(&merged), 2 * page_count(&std::fs::read("../acroform_files/af8.pdf").unwrap()));
+
+    let doc = AcroFormDocument::from_bytes(merged).expect("Failed to load merged PDF");
+    let fields: HashMap<_, _> = doc.fields().unwrap().into_iter().map(|f| (f.name, f.current_value)).collect();
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields[FIELD], Some(FieldValue::Text("ALICE".to_string())));
+    assert_eq!(fields[&format!("doc2_{}", FIELD)], Some(FieldValue::Text("BOB".to_string())));
+}
+
+#[test]
+fn test_merge_same_named_fields() {
+    let (a, b) = (filled("ALICE"), filled("BOB"));
+    let merged = AcroFormDocument::merge([&a, &b], FieldCollision::Merge).expect("Failed to merge");
+
+    let mut doc = AcroFormDocument::from_bytes(merged).expect("Failed to load merged PDF");
+    let fields = doc.fields().unwrap();
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields[0].name, FIELD);
+    // the value of the first document wins
+    assert_eq!(fields[0].current_value, Some(FieldValue::Text("ALICE".to_string())));
+
+    // both widgets now belong to the same field
+    let mut values = HashMap::new();
+    values.insert(FIELD.to_string(), FieldValue::Text("CAROL".to_string()));
+    let data = doc.fill(values).expect("Failed to fill merged PDF");
+    let file = pdf::file::FileOptions::cached().load(data).unwrap();
+    let resolver = file.resolver();
+    let form = file.get_root().forms.as_ref().expect("no form");
+    assert_eq!(form.fields.len(), 1);
+    let field = &form.fields[0];
+    assert_eq!(field.kids.len(), 2);
+    assert_eq!(field.value, FieldValue::Text("CAROL".to_string()).to_primitive());
+    for kid in field.kids.iter() {
+        let kid = resolver.get(*kid).unwrap();
+        assert!(kid.name.is_none());
+    }
+}
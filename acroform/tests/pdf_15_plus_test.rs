@@ -10,7 +10,7 @@
 /// create a new object with a different ID instead of properly updating the
 /// xref entry to point to the new uncompressed object.
 
-use acroform::{AcroFormDocument, FieldValue};
+use acroform::{AcroFormDocument, FieldValue, SaveOptions};
 use std::collections::HashMap;
 
 #[test]
@@ -121,3 +121,29 @@ fn test_pdf_13_backward_compatibility() {
         "Field value was not updated correctly in PDF 1.3"
     );
 }
+
+#[test]
+fn test_pdf_17_fill_with_object_streams() {
+    // Filling pulls the fields out of their object streams; pack them back in
+    let fill = |options: &SaveOptions| {
+        let mut doc = AcroFormDocument::from_pdf("../acroform_files/af8_clean.pdf")
+            .expect("Failed to load PDF 1.7 file");
+        let values: HashMap<_, _> = doc.fields().expect("Failed to get fields").iter()
+            .filter(|f| f.field_type == acroform::FieldType::Text)
+            .map(|f| (f.name.clone(), FieldValue::Text("PACKED".to_string())))
+            .collect();
+        assert!(!values.is_empty());
+        (doc.fill_with(values.clone(), options).expect("Failed to fill form"), values)
+    };
+    let (plain, _) = fill(&SaveOptions::new());
+    let (packed, values) = fill(&SaveOptions::new().object_streams(100));
+    assert!(packed.len() < plain.len(), "{} >= {}", packed.len(), plain.len());
+
+    let doc = AcroFormDocument::from_bytes(packed)
+        .expect("Failed to reopen filled PDF");
+    for field in doc.fields().expect("Failed to get fields from filled PDF") {
+        if values.contains_key(&field.name) {
+            assert_eq!(field.current_value, Some(FieldValue::Text("PACKED".to_string())), "{}", field.name);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use acroform::{AcroFormDocument, FieldValue, FormTemplate};
+use std::collections::HashMap;
+
+const FIELD: &str = "topmostSubform[0].Page1[0].P[0].MbrName[1]";
+
+fn value_of(data: Vec<u8>) -> Option<FieldValue> {
+    let doc = AcroFormDocument::from_bytes(data).expect("Failed to load filled PDF");
+    let fields = doc.fields().expect("Failed to get fields");
+    fields.into_iter().find(|f| f.name == FIELD).expect("Field not found").current_value
+}
+
+#[test]
+fn test_template_is_send_and_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<FormTemplate>();
+}
+
+#[test]
+fn test_fill_template_from_threads() {
+    let template = FormTemplate::from_pdf("../acroform_files/af8.pdf")
+        .expect("Failed to load template");
+    assert!(template.fields().iter().any(|f| f.name == FIELD));
+
+    let filled: Vec<_> = std::thread::scope(|s| {
+        let handles: Vec<_> = (0 .. 8).map(|i| {
+            let template = &template;
+            s.spawn(move || {
+                let mut values = HashMap::new();
+                values.insert(FIELD.to_string(), FieldValue::Text(format!("RECORD_{}", i)));
+                template.fill(values).expect("Failed to fill template")
+            })
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    for (i, data) in filled.into_iter().enumerate() {
+        assert_eq!(value_of(data), Some(FieldValue::Text(format!("RECORD_{}", i))));
+    }
+
+    // sessions don't change the template
+    assert_eq!(value_of(template.session().save().unwrap()), Some(FieldValue::Text("OLD_VALUE".to_string())));
+}
+
+#[test]
+fn test_fill_session() {
+    let template = FormTemplate::from_pdf("../acroform_files/af8.pdf")
+        .expect("Failed to load template");
+    let mut session = template.session();
+    let mut values = HashMap::new();
+    values.insert(FIELD.to_string(), FieldValue::Text("FIRST".to_string()));
+    session.set_values(values);
+    let mut values = HashMap::new();
+    values.insert(FIELD.to_string(), FieldValue::Text("SECOND".to_string()));
+    values.insert("no such field".to_string(), FieldValue::Text("IGNORED".to_string()));
+    session.set_values(values);
+
+    let mut out = Vec::new();
+    session.save_to_writer(&Default::default(), &mut out).expect("Failed to write session");
+    assert_eq!(value_of(out), Some(FieldValue::Text("SECOND".to_string())));
+}
+
+#[test]
+fn test_encrypted_template() {
+    let doc = AcroFormDocument::from_pdf_with_password("../files/password_protected/passwords_aes_128.pdf", "ownerpassword")
+        .expect("Failed to open encrypted PDF");
+    let template = FormTemplate::from_document(doc).expect("Failed to build template");
+    let data = template.fill(HashMap::new()).expect("Failed to fill template");
+    AcroFormDocument::from_bytes_with_password(data, "userpassword")
+        .expect("Failed to load filled encrypted PDF");
+}
@@ -3,12 +3,9 @@ use std::{path::PathBuf, error::Error};
 use pdf::{
     file::FileOptions,
     object::*,
-    primitive::Name, enc::{StreamFilter, DCTDecodeParams}, content::{Op, Matrix, Content},
 };
 
 use clap::Parser;
-use std::io::Cursor;
-use image::io::Reader as ImageReader;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -17,7 +14,7 @@ struct Args {
     #[arg(short, long)]
     input: PathBuf,
 
-    /// Input image file
+    /// Input image file, JPEG or PNG
     #[arg(long)]
     image: PathBuf,
 
@@ -43,32 +40,14 @@ struct Align {
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
     
+    let mut file = FileOptions::cached().open(&args.input)?;
+
     let img_data = std::fs::read(&args.image)?;
-    let img = ImageReader::with_format(Cursor::new(&img_data), image::ImageFormat::Jpeg).decode()?;
-    let image_dict = ImageDict {
-        width: img.width(),
-        height: img.height(),
-        color_space: Some(ColorSpace::DeviceRGB),
-        bits_per_component: Some(8),
-        .. Default::default()
+    let image = match args.image.extension().and_then(|e| e.to_str()) {
+        Some("png") => ImageXObject::from_png(&img_data, &mut file)?,
+        _ => ImageXObject::from_jpeg(&img_data)?,
     };
-    let image = Stream::new_with_filters(image_dict, img_data, vec![StreamFilter::DCTDecode(DCTDecodeParams { color_transform: None})]);
-
-    let mut file = FileOptions::cached().open(&args.input).unwrap();
-    let page = file.get_page(args.page).expect("no such page");
-
-    let resources = page.resources()?;
-    let mut resources2: Resources = (**resources).clone();
-
-    let image_obj = XObject::Image(ImageXObject { inner: image });
-    let image_ref = file.create(image_obj)?;
-
-    // assume that name did not exist
-    let image_name = Name::from("MyImage");
-    resources2.xobjects.insert(image_name.clone(), image_ref.get_ref());
-
-
-    let mut ops = page.contents.as_ref().unwrap().operations(&file.resolver())?;
+    let page = file.get_page(args.page)?;
 
     let mm = 72.0 / 25.4; // one millimeter
     // bottom right corner of the page, but 5mm margin
@@ -86,8 +65,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let px_scale = 72. / dpi;
     let media_box = page.media_box.unwrap();
-    let scale = Point { x: img.width() as f32 * px_scale , y: img.height() as f32 * px_scale };
-    let skew = Point { x: 0.0, y: 0.0 };
+    let scale = Point { x: image.width as f32 * px_scale , y: image.height as f32 * px_scale };
     let page_size = Point {
         x: media_box.right - media_box.left,
         y: media_box.top - media_box.bottom
@@ -102,22 +80,12 @@ fn main() -> Result<(), Box<dyn Error>> {
         y: page_origin.y + v_align.page_abs + v_align.img_rel * scale.y + v_align.page_rel * page_size.y
     };
 
-    ops.append(&mut vec![
-        Op::Save, // ADD IMAGE START
-        Op::Transform { matrix: Matrix{ // IMAGE MANIPULATION
-            a: scale.x, d: scale.y,
-            b: skew.x, c: skew.y,
-            e: position.x, f: position.y,
-        } },
-        Op::XObject {name: image_name}, // IMAGE
-        Op::Restore, // ADD IMAGE STOP
-    ]);
-
-    let mut page2: Page = (*page).clone();
-    page2.contents = Some(Content::from_ops(ops));
-    page2.resources = Some(file.create(resources2)?.into());
-
-    file.update(page.get_ref().get_inner(), page2)?;
+    file.add_image(args.page, image, Rectangle {
+        left: position.x,
+        bottom: position.y,
+        right: position.x + scale.x,
+        top: position.y + scale.y,
+    })?;
 
     file.save_to(&args.output)?;
 
@@ -0,0 +1,46 @@
+use std::env::args;
+
+use pdf::error::PdfError;
+use pdf::file::FileOptions;
+use pdf::json::JsonOptions;
+use pdf::object::PlainRef;
+
+/// print a PDF as JSON, like `qpdf --json`
+///
+/// usage: json <file> [--data] [--object <id>[,<gen>]] [--field <name>]
+#[cfg(feature="cache")]
+fn main() -> Result<(), PdfError> {
+    let mut args = args().skip(1);
+    let path = args.next().expect("Please provide a file path to the PDF you want to dump.");
+
+    let mut options = JsonOptions::new();
+    let mut object = None;
+    let mut field = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--data" => options = options.stream_data(true),
+            "--object" => {
+                let spec = args.next().expect("--object needs an object number");
+                let (id, gen) = spec.split_once(',').unwrap_or((&spec, "0"));
+                object = Some(PlainRef {
+                    id: id.parse().expect("invalid object number"),
+                    gen: gen.parse().expect("invalid generation number"),
+                });
+            }
+            "--field" => field = Some(args.next().expect("--field needs a field name")),
+            _ => panic!("unknown argument {}", arg),
+        }
+    }
+
+    let file = FileOptions::cached().open(&path)?;
+    let json = match (object, field) {
+        (Some(r), _) => file.object_to_json(r, &options),
+        (None, Some(name)) => file.field_to_json(&name, &options)?,
+        (None, None) => file.to_json(&options),
+    };
+    println!("{:#}", json);
+    Ok(())
+}
+
+#[cfg(not(feature="cache"))]
+fn main() {}
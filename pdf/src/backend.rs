@@ -1,10 +1,14 @@
 use crate::error::*;
 use crate::parser::Lexer;
-use crate::parser::read_xref_and_trailer_at;
+use crate::parser::{read_xref_and_trailer_at, check_xref_size};
 use crate::xref::XRefTable;
 use crate::primitive::Dictionary;
 use crate::object::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::ops::Deref;
+use std::sync::Mutex;
 
 use std::ops::{
     RangeFull,
@@ -16,13 +20,43 @@ use std::ops::{
 pub const MAX_ID: u32 = 1_000_000;
 
 pub trait Backend: Sized {
-    fn read<T: IndexRange>(&self, range: T) -> Result<&[u8]>;
+    fn read<T: IndexRange>(&self, range: T) -> Result<Cow<'_, [u8]>>;
     //fn write<T: IndexRange>(&mut self, range: T) -> Result<&mut [u8]>;
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
+    /// Run `f` on a lexer positioned at `pos`.
+    ///
+    /// Only a window of the file is read at first. As long as `f` runs out of data before the
+    /// window reaches the end of the file, it is run again with a larger one. Other errors
+    /// are returned right away, so a malformed object doesn't pull in the rest of the file.
+    fn lex_at<T>(&self, pos: usize, mut f: impl FnMut(&mut Lexer) -> Result<T>) -> Result<T> {
+        let mut window = 64 * 1024;
+        loop {
+            let end = pos.saturating_add(window).min(self.len());
+            let data = t!(self.read(pos .. end));
+            match f(&mut Lexer::with_offset(&data, pos)) {
+                Err(e) if e.is_eof() && end < self.len() => window *= 4,
+                r => return r
+            }
+        }
+    }
+
+    /// Write `range` of the file to `out`.
+    fn copy_to<T: IndexRange>(&self, range: T, out: &mut impl Write) -> Result<()> {
+        const CHUNK: usize = 1024 * 1024;
+        let range = t!(range.to_range(self.len()));
+        let mut pos = range.start;
+        while pos < range.end {
+            let end = pos.saturating_add(CHUNK).min(range.end);
+            out.write_all(&t!(self.read(pos .. end)))?;
+            pos = end;
+        }
+        Ok(())
+    }
+
     /// Returns the offset of the beginning of the file, i.e., where the `%PDF-1.5` header is.
     /// (currently only used internally!)
     fn locate_start_offset(&self) -> Result<usize> {
@@ -43,10 +77,18 @@ pub trait Backend: Sized {
         // `\nPOS\n%%EOF` where POS is the position encoded as base 10 integer.
         // u64::MAX has 20 digits + \n\n(2) + %%EOF(5) = 27 bytes max.
 
-        let mut lexer = Lexer::new(t!(self.read(..)));
-        lexer.set_pos_from_end(0);
-        t!(lexer.seek_substr_back(b"startxref"));
-        t!(lexer.next()).to::<usize>()
+        let mut window = 1024;
+        loop {
+            let start = self.len().saturating_sub(window);
+            let data = t!(self.read(start ..));
+            let mut lexer = Lexer::new(&data);
+            lexer.set_pos_from_end(0);
+            match lexer.seek_substr_back(b"startxref").and_then(|_| lexer.next()) {
+                Ok(pos) => return pos.to::<usize>(),
+                Err(e) if start == 0 => return Err(e),
+                Err(_) => window *= 64
+            }
+        }
     }
 
     /// Used internally by File, but could also be useful for applications that want to look at the raw PDF objects.
@@ -57,9 +99,7 @@ pub trait Backend: Sized {
             bail!("XRef offset outside file bounds");
         }
 
-        let mut lexer = Lexer::with_offset(t!(self.read(pos ..)), pos);
-        
-        let (xref_sections, trailer) = t!(read_xref_and_trailer_at(&mut lexer, resolve));
+        let (xref_sections, trailer) = t!(self.lex_at(pos, |lexer| read_xref_and_trailer_at(lexer, resolve)));
         
         let highest_id = t!(trailer.get("Size")
             .ok_or_else(|| PdfError::MissingEntry {field: "Size".into(), typ: "XRefTable"})?
@@ -68,6 +108,7 @@ pub trait Backend: Sized {
         if highest_id > MAX_ID {
             bail!("too many objects");
         }
+        t!(check_xref_size(highest_id as usize, resolve));
         let mut refs = XRefTable::new(highest_id as ObjNr);
         for section in xref_sections {
             refs.add_entries_from(section)?;
@@ -88,8 +129,7 @@ pub trait Backend: Sized {
             seen.push(prev_xref_offset);
 
             let pos = t!(start_offset.checked_add(prev_xref_offset).ok_or(PdfError::Invalid));
-            let mut lexer = Lexer::with_offset(t!(self.read(pos..)), pos);
-            let (xref_sections, trailer) = t!(read_xref_and_trailer_at(&mut lexer, resolve));
+            let (xref_sections, trailer) = t!(self.lex_at(pos, |lexer| read_xref_and_trailer_at(lexer, resolve)));
             
             for section in xref_sections {
                 refs.add_entries_from(section)?;
@@ -111,9 +151,14 @@ pub trait Backend: Sized {
 
 
 impl<T> Backend for T where T: Deref<Target=[u8]> { //+ DerefMut<Target=[u8]> {
-    fn read<R: IndexRange>(&self, range: R) -> Result<&[u8]> {
+    fn read<R: IndexRange>(&self, range: R) -> Result<Cow<'_, [u8]>> {
         let r = t!(range.to_range(self.len()));
-        Ok(&self[r])
+        Ok(Cow::Borrowed(&self[r]))
+    }
+    // everything is in memory already
+    fn lex_at<U>(&self, pos: usize, mut f: impl FnMut(&mut Lexer) -> Result<U>) -> Result<U> {
+        let r = t!((pos ..).to_range(self.len()));
+        f(&mut Lexer::with_offset(&self[r], pos))
     }
     /*
     fn write<R: IndexRange>(&mut self, range: R) -> Result<&mut [u8]> {
@@ -126,6 +171,105 @@ impl<T> Backend for T where T: Deref<Target=[u8]> { //+ DerefMut<Target=[u8]> {
     }
 }
 
+/// A backend for any `Read + Seek` source, like a `std::fs::File`, that only keeps
+/// a bounded number of blocks of the file in memory.
+///
+/// The blocks that were used least recently are dropped first. Reads that are larger
+/// than half of the cache, like the data of big streams, bypass it.
+pub struct ReaderBackend<R> {
+    len: usize,
+    block_size: usize,
+    max_blocks: usize,
+    inner: Mutex<Blocks<R>>,
+}
+struct Blocks<R> {
+    reader: R,
+    // block index -> (data, last use)
+    blocks: HashMap<usize, (Vec<u8>, u64)>,
+    clock: u64,
+}
+impl<R: Read + Seek> ReaderBackend<R> {
+    /// Cache up to 64 blocks of 64 KiB.
+    pub fn new(reader: R) -> Result<Self> {
+        Self::with_cache_size(reader, 64 * 1024, 64)
+    }
+    /// Cache up to `max_blocks` blocks of `block_size` bytes.
+    pub fn with_cache_size(mut reader: R, block_size: usize, max_blocks: usize) -> Result<Self> {
+        let len = reader.seek(SeekFrom::End(0))? as usize;
+        Ok(ReaderBackend {
+            len,
+            block_size: block_size.max(1),
+            max_blocks: max_blocks.max(1),
+            inner: Mutex::new(Blocks { reader, blocks: HashMap::new(), clock: 0 }),
+        })
+    }
+    pub fn into_inner(self) -> R {
+        match self.inner.into_inner() {
+            Ok(blocks) => blocks.reader,
+            Err(e) => e.into_inner().reader,
+        }
+    }
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Blocks<R>>> {
+        self.inner.lock().map_err(|_| other!("a read from the backend panicked"))
+    }
+}
+impl<R: Read + Seek> Blocks<R> {
+    fn read_exact_at(&mut self, pos: usize, buf: &mut [u8]) -> io::Result<()> {
+        self.reader.seek(SeekFrom::Start(pos as u64))?;
+        self.reader.read_exact(buf)
+    }
+}
+impl<R: Read + Seek> Backend for ReaderBackend<R> {
+    fn read<T: IndexRange>(&self, range: T) -> Result<Cow<'_, [u8]>> {
+        let range = t!(range.to_range(self.len));
+        let mut inner = self.lock()?;
+        let mut out = vec![0; range.len()];
+        if range.len() > self.block_size * self.max_blocks / 2 {
+            inner.read_exact_at(range.start, &mut out)?;
+            return Ok(Cow::Owned(out));
+        }
+
+        let mut pos = range.start;
+        while pos < range.end {
+            let index = pos / self.block_size;
+            let block_start = index * self.block_size;
+            inner.clock += 1;
+            let clock = inner.clock;
+            if !inner.blocks.contains_key(&index) {
+                if inner.blocks.len() >= self.max_blocks {
+                    let oldest = inner.blocks.iter().min_by_key(|(_, &(_, used))| used).map(|(&i, _)| i);
+                    if let Some(i) = oldest {
+                        inner.blocks.remove(&i);
+                    }
+                }
+                let mut block = vec![0; self.block_size.min(self.len - block_start)];
+                inner.read_exact_at(block_start, &mut block)?;
+                inner.blocks.insert(index, (block, clock));
+            }
+            let (block, used) = inner.blocks.get_mut(&index).unwrap();
+            *used = clock;
+            let end = range.end.min(block_start + block.len());
+            out[pos - range.start .. end - range.start].copy_from_slice(&block[pos - block_start .. end - block_start]);
+            pos = end;
+        }
+        Ok(Cow::Owned(out))
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    // copy straight from the source, without filling the cache
+    fn copy_to<T: IndexRange>(&self, range: T, out: &mut impl Write) -> Result<()> {
+        let range = t!(range.to_range(self.len));
+        let mut inner = self.lock()?;
+        inner.reader.seek(SeekFrom::Start(range.start as u64))?;
+        let copied = io::copy(&mut (&mut inner.reader).take(range.len() as u64), out)?;
+        if copied != range.len() as u64 {
+            err!(PdfError::EOF);
+        }
+        Ok(())
+    }
+}
+
 /// `IndexRange` is implemented by Rust's built-in range types, produced
 /// by range syntax like `..`, `a..`, `..b` or `c..d`.
 pub trait IndexRange
@@ -7,7 +7,7 @@ use datasize::DataSize;
 
 use crate::PdfError;
 use crate::any::AnySync;
-use crate::enc::StreamFilter;
+use crate::enc::{Decoders, StreamFilter};
 use crate::file::Cache;
 use crate::file::FileOptions;
 use crate::file::Log;
@@ -19,6 +19,7 @@ use crate::error::Result;
 use crate::parser::ParseFlags;
 use crate::primitive::Dictionary;
 use crate::primitive::Primitive;
+use crate::primitive::PdfString;
 
 #[derive(Default)]
 pub struct PageBuilder {
@@ -220,6 +221,12 @@ impl<'a, R, U> Importer<'a, R, U> {
     }
 }
 impl<'a, R: Resolve, U> Importer<'a, R, U> {
+    /// Refer to `new` wherever `old` is cloned, instead of cloning `old`.
+    ///
+    /// This allows to write the clone of an object yourself, e.g. with some entries changed.
+    pub fn redirect(&mut self, old: PlainRef, new: PlainRef) {
+        self.map.insert(old, new);
+    }
     pub fn finish(self) -> ImporterMap<R> {
         ImporterMap { resolver: self.resolver, map: self.map }
     }
@@ -337,6 +344,12 @@ impl<'a, R: Resolve, U> Resolve for Importer<'a, R, U> {
     fn stream_data(&self, id: PlainRef, range: Range<usize>) -> Result<Arc<[u8]>> {
         self.resolver.stream_data(id, range)
     }
+    fn decoders(&self) -> &Decoders {
+        self.resolver.decoders()
+    }
+    fn count_decoded(&self, len: usize) -> Result<()> {
+        self.resolver.count_decoded(len)
+    }
 }
 impl<'a, R, U: Updater> Updater for Importer<'a, R, U> {
     fn create<T: ObjectWrite>(&mut self, obj: T) -> Result<RcRef<T>> {
@@ -369,13 +382,14 @@ impl<'a, R: Resolve, U: Updater> Cloner for Importer<'a, R, U> {
         if let Some(&new_ref) = self.map.get(&old) {
             return Ok(new_ref);
         }
+        // the object may refer back to itself, so it is known before it is cloned
+        let promise = self.updater.promise::<Primitive>();
+        let new = promise.get_inner();
+        self.map.insert(old, new);
+
         let obj = self.resolver.resolve(old)?;
         let clone = obj.deep_clone(self)?;
-
-        let new = self.updater.create(clone)?
-            .get_ref().get_inner();
-
-        self.map.insert(old, new);
+        self.updater.fulfill(promise, clone)?;
 
         Ok(new)
     }
@@ -402,4 +416,959 @@ impl<'a, R: Resolve, U: Updater> Cloner for Importer<'a, R, U> {
         self.shared.insert(key, (AnySync::new_without_size(old.clone()), AnySync::new_without_size(new.clone())));
         Ok(new)
     }
-}
\ No newline at end of file
+}
+/// What happens to a field of an added document whose name is already taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldCollision {
+    /// Put a prefix in front of the name of the added field, see `FormMerger::add_with_prefix`
+    #[default]
+    Rename,
+    /// Turn the fields into one field, whose widgets all show the same value
+    Merge,
+}
+
+// entries of a field dictionary, as opposed to those of its widget annotation
+const FIELD_KEYS: &[&str] = &["FT", "Parent", "T", "TU", "TM", "Ff", "V", "DV", "DA", "Q", "DS", "RV", "Opt", "TI", "I", "MaxLen", "Lock", "SV"];
+
+/// Combines the pages and interactive forms of several documents into one.
+///
+/// Pages are imported with their annotations, and the field trees of the documents are
+/// joined in one `/AcroForm`. The default resources (`/DR`) of the forms are merged, and
+/// fonts that would replace another one of the same name are renamed, also in the `/DA`
+/// strings that use them.
+///
+/// ```no_run
+/// # use pdf::file::FileOptions;
+/// # use pdf::build::{FormMerger, FieldCollision};
+/// let a = FileOptions::cached().open("a.pdf").unwrap();
+/// let b = FileOptions::cached().open("b.pdf").unwrap();
+/// let mut merger = FormMerger::new(FileOptions::uncached()).collisions(FieldCollision::Merge);
+/// merger.add(&a.resolver(), &a.trailer.root).unwrap();
+/// merger.add(&b.resolver(), &b.trailer.root).unwrap();
+/// let data = merger.build().unwrap();
+/// ```
+pub struct FormMerger<SC, OC, L> {
+    pub storage: Storage<Vec<u8>, SC, OC, L>,
+    collisions: FieldCollision,
+    documents: usize,
+    pages: PromisedRef<Primitive>,
+    kids: Vec<Primitive>,
+    // all field and widget dictionaries, written in `build()`
+    fields: HashMap<PlainRef, Dictionary>,
+    removed: Vec<PlainRef>,
+    top_fields: Vec<PlainRef>,
+    // position of the named top-level fields in `top_fields`
+    names: HashMap<PdfString, usize>,
+    share_imports: bool,
+    // objects of the added document -> their imports, with `share_imports`
+    imported: HashMap<PlainRef, PlainRef>,
+    has_form: bool,
+    dr: Dictionary,
+    da: Option<Primitive>,
+    q: Option<Primitive>,
+    need_appearances: bool,
+    sig_flags: i32,
+    checkpoint: Checkpoint,
+}
+
+/// The state of a `FormMerger` before the document that is being added,
+/// which is restored if the document can't be added completely.
+#[derive(Default)]
+struct Checkpoint {
+    objects: usize,
+    documents: usize,
+    kids: usize,
+    removed: usize,
+    top_fields: usize,
+    // fields of earlier documents as they were before being merged with the added ones
+    fields: HashMap<PlainRef, Dictionary>,
+    // top-level fields that were replaced by merged ones
+    replaced: Vec<(usize, PlainRef)>,
+    imported: HashMap<PlainRef, PlainRef>,
+    dr: Dictionary,
+    da: Option<Primitive>,
+    q: Option<Primitive>,
+    has_form: bool,
+    need_appearances: bool,
+    sig_flags: i32,
+}
+
+impl<SC, OC, L> FormMerger<SC, OC, L>
+where
+    SC: Cache<Result<AnySync, Arc<PdfError>>>,
+    OC: Cache<Result<Arc<[u8]>, Arc<PdfError>>>,
+    L: Log,
+{
+    pub fn new(fileoptions: FileOptions<'_, SC, OC, L>) -> Self {
+        let mut storage = fileoptions.storage();
+        let pages = storage.promise();
+        FormMerger {
+            storage,
+            collisions: FieldCollision::default(),
+            documents: 0,
+            pages,
+            kids: vec![],
+            fields: HashMap::new(),
+            removed: vec![],
+            top_fields: vec![],
+            names: HashMap::new(),
+            share_imports: false,
+            imported: HashMap::new(),
+            has_form: false,
+            dr: Dictionary::new(),
+            da: None,
+            q: None,
+            need_appearances: false,
+            sig_flags: 0,
+            checkpoint: Checkpoint::default(),
+        }
+    }
+    pub fn collisions(mut self, collisions: FieldCollision) -> Self {
+        self.collisions = collisions;
+        self
+    }
+    /// Import the objects of a document only once, for documents that are all copies of
+    /// the same file, like the filled copies of a template.
+    ///
+    /// Pages, annotations and fields are imported for every copy, everything they refer to,
+    /// like fonts, images and appearance streams, is shared by all copies.
+    pub fn share_imports(mut self, share_imports: bool) -> Self {
+        self.share_imports = share_imports;
+        self
+    }
+
+    /// Add all pages and fields of the document with the catalog `root`.
+    ///
+    /// With `FieldCollision::Rename`, fields of the n-th document whose names are taken
+    /// get the prefix `docn_`.
+    pub fn add(&mut self, resolve: impl Resolve, root: &RcRef<Catalog>) -> Result<()> {
+        let prefix = format!("doc{}_", self.documents + 1);
+        self.add_with_prefix(resolve, root, &prefix)
+    }
+
+    /// Like `add`, with the given prefix for fields whose names are taken.
+    ///
+    /// If the document can't be added, nothing of it is left in the merged document.
+    pub fn add_with_prefix(&mut self, resolve: impl Resolve, root: &RcRef<Catalog>, prefix: &str) -> Result<()> {
+        self.checkpoint = Checkpoint {
+            objects: self.storage.num_objects(),
+            documents: self.documents,
+            kids: self.kids.len(),
+            removed: self.removed.len(),
+            top_fields: self.top_fields.len(),
+            fields: HashMap::new(),
+            replaced: vec![],
+            imported: match self.share_imports {
+                true => self.imported.clone(),
+                false => HashMap::new(),
+            },
+            dr: self.dr.clone(),
+            da: self.da.clone(),
+            q: self.q.clone(),
+            has_form: self.has_form,
+            need_appearances: self.need_appearances,
+            sig_flags: self.sig_flags,
+        };
+        let result = self.add_document(resolve, root, prefix);
+        match result {
+            Ok(()) => self.checkpoint = Checkpoint::default(),
+            Err(_) => self.restore(),
+        }
+        result
+    }
+
+    /// Go back to the state before the document that failed to be added.
+    fn restore(&mut self) {
+        let checkpoint = std::mem::take(&mut self.checkpoint);
+        self.storage.truncate(checkpoint.objects);
+        self.documents = checkpoint.documents;
+        self.kids.truncate(checkpoint.kids);
+        self.removed.truncate(checkpoint.removed);
+        for (i, r) in checkpoint.replaced.into_iter().rev() {
+            self.top_fields[i] = r;
+        }
+        self.top_fields.truncate(checkpoint.top_fields);
+        self.names.retain(|_, &mut i| i < checkpoint.top_fields);
+        self.fields.extend(checkpoint.fields);
+        self.fields.retain(|r, _| (r.id as usize) < checkpoint.objects);
+        if self.share_imports {
+            self.imported = checkpoint.imported;
+        }
+        self.dr = checkpoint.dr;
+        self.da = checkpoint.da;
+        self.q = checkpoint.q;
+        self.has_form = checkpoint.has_form;
+        self.need_appearances = checkpoint.need_appearances;
+        self.sig_flags = checkpoint.sig_flags;
+    }
+
+    fn add_document(&mut self, resolve: impl Resolve, root: &RcRef<Catalog>, prefix: &str) -> Result<()> {
+        self.documents += 1;
+        let catalog = resolve.resolve(root.get_ref().get_inner())?.into_dictionary()?;
+        let pages_root = try_opt!(catalog.get("Pages")).clone().into_reference()?;
+        let pages = leaf_pages(&resolve, pages_root)?;
+        let mut form = match catalog.get("AcroForm") {
+            Some(p) => resolve_dict(&resolve, p)?,
+            None => Dictionary::new(),
+        };
+        let top_fields = refs(&resolve, form.get("Fields"))?;
+        let mut nodes = vec![];
+        let mut queue = top_fields.clone();
+        while let Some(r) = queue.pop() {
+            if !nodes.contains(&r) {
+                nodes.push(r);
+                queue.extend(refs(&resolve, resolve.resolve(r)?.into_dictionary()?.get("Kids"))?);
+            }
+        }
+
+        // with shared imports, only the annotations are imported again besides pages and fields
+        let mut per_copy = HashSet::new();
+        if self.share_imports {
+            for (_, page) in pages.iter() {
+                if let Some(&Primitive::Reference(r)) = page.get("Annots") {
+                    per_copy.insert(r);
+                }
+                for annot in refs(&resolve, page.get("Annots"))? {
+                    per_copy.insert(annot);
+                    if let Some(&Primitive::Reference(popup)) = resolve.resolve(annot)?.into_dictionary()?.get("Popup") {
+                        per_copy.insert(popup);
+                    }
+                }
+            }
+        }
+
+        let FormMerger { storage, fields, dr, imported, .. } = self;
+        let mut importer = Importer::new(resolve, storage);
+        for (&old, &new) in imported.iter().filter(|&(old, _)| !per_copy.contains(old)) {
+            importer.redirect(old, new);
+        }
+
+        // pages and fields are written here, everything they refer to is imported as it is
+        let mut page_promises = Vec::with_capacity(pages.len());
+        for &(old, _) in pages.iter() {
+            let promise = importer.promise::<Primitive>();
+            importer.redirect(old, promise.get_inner());
+            page_promises.push(promise);
+        }
+        let mut new_nodes = Vec::with_capacity(nodes.len());
+        for &old in nodes.iter() {
+            let new = importer.promise::<Primitive>().get_inner();
+            importer.redirect(old, new);
+            new_nodes.push(new);
+        }
+
+        let mut renamed_fonts = HashMap::new();
+        if let Some(p) = form.get("DR") {
+            for (category, entries) in resolve_dict(&importer, p)?.iter() {
+                let mut merged = match dr.remove(category) {
+                    Some(Primitive::Dictionary(d)) => d,
+                    _ => Dictionary::new(),
+                };
+                for (name, value) in resolve_dict(&importer, entries)?.iter() {
+                    let value = value.deep_clone(&mut importer)?;
+                    match merged.get(name) {
+                        None => {
+                            merged.insert(name.clone(), value);
+                        }
+                        Some(old) if *old == value => {}
+                        // other resources are only used by appearance streams, which have their own
+                        Some(_) if category.as_str() != "Font" => {}
+                        Some(_) => {
+                            let new_name = (1 ..).map(|n| format!("{}_{}", name, n)).find(|n| merged.get(n).is_none()).unwrap();
+                            renamed_fonts.insert(name.as_str().to_string(), new_name.clone());
+                            merged.insert(new_name, value);
+                        }
+                    }
+                }
+                dr.insert(category.clone(), merged);
+            }
+        }
+
+        for (&old, &new) in nodes.iter().zip(new_nodes.iter()) {
+            let mut dict = importer.resolve(old)?.into_dictionary()?.deep_clone(&mut importer)?;
+            if let Some(Primitive::String(da)) = dict.get("DA") {
+                dict.insert("DA", rename_fonts(da, &renamed_fonts));
+            }
+            fields.insert(new, dict);
+        }
+
+        for ((_, mut dict), promise) in pages.into_iter().zip(page_promises) {
+            dict.insert("Parent", Primitive::Null);
+            let mut dict = dict.deep_clone(&mut importer)?;
+            dict.insert("Parent", Primitive::Reference(self.pages.get_inner()));
+            self.kids.push(Primitive::Reference(promise.get_inner()));
+            importer.fulfill(promise, Primitive::Dictionary(dict))?;
+        }
+        if self.share_imports {
+            self.imported = importer.finish().map;
+        }
+
+        if catalog.get("AcroForm").is_none() {
+            return Ok(());
+        }
+        self.has_form = true;
+        self.need_appearances |= matches!(form.get("NeedAppearances"), Some(Primitive::Boolean(true)));
+        if let Some(Primitive::Integer(flags)) = form.get("SigFlags") {
+            self.sig_flags |= flags;
+        }
+
+        // the defaults of the form go to its fields if they differ from the merged ones
+        let da = match form.remove("DA") {
+            Some(Primitive::String(da)) => Some(rename_fonts(&da, &renamed_fonts)),
+            _ => None,
+        };
+        let q = form.remove("Q");
+        let mut defaults = Dictionary::new();
+        for (key, value, merged) in [("DA", da, &mut self.da), ("Q", q, &mut self.q)] {
+            match (value, &*merged) {
+                (Some(value), None) if self.top_fields.is_empty() => *merged = Some(value),
+                (Some(value), Some(old)) if value == *old => {}
+                (Some(value), _) => {
+                    defaults.insert(key, value);
+                }
+                _ => {}
+            }
+        }
+
+        for &old in top_fields.iter() {
+            let new = new_nodes[nodes.iter().position(|&r| r == old).unwrap()];
+            let dict = self.fields.get_mut(&new).unwrap();
+            for (key, value) in defaults.iter() {
+                if dict.get(key).is_none() {
+                    dict.insert(key.clone(), value.clone());
+                }
+            }
+
+            let name = match dict.get("T") {
+                Some(Primitive::String(name)) => name.clone(),
+                _ => {
+                    self.top_fields.push(new);
+                    continue;
+                }
+            };
+            match (self.names.get(&name).copied(), self.collisions) {
+                (None, _) => {
+                    self.names.insert(name, self.top_fields.len());
+                    self.top_fields.push(new);
+                }
+                (Some(_), FieldCollision::Rename) => {
+                    let new_name = prefixed(&name, prefix);
+                    if self.names.contains_key(&new_name) {
+                        bail!("field {} is already taken", new_name.to_string_lossy());
+                    }
+                    self.fields.get_mut(&new).unwrap().insert("T", Primitive::String(new_name.clone()));
+                    self.names.insert(new_name, self.top_fields.len());
+                    self.top_fields.push(new);
+                }
+                (Some(i), FieldCollision::Merge) => {
+                    self.checkpoint.replaced.push((i, self.top_fields[i]));
+                    self.top_fields[i] = self.merge_field(self.top_fields[i], new)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn field_name(&self, r: PlainRef) -> Option<&PdfString> {
+        match self.fields.get(&r)?.get("T") {
+            Some(Primitive::String(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The field `r`, to be changed. Fields of earlier documents are saved first.
+    fn field_mut(&mut self, r: PlainRef) -> &mut Dictionary {
+        let dict = self.fields.get_mut(&r).unwrap();
+        if (r.id as usize) < self.checkpoint.objects {
+            self.checkpoint.fields.entry(r).or_insert_with(|| dict.clone());
+        }
+        dict
+    }
+
+    /// Turn a field that is its own widget into a field with the widget as kid
+    /// and return the field.
+    fn lift(&mut self, r: PlainRef) -> PlainRef {
+        let widget = self.field_mut(r);
+        if !matches!(widget.get("Subtype"), Some(Primitive::Name(n)) if n == "Widget") {
+            return r;
+        }
+        let mut field = Dictionary::new();
+        for &key in FIELD_KEYS {
+            if let Some(value) = widget.remove(key) {
+                field.insert(key, value);
+            }
+        }
+        let new = self.storage.promise::<Primitive>().get_inner();
+        self.field_mut(r).insert("Parent", Primitive::Reference(new));
+        field.insert("Kids", vec![Primitive::Reference(r)]);
+        self.fields.insert(new, field);
+        new
+    }
+
+    /// Make `incoming` part of the field `existing` of the same name and return the merged field.
+    fn merge_field(&mut self, existing: PlainRef, incoming: PlainRef) -> Result<PlainRef> {
+        let existing = self.lift(existing);
+        let incoming = self.lift(incoming);
+        let dict = self.fields.remove(&incoming).unwrap();
+        self.removed.push(incoming);
+
+        let merged = self.field_mut(existing);
+        if let (Some(a), Some(b)) = (merged.get("FT"), dict.get("FT")) {
+            if a != b {
+                bail!("fields named {:?} have different types", merged.get("T"));
+            }
+        }
+        for &key in FIELD_KEYS {
+            if merged.get(key).is_none() && !matches!(key, "Parent" | "T") {
+                if let Some(value) = dict.get(key) {
+                    merged.insert(key, value.clone());
+                }
+            }
+        }
+
+        for kid in refs(&NoResolve, dict.get("Kids"))? {
+            let same = match self.field_name(kid) {
+                Some(name) => {
+                    let name = name.clone();
+                    refs(&NoResolve, self.fields[&existing].get("Kids"))?.into_iter()
+                        .find(|&r| self.field_name(r) == Some(&name))
+                }
+                None => None,
+            };
+            let kid = match same {
+                Some(same) => {
+                    let merged = self.merge_field(same, kid)?;
+                    let kids = refs(&NoResolve, self.fields[&existing].get("Kids"))?;
+                    let kids: Vec<_> = kids.into_iter().filter(|&r| r != same && r != merged).map(Primitive::Reference).collect();
+                    self.field_mut(existing).insert("Kids", kids);
+                    merged
+                }
+                None => kid,
+            };
+            self.field_mut(kid).insert("Parent", Primitive::Reference(existing));
+            let merged = self.field_mut(existing);
+            let mut kids = match merged.remove("Kids") {
+                Some(Primitive::Array(kids)) => kids,
+                _ => vec![],
+            };
+            kids.push(Primitive::Reference(kid));
+            merged.insert("Kids", kids);
+        }
+        Ok(existing)
+    }
+
+    /// Write the merged document.
+    pub fn build(mut self) -> Result<Vec<u8>> {
+        for (r, dict) in std::mem::take(&mut self.fields) {
+            self.storage.update(r, Primitive::Dictionary(dict))?;
+        }
+        for r in self.removed.drain(..) {
+            self.storage.update(r, Primitive::Null)?;
+        }
+
+        let mut pages = Dictionary::new();
+        pages.insert("Type", Primitive::name("Pages"));
+        pages.insert("Count", Primitive::Integer(self.kids.len() as i32));
+        pages.insert("Kids", std::mem::take(&mut self.kids));
+        let pages_ref = self.pages.get_inner();
+        self.storage.fulfill(self.pages, Primitive::Dictionary(pages))?;
+
+        let mut catalog = Dictionary::new();
+        catalog.insert("Type", Primitive::name("Catalog"));
+        catalog.insert("Pages", Primitive::Reference(pages_ref));
+        if self.has_form {
+            let mut form = Dictionary::new();
+            form.insert("Fields", self.top_fields.iter().map(|&r| Primitive::Reference(r)).collect::<Vec<_>>());
+            if self.need_appearances {
+                form.insert("NeedAppearances", Primitive::Boolean(true));
+            }
+            if self.sig_flags != 0 {
+                form.insert("SigFlags", Primitive::Integer(self.sig_flags));
+            }
+            if !self.dr.is_empty() {
+                form.insert("DR", std::mem::take(&mut self.dr));
+            }
+            if let Some(da) = self.da.take() {
+                form.insert("DA", da);
+            }
+            if let Some(q) = self.q.take() {
+                form.insert("Q", q);
+            }
+            let form = self.storage.create(Primitive::Dictionary(form))?.get_ref().get_inner();
+            catalog.insert("AcroForm", Primitive::Reference(form));
+        }
+        let catalog = self.storage.create(Primitive::Dictionary(catalog))?.get_ref().get_inner();
+
+        let mut trailer = Trailer {
+            root: self.storage.resolver().get(Ref::new(catalog))?,
+            encrypt_dict: None,
+            size: 0,
+            id: vec![],
+            info_dict: None,
+            prev_trailer_pos: None,
+        };
+        self.storage.save(&mut trailer)?;
+        Ok(self.storage.into_inner())
+    }
+}
+
+/// Builds new documents out of page ranges of a document.
+///
+/// Each new document only gets the objects that its pages refer to, together with the
+/// outline items, named destinations, page labels and form fields that belong to them.
+/// References to pages that are left out become `null`.
+///
+/// ```no_run
+/// # use pdf::file::FileOptions;
+/// # use pdf::build::PageSplitter;
+/// let file = FileOptions::cached().open("batch.pdf").unwrap();
+/// let splitter = PageSplitter::new(file.resolver(), &file.trailer.root).unwrap();
+/// for (i, data) in splitter.split([0 .. 2, 2 .. 5]).unwrap().into_iter().enumerate() {
+///     std::fs::write(format!("part_{}.pdf", i), data).unwrap();
+/// }
+/// ```
+pub struct PageSplitter<R> {
+    resolve: R,
+    catalog: Dictionary,
+    // leaf pages, with the attributes they inherit
+    pages: Vec<(PlainRef, Dictionary)>,
+    // all nodes of the page tree
+    nodes: Vec<PlainRef>,
+    // named destinations from the name tree and from the `/Dests` dictionary
+    named_dests: Vec<(PdfString, Primitive)>,
+    old_dests: Dictionary,
+}
+impl<R: Resolve> PageSplitter<R> {
+    pub fn new(resolve: R, root: &RcRef<Catalog>) -> Result<Self> {
+        let catalog = resolve.resolve(root.get_ref().get_inner())?.into_dictionary()?;
+        let pages_root = try_opt!(catalog.get("Pages")).clone().into_reference()?;
+        let pages = leaf_pages(&resolve, pages_root)?;
+
+        let mut nodes = vec![pages_root];
+        let mut queue = vec![pages_root];
+        while let Some(r) = queue.pop() {
+            let dict = resolve.resolve(r)?.into_dictionary()?;
+            if !matches!(dict.get("Type"), Some(Primitive::Name(n)) if n == "Page") {
+                for kid in refs(&resolve, dict.get("Kids"))? {
+                    if !nodes.contains(&kid) {
+                        nodes.push(kid);
+                        queue.push(kid);
+                    }
+                }
+            }
+        }
+
+        let mut named_dests = vec![];
+        if let Some(names) = catalog.get("Names") {
+            if let Some(dests) = resolve_dict(&resolve, names)?.get("Dests") {
+                for (key, dest) in tree_entries(&resolve, dests, "Names")? {
+                    if let Primitive::String(key) = key {
+                        named_dests.push((key, dest));
+                    }
+                }
+            }
+        }
+        let old_dests = match catalog.get("Dests") {
+            Some(p) => resolve_dict(&resolve, p)?,
+            None => Dictionary::new(),
+        };
+
+        Ok(PageSplitter { resolve, catalog, pages, nodes, named_dests, old_dests })
+    }
+
+    /// The number of pages of the document
+    pub fn num_pages(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    /// Build one document for each of the page ranges
+    pub fn split(&self, ranges: impl IntoIterator<Item=Range<u32>>) -> Result<Vec<Vec<u8>>> {
+        ranges.into_iter().map(|range| self.extract(range)).collect()
+    }
+
+    /// Build a document with the pages in `range`, counting from 0
+    pub fn extract(&self, range: Range<u32>) -> Result<Vec<u8>> {
+        if range.start >= range.end || range.end > self.num_pages() {
+            return Err(PdfError::PageOutOfBounds { page_nr: range.end, max: self.num_pages() });
+        }
+        let pages = &self.pages[range.start as usize .. range.end as usize];
+        let mut storage = FileOptions::uncached().storage();
+        let mut importer = Importer::new(&self.resolve, &mut storage);
+
+        // the pages are written here, other pages and the old page tree are not copied
+        let null = importer.create(Primitive::Null)?.get_ref().get_inner();
+        for &r in self.nodes.iter().chain(self.pages.iter().map(|(r, _)| r)) {
+            importer.redirect(r, null);
+        }
+        let pages_root = importer.promise::<Primitive>();
+        let mut page_promises = Vec::with_capacity(pages.len());
+        for &(old, _) in pages {
+            let promise = importer.promise::<Primitive>();
+            importer.redirect(old, promise.get_inner());
+            page_promises.push(promise);
+        }
+
+        // the fields of the widgets on the pages
+        let mut fields = vec![];
+        for (_, page) in pages {
+            for annot in refs(&self.resolve, page.get("Annots"))? {
+                let mut r = annot;
+                loop {
+                    let dict = self.resolve.resolve(r)?.into_dictionary()?;
+                    if r == annot && !matches!(dict.get("Subtype"), Some(Primitive::Name(n)) if n == "Widget") || fields.contains(&r) {
+                        break;
+                    }
+                    fields.push(r);
+                    match dict.get("Parent") {
+                        Some(&Primitive::Reference(parent)) => r = parent,
+                        _ => break,
+                    }
+                }
+            }
+        }
+        let mut field_promises = Vec::with_capacity(fields.len());
+        for &old in fields.iter() {
+            let promise = importer.promise::<Primitive>();
+            importer.redirect(old, promise.get_inner());
+            field_promises.push(promise);
+        }
+
+        let mut kids = vec![];
+        for ((_, page), promise) in pages.iter().zip(page_promises) {
+            let mut page = page.clone();
+            page.insert("Parent", Primitive::Null);
+            let mut page = page.deep_clone(&mut importer)?;
+            page.insert("Parent", Primitive::Reference(pages_root.get_inner()));
+            kids.push(Primitive::Reference(promise.get_inner()));
+            importer.fulfill(promise, Primitive::Dictionary(page))?;
+        }
+        let mut tree = Dictionary::new();
+        tree.insert("Type", Primitive::name("Pages"));
+        tree.insert("Count", Primitive::Integer(kids.len() as i32));
+        tree.insert("Kids", kids);
+        let mut catalog = Dictionary::new();
+        catalog.insert("Type", Primitive::name("Catalog"));
+        catalog.insert("Pages", Primitive::Reference(pages_root.get_inner()));
+        importer.fulfill(pages_root, Primitive::Dictionary(tree))?;
+
+        let mut top_fields = vec![];
+        for (&old, promise) in fields.iter().zip(field_promises) {
+            let mut dict = self.resolve.resolve(old)?.into_dictionary()?;
+            if let Some(kids) = dict.get("Kids") {
+                let kids: Vec<_> = refs(&self.resolve, Some(kids))?.into_iter()
+                    .filter(|kid| fields.contains(kid))
+                    .map(Primitive::Reference)
+                    .collect();
+                dict.insert("Kids", kids);
+            }
+            if !matches!(dict.get("Parent"), Some(Primitive::Reference(_))) {
+                top_fields.push(Primitive::Reference(promise.get_inner()));
+            }
+            let dict = dict.deep_clone(&mut importer)?;
+            importer.fulfill(promise, Primitive::Dictionary(dict))?;
+        }
+        if let Some(form) = self.catalog.get("AcroForm") {
+            let mut form = resolve_dict(&self.resolve, form)?;
+            // the calculation order and XFA forms refer to fields of all pages
+            form.remove("CO");
+            form.remove("XFA");
+            form.insert("Fields", Primitive::Null);
+            let mut form = form.deep_clone(&mut importer)?;
+            form.insert("Fields", top_fields);
+            catalog.insert("AcroForm", importer.create(Primitive::Dictionary(form))?.get_ref().get_inner());
+        }
+
+        let in_range = |page: Option<PlainRef>| page.map(|p| pages.iter().any(|&(r, _)| r == p)).unwrap_or(false);
+
+        if let Some(outlines) = self.catalog.get("Outlines") {
+            let outlines = resolve_dict(&self.resolve, outlines)?;
+            let promise = importer.promise::<Primitive>();
+            let root = promise.get_inner();
+            let (first, last, count) = self.copy_outline_items(outlines.get("First"), root, &in_range, &mut importer)?;
+            if let (Some(first), Some(last)) = (first, last) {
+                let mut dict = Dictionary::new();
+                dict.insert("Type", Primitive::name("Outlines"));
+                dict.insert("First", Primitive::Reference(first));
+                dict.insert("Last", Primitive::Reference(last));
+                dict.insert("Count", Primitive::Integer(count));
+                importer.fulfill(promise, Primitive::Dictionary(dict))?;
+                catalog.insert("Outlines", Primitive::Reference(root));
+            } else {
+                importer.fulfill(promise, Primitive::Null)?;
+            }
+        }
+
+        let mut names = vec![];
+        for (name, dest) in self.named_dests.iter() {
+            if in_range(self.dest_page(dest)) {
+                names.push(Primitive::String(name.clone()));
+                names.push(dest.deep_clone(&mut importer)?);
+            }
+        }
+        if !names.is_empty() {
+            let mut dests = Dictionary::new();
+            dests.insert("Names", names);
+            let mut tree = Dictionary::new();
+            tree.insert("Dests", dests);
+            catalog.insert("Names", tree);
+        }
+        let mut dests = Dictionary::new();
+        for (name, dest) in self.old_dests.iter() {
+            if in_range(self.dest_page(dest)) {
+                dests.insert(name.clone(), dest.deep_clone(&mut importer)?);
+            }
+        }
+        if !dests.is_empty() {
+            catalog.insert("Dests", dests);
+        }
+
+        if let Some(labels) = self.catalog.get("PageLabels") {
+            let labels = self.page_labels(labels, range.clone())?.deep_clone(&mut importer)?;
+            catalog.insert("PageLabels", labels);
+        }
+
+        let catalog = importer.create(Primitive::Dictionary(catalog))?.get_ref().get_inner();
+        drop(importer);
+        let mut trailer = Trailer {
+            root: storage.resolver().get(Ref::new(catalog))?,
+            encrypt_dict: None,
+            size: 0,
+            id: vec![],
+            info_dict: None,
+            prev_trailer_pos: None,
+        };
+        storage.save(&mut trailer)?;
+        Ok(storage.into_inner())
+    }
+
+    /// The page that `dest` points to, if it is known.
+    fn dest_page(&self, dest: &Primitive) -> Option<PlainRef> {
+        match *dest {
+            Primitive::Reference(r) => self.dest_page(&self.resolve.resolve(r).ok()?),
+            Primitive::Array(ref parts) => match parts.first()? {
+                &Primitive::Reference(page) => Some(page),
+                _ => None,
+            },
+            Primitive::Dictionary(ref dict) => self.dest_page(dict.get("D")?),
+            Primitive::String(ref name) => {
+                let (_, dest) = self.named_dests.iter().find(|(n, _)| n == name)?;
+                self.dest_page(dest)
+            }
+            Primitive::Name(ref name) => self.dest_page(self.old_dests.get(name)?),
+            _ => None,
+        }
+    }
+
+    /// Copy the outline item `first` and the ones after it that lead to a page in range,
+    /// or have children that do, and return the first and last copy and their number.
+    fn copy_outline_items(&self, first: Option<&Primitive>, parent: PlainRef, in_range: &impl Fn(Option<PlainRef>) -> bool, importer: &mut Importer<&R, impl Updater>) -> Result<(Option<PlainRef>, Option<PlainRef>, i32)> {
+        let mut kept: Vec<(PromisedRef<Primitive>, Dictionary)> = vec![];
+        let mut count = 0;
+        let mut next = first.cloned();
+        let mut seen = HashSet::new();
+        while let Some(Primitive::Reference(r)) = next {
+            if !seen.insert(r) {
+                break;
+            }
+            let mut item = self.resolve.resolve(r)?.into_dictionary()?;
+            next = item.remove("Next");
+
+            let promise = importer.promise::<Primitive>();
+            let (first, last, children) = self.copy_outline_items(item.get("First"), promise.get_inner(), in_range, importer)?;
+            let dest = item.get("Dest").or_else(|| match item.get("A") {
+                Some(Primitive::Dictionary(action)) => action.get("D"),
+                _ => None,
+            });
+            if first.is_none() && !in_range(dest.and_then(|d| self.dest_page(d))) {
+                importer.fulfill(promise, Primitive::Null)?;
+                continue;
+            }
+
+            let open = !matches!(item.get("Count"), Some(&Primitive::Integer(n)) if n < 0);
+            for key in ["Parent", "First", "Last", "Prev", "Count"] {
+                item.remove(key);
+            }
+            let mut item = item.deep_clone(importer)?;
+            item.insert("Parent", Primitive::Reference(parent));
+            if let (Some(first), Some(last)) = (first, last) {
+                item.insert("First", Primitive::Reference(first));
+                item.insert("Last", Primitive::Reference(last));
+                item.insert("Count", Primitive::Integer(if open { children } else { -children }));
+            }
+            count += 1 + if open { children } else { 0 };
+            kept.push((promise, item));
+        }
+
+        let refs: Vec<PlainRef> = kept.iter().map(|(p, _)| p.get_inner()).collect();
+        let (first, last) = (refs.first().copied(), refs.last().copied());
+        for (i, (promise, mut item)) in kept.into_iter().enumerate() {
+            if i > 0 {
+                item.insert("Prev", Primitive::Reference(refs[i - 1]));
+            }
+            if let Some(&next) = refs.get(i + 1) {
+                item.insert("Next", Primitive::Reference(next));
+            }
+            importer.fulfill(promise, Primitive::Dictionary(item))?;
+        }
+        Ok((first, last, count))
+    }
+
+    /// The page labels of the pages in `range`, as a number tree that starts at 0.
+    fn page_labels(&self, labels: &Primitive, range: Range<u32>) -> Result<Primitive> {
+        let mut nums = vec![];
+        let mut current = None;
+        for (key, label) in tree_entries(&self.resolve, labels, "Nums")? {
+            let start = key.as_integer()? as u32;
+            let label = resolve_dict(&self.resolve, &label)?;
+            if start <= range.start {
+                current = Some((start, label));
+            } else if start < range.end {
+                nums.push(Primitive::Integer((start - range.start) as i32));
+                nums.push(Primitive::Dictionary(label));
+            }
+        }
+        if let Some((start, mut label)) = current {
+            // continue the numbering where the range starts
+            let first = match label.get("St") {
+                Some(p) => p.as_integer()?,
+                None => 1,
+            };
+            label.insert("St", Primitive::Integer(first + (range.start - start) as i32));
+            nums.insert(0, Primitive::Dictionary(label));
+            nums.insert(0, Primitive::Integer(0));
+        }
+        let mut tree = Dictionary::new();
+        tree.insert("Nums", nums);
+        Ok(Primitive::Dictionary(tree))
+    }
+}
+
+/// The entries of a name tree (`key = "Names"`) or number tree (`key = "Nums"`).
+fn tree_entries(resolve: &impl Resolve, node: &Primitive, key: &str) -> Result<Vec<(Primitive, Primitive)>> {
+    let mut entries = vec![];
+    let mut queue = vec![node.clone()];
+    let mut seen = HashSet::new();
+    while let Some(node) = queue.pop() {
+        if let Primitive::Reference(r) = node {
+            if !seen.insert(r) {
+                continue;
+            }
+        }
+        let node = resolve_dict(resolve, &node)?;
+        if let Some(pairs) = node.get(key) {
+            let pairs = match pairs {
+                &Primitive::Reference(r) => resolve.resolve(r)?.into_array()?,
+                p => p.clone().into_array()?,
+            };
+            let mut pairs = pairs.into_iter();
+            while let (Some(k), Some(v)) = (pairs.next(), pairs.next()) {
+                entries.push((k, v));
+            }
+        }
+        if let Some(kids) = node.get("Kids") {
+            queue.extend(refs(resolve, Some(kids))?.into_iter().rev().map(Primitive::Reference));
+        }
+    }
+    Ok(entries)
+}
+
+pub(crate) fn resolve_dict(resolve: &impl Resolve, p: &Primitive) -> Result<Dictionary> {
+    match *p {
+        Primitive::Reference(r) => resolve.resolve(r)?.into_dictionary(),
+        ref p => p.clone().into_dictionary(),
+    }
+}
+
+/// The references in the (possibly indirect) array `p`.
+pub(crate) fn refs(resolve: &impl Resolve, p: Option<&Primitive>) -> Result<Vec<PlainRef>> {
+    let array = match p {
+        Some(&Primitive::Reference(r)) => resolve.resolve(r)?.into_array()?,
+        Some(p) => p.clone().into_array()?,
+        None => return Ok(vec![]),
+    };
+    Ok(array.into_iter().filter_map(|p| p.into_reference().ok()).collect())
+}
+
+/// The leaf pages below `root`, with the attributes they inherit.
+pub(crate) fn leaf_pages(resolve: &impl Resolve, root: PlainRef) -> Result<Vec<(PlainRef, Dictionary)>> {
+    const INHERITED: &[&str] = &["Resources", "MediaBox", "CropBox", "Rotate"];
+    let mut pages = vec![];
+    let mut seen = HashSet::new();
+    let mut stack = vec![(root, Dictionary::new())];
+    while let Some((r, inherited)) = stack.pop() {
+        if !seen.insert(r) {
+            continue;
+        }
+        let mut dict = resolve.resolve(r)?.into_dictionary()?;
+        for (key, value) in inherited.iter() {
+            if dict.get(key).is_none() {
+                dict.insert(key.clone(), value.clone());
+            }
+        }
+        match dict.get("Kids") {
+            Some(kids) if !matches!(dict.get("Type"), Some(Primitive::Name(n)) if n == "Page") => {
+                let mut inherited = Dictionary::new();
+                for &key in INHERITED {
+                    if let Some(value) = dict.get(key) {
+                        inherited.insert(key, value.clone());
+                    }
+                }
+                let kids = refs(resolve, Some(kids))?;
+                stack.extend(kids.into_iter().rev().map(|kid| (kid, inherited.clone())));
+            }
+            _ => pages.push((r, dict)),
+        }
+    }
+    Ok(pages)
+}
+
+/// Replace the font names in a `/DA` string.
+fn rename_fonts(da: &PdfString, renamed: &HashMap<String, String>) -> Primitive {
+    if renamed.is_empty() {
+        return Primitive::String(da.clone());
+    }
+    let da = String::from_utf8_lossy(da.as_bytes());
+    let parts: Vec<String> = da.split_ascii_whitespace().map(|part| {
+        match part.strip_prefix('/').and_then(|name| renamed.get(name)) {
+            Some(new) => format!("/{}", new),
+            None => part.to_string(),
+        }
+    }).collect();
+    Primitive::String(PdfString::from(parts.join(" ").as_str()))
+}
+
+/// `prefix` followed by `name`, in the encoding of `name`.
+fn prefixed(name: &PdfString, prefix: &str) -> PdfString {
+    let mut data = vec![];
+    match name.as_bytes().strip_prefix(&[0xfe, 0xff]) {
+        Some(utf16) => {
+            data.extend_from_slice(&[0xfe, 0xff]);
+            data.extend(prefix.encode_utf16().flat_map(|c| c.to_be_bytes()));
+            data.extend_from_slice(utf16);
+        }
+        None => {
+            data.extend_from_slice(prefix.as_bytes());
+            data.extend_from_slice(name.as_bytes());
+        }
+    }
+    PdfString::new(data.as_slice().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_da_fonts() {
+        let renamed = [("Helv".to_string(), "Helv_1".to_string())].into_iter().collect();
+        let da = rename_fonts(&"/Helv 0 Tf /HelvBold 1 Tf 0 g".into(), &renamed);
+        assert_eq!(da, Primitive::String("/Helv_1 0 Tf /HelvBold 1 Tf 0 g".into()));
+    }
+
+    #[test]
+    fn prefix_field_names() {
+        assert_eq!(prefixed(&"name".into(), "doc2_").as_bytes(), b"doc2_name");
+        let utf16 = PdfString::new([0xfe, 0xff, 0, b'n'][..].into());
+        assert_eq!(prefixed(&utf16, "d_").to_string_lossy(), "d_n");
+    }
+}
@@ -408,9 +408,7 @@ impl OpBuilder {
             "sc" | "scn" => {
                 push(Op::FillColor { color: Color::Other(args.collect()) });
             }
-            "sh"  => {
-
-            }
+            "sh"  => push(Op::Shade { name: name(&mut args)? }),
             "T*"  => push(Op::TextNewline),
             "Tc"  => push(Op::CharSpacing { char_space: number(&mut args)? }),
             "Td"  => push(Op::MoveTextPosition { translation: point(&mut args)? }),
@@ -902,6 +900,35 @@ impl Default for Matrix {
         }
     }
 }
+impl Matrix {
+    pub fn translate(x: f32, y: f32) -> Matrix {
+        Matrix { e: x, f: y, ..Matrix::default() }
+    }
+    pub fn scale(x: f32, y: f32) -> Matrix {
+        Matrix { a: x, d: y, ..Matrix::default() }
+    }
+    /// Map the point `p` from the space of this matrix to the outer space
+    pub fn transform(&self, p: Point) -> Point {
+        Point {
+            x: self.a * p.x + self.c * p.y + self.e,
+            y: self.b * p.x + self.d * p.y + self.f,
+        }
+    }
+}
+/// `a * b` first applies `a`, then `b`, so `cm` sets the CTM to `matrix * ctm`.
+impl std::ops::Mul for Matrix {
+    type Output = Matrix;
+    fn mul(self, m: Matrix) -> Matrix {
+        Matrix {
+            a: self.a * m.a + self.b * m.c,
+            b: self.a * m.b + self.b * m.d,
+            c: self.c * m.a + self.d * m.c,
+            d: self.c * m.b + self.d * m.d,
+            e: self.e * m.a + self.f * m.c + m.e,
+            f: self.e * m.b + self.f * m.d + m.f,
+        }
+    }
+}
 impl Object for Matrix {
     fn from_primitive(p: Primitive, _resolve: &impl Resolve) -> Result<Self> {
         matrix(&mut p.into_array()?.into_iter())
@@ -932,7 +959,7 @@ impl From<euclid::Transform2D<f32, PdfSpace, PdfSpace>> for Matrix {
     }
 }
 
-#[derive(Debug, Clone, DataSize)]
+#[derive(Debug, Clone, PartialEq, DataSize)]
 pub enum Color {
     Gray(f32),
     Rgb(Rgb),
@@ -9,11 +9,18 @@ use std::fmt;
 use std::collections::HashMap;
 use datasize::DataSize;
 use crate::object::PlainRef;
-use crate::primitive::{Dictionary, PdfString, Name};
+use crate::primitive::{Dictionary, PdfString, Name, Primitive};
 use crate::error::{PdfError, Result};
+use bitflags::bitflags;
+
+#[cfg(feature = "pubsec")]
+mod pubsec;
+#[cfg(feature = "pubsec")]
+pub use self::pubsec::Recipient;
 
 type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
 type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
 type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
 
 const PADDING: [u8; 32] = [
@@ -61,20 +68,31 @@ impl Rc4 {
 }
 
 /// 7.6.1 Table 20 + 7.6.3.2 Table 21
-#[derive(Object, Debug, Clone, DataSize)]
+#[derive(Object, ObjectWrite, Debug, Clone, DataSize)]
 pub struct CryptDict {
-    #[pdf(key="O")]
+    #[pdf(key="Filter", default="Name::from(\"Standard\")")]
+    filter: Name,
+
+    #[pdf(key="SubFilter")]
+    sub_filter: Option<Name>,
+
+    // the entries below up to /P are only used by the standard security handler
+    #[pdf(key="O", default="PdfString::from(\"\")")]
     o: PdfString,
 
-    #[pdf(key="U")]
+    #[pdf(key="U", default="PdfString::from(\"\")")]
     u: PdfString,
 
-    #[pdf(key="R")]
+    #[pdf(key="R", default="0")]
     r: u32,
 
-    #[pdf(key="P")]
+    #[pdf(key="P", default="-1")]
     p: i32,
 
+    /// 7.6.5.2 Table 24, for the public-key security handler
+    #[pdf(key="Recipients")]
+    recipients: Option<Vec<PdfString>>,
+
     #[pdf(key="V")]
     v: i32,
 
@@ -100,7 +118,227 @@ pub struct CryptDict {
     _other: Dictionary
 }
 
-#[derive(Object, Debug, Clone, Copy, DataSize)]
+impl CryptDict {
+    /// The access permissions granted by the `/P` entry.
+    pub fn permissions(&self) -> Permissions {
+        Permissions::from_p(self.p)
+    }
+    /// The name of the security handler, `Standard` or `Adobe.PubSec`.
+    pub fn filter(&self) -> &Name {
+        &self.filter
+    }
+}
+
+bitflags! {
+    /// User access permissions of an encrypted document (7.6.3.2 Table 22).
+    ///
+    /// Each flag corresponds to a bit of the `/P` entry in the encryption dictionary.
+    #[repr(transparent)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Permissions: u32 {
+        /// Print the document (bit 3).
+        const PRINT = 1 << 2;
+        /// Modify the contents of the document (bit 4).
+        const MODIFY = 1 << 3;
+        /// Copy or otherwise extract text and graphics (bit 5).
+        const EXTRACT = 1 << 4;
+        /// Add or modify annotations and fill in form fields (bit 6).
+        const ANNOTATE = 1 << 5;
+        /// Fill in existing form fields, even if `ANNOTATE` is clear (bit 9).
+        const FILL_FORMS = 1 << 8;
+        /// Extract text and graphics for accessibility purposes (bit 10).
+        const EXTRACT_ACCESSIBILITY = 1 << 9;
+        /// Insert, rotate or delete pages and create bookmarks (bit 11).
+        const ASSEMBLE = 1 << 10;
+        /// Print at full quality (bit 12).
+        const PRINT_HIGH_QUALITY = 1 << 11;
+    }
+}
+impl Permissions {
+    /// Decode the value of the `/P` entry.
+    pub fn from_p(p: i32) -> Permissions {
+        Permissions::from_bits_truncate(p as u32)
+    }
+    /// Encode as a `/P` value. Reserved bits are set as required by the specification.
+    pub fn to_p(self) -> i32 {
+        (self.bits() | 0xFFFF_F0C0) as i32
+    }
+    /// Whether form fields may be filled in.
+    pub fn can_fill_forms(self) -> bool {
+        self.intersects(Permissions::FILL_FORMS | Permissions::ANNOTATE)
+    }
+}
+
+/// Standard security handler revisions that can be used to encrypt a document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityRevision {
+    /// RC4 with a 40 bit key (revision 2)
+    Rc4_40,
+    /// RC4 with a 128 bit key (revision 3)
+    Rc4_128,
+    /// AES with a 128 bit key (revision 4)
+    Aes128,
+    /// AES with a 256 bit key (revision 6)
+    Aes256,
+}
+
+/// Settings for encrypting a document with the standard security handler.
+#[derive(Clone)]
+pub struct Encryption {
+    user_password: Vec<u8>,
+    owner_password: Vec<u8>,
+    revision: SecurityRevision,
+    permissions: Permissions,
+}
+impl Encryption {
+    /// AES-256 encryption granting all permissions.
+    /// An empty owner password means the user password is used for both.
+    pub fn new(user_password: &[u8], owner_password: &[u8]) -> Encryption {
+        Encryption {
+            user_password: user_password.to_vec(),
+            owner_password: owner_password.to_vec(),
+            revision: SecurityRevision::Aes256,
+            permissions: Permissions::all(),
+        }
+    }
+    pub fn revision(self, revision: SecurityRevision) -> Encryption {
+        Encryption { revision, ..self }
+    }
+    pub fn permissions(self, permissions: Permissions) -> Encryption {
+        Encryption { permissions, ..self }
+    }
+    pub fn get_revision(&self) -> SecurityRevision {
+        self.revision
+    }
+
+    /// Create the /Encrypt dictionary and the matching decoder.
+    /// `id` is the first entry of the trailer /ID.
+    pub fn build(&self, id: &[u8]) -> Result<(CryptDict, Decoder)> {
+        let owner_password = match self.owner_password.len() {
+            0 => &self.user_password,
+            _ => &self.owner_password,
+        };
+        let std_cf = |method, length| {
+            let mut filters = HashMap::new();
+            filters.insert(Name::from("StdCF"), CryptFilter {
+                method,
+                auth_event: AuthEvent::DocOpen,
+                length: Some(length),
+                recipients: None,
+                encrypt_metadata: true,
+                _other: Dictionary::new(),
+            });
+            filters
+        };
+        let mut other = Dictionary::new();
+
+        let (r, v, key_size, method) = match self.revision {
+            SecurityRevision::Rc4_40 => (2, 1, 5, CryptMethod::V2),
+            SecurityRevision::Rc4_128 => (3, 2, 16, CryptMethod::V2),
+            SecurityRevision::Aes128 => (4, 4, 16, CryptMethod::AESV2),
+            SecurityRevision::Aes256 => {
+                let user = prepare_password_rev_6(&self.user_password)?;
+                let owner = prepare_password_rev_6(owner_password)?;
+
+                // Algorithm 8 and 9
+                let mut file_key = [0; 32];
+                let mut salts = [0; 32];
+                fill_random(&mut file_key)?;
+                fill_random(&mut salts)?;
+                let (user_validation_salt, user_key_salt) = (&salts[0..8], &salts[8..16]);
+                let (owner_validation_salt, owner_key_salt) = (&salts[16..24], &salts[24..32]);
+
+                let mut u = Decoder::revision_6_kdf(&user, user_validation_salt, b"").to_vec();
+                u.extend_from_slice(user_validation_salt);
+                u.extend_from_slice(user_key_salt);
+                let ue = aes256_wrap(&Decoder::revision_6_kdf(&user, user_key_salt, b""), &file_key);
+
+                let mut o = Decoder::revision_6_kdf(&owner, owner_validation_salt, &u).to_vec();
+                o.extend_from_slice(owner_validation_salt);
+                o.extend_from_slice(owner_key_salt);
+                let oe = aes256_wrap(&Decoder::revision_6_kdf(&owner, owner_key_salt, &u), &file_key);
+
+                // Algorithm 10
+                let mut perms = [0; 16];
+                perms[..4].copy_from_slice(&self.permissions.to_p().to_le_bytes());
+                perms[4..8].copy_from_slice(&[0xff; 4]);
+                perms[8..12].copy_from_slice(b"Tadb");
+                fill_random(&mut perms[12..])?;
+                other.insert("Perms", PdfString::new(aes256_wrap(&file_key, &perms).into()));
+                other.insert("StrF", Name::from("StdCF"));
+
+                let dict = CryptDict {
+                    filter: Name::from("Standard"),
+                    sub_filter: None,
+                    recipients: None,
+                    o: PdfString::new(o.into()),
+                    u: PdfString::new(u.into()),
+                    r: 6,
+                    p: self.permissions.to_p(),
+                    v: 5,
+                    bits: 256,
+                    crypt_filters: std_cf(CryptMethod::AESV3, 32),
+                    default_crypt_filter: Some(Name::from("StdCF")),
+                    encrypt_metadata: true,
+                    oe: Some(PdfString::new(oe.into())),
+                    ue: Some(PdfString::new(ue.into())),
+                    _other: other,
+                };
+                return Ok((dict, Decoder { owner: true, ..Decoder::new(file_key.to_vec(), 32, CryptMethod::AESV3, true) }));
+            }
+        };
+
+        // Algorithm 3
+        let owner_key = key_derivation_owner_password_rc4(r, key_size, owner_password)?;
+        let mut o = pad_password(&self.user_password).to_vec();
+        Rc4::encrypt(&owner_key, &mut o);
+        if r >= 3 {
+            for i in 1u8..=19 {
+                let key: Vec<u8> = owner_key.iter().map(|b| b ^ i).collect();
+                Rc4::encrypt(&key, &mut o);
+            }
+        }
+
+        let (crypt_filters, default_crypt_filter) = if v == 4 {
+            other.insert("StrF", Name::from("StdCF"));
+            (std_cf(method, 16), Some(Name::from("StdCF")))
+        } else {
+            (HashMap::new(), None)
+        };
+        let mut dict = CryptDict {
+            filter: Name::from("Standard"),
+            sub_filter: None,
+            recipients: None,
+            o: PdfString::new(o.into()),
+            u: PdfString::new(Vec::new().into()),
+            r,
+            p: self.permissions.to_p(),
+            v,
+            bits: 8 * key_size as u32,
+            crypt_filters,
+            default_crypt_filter,
+            encrypt_metadata: true,
+            oe: None,
+            ue: None,
+            _other: other,
+        };
+
+        // Algorithm 4 and 5
+        let key = key_derivation_user_password_rc4(r, key_size, &dict, id, &self.user_password);
+        let u = if r == 2 {
+            compute_u_rev_2(&key[..key_size])
+        } else {
+            let mut u = compute_u_rev_3_4(id, &key[..key_size]).to_vec();
+            u.resize(32, 0);
+            u
+        };
+        dict.u = PdfString::new(u.into());
+
+        Ok((dict, Decoder { owner: true, ..Decoder::new(key, key_size, method, true) }))
+    }
+}
+
+#[derive(Object, ObjectWrite, Debug, Clone, Copy, DataSize)]
 pub enum CryptMethod {
     None,
     V2,
@@ -108,13 +346,13 @@ pub enum CryptMethod {
     AESV3,
 }
 
-#[derive(Object, Debug, Clone, Copy, DataSize)]
+#[derive(Object, ObjectWrite, Debug, Clone, Copy, DataSize)]
 pub enum AuthEvent {
     DocOpen,
     EFOpen
 }
 
-#[derive(Object, Debug, Clone, DataSize)]
+#[derive(Object, ObjectWrite, Debug, Clone, DataSize)]
 #[pdf(Type="CryptFilter?")]
 pub struct CryptFilter {
     #[pdf(key="CFM", default="CryptMethod::None")]
@@ -126,10 +364,18 @@ pub struct CryptFilter {
     #[pdf(key="Length")]
     pub length: Option<u32>,
 
+    /// Only used by the public-key security handler
+    #[pdf(key="Recipients")]
+    pub recipients: Option<Vec<PdfString>>,
+
+    #[pdf(key="EncryptMetadata", default="true")]
+    pub encrypt_metadata: bool,
+
     #[pdf(other)]
     _other: Dictionary
 }
 
+#[derive(Clone)]
 pub struct Decoder {
     key_size: usize,
     key: Vec<u8>, // maximum length
@@ -146,155 +392,185 @@ pub struct Decoder {
     /// Whether the metadata is encrypted, as indicated by /EncryptMetadata
     /// in the /Encrypt dictionary.
     encrypt_metadata: bool,
+    /// The permissions of a public-key recipient, which are not part of the /Encrypt dictionary.
+    permissions: Option<Permissions>,
+    /// Whether the document was opened with the owner password, or by a recipient with all permissions.
+    pub(crate) owner: bool,
 }
-impl Decoder {
-    pub fn default(dict: &CryptDict, id: &[u8]) -> Result<Decoder> {
-        Decoder::from_password(dict, id, b"")
-    }
 
-    fn key(&self) -> &[u8] {
-        &self.key[.. std::cmp::min(self.key_size, 16)]
-    }
+fn compute_u_rev_2(key: &[u8]) -> Vec<u8> {
+    // algorithm 4
+    let mut data = PADDING.to_vec();
+    Rc4::encrypt(key, &mut data);
+    data
+}
 
-    pub fn new(key: Vec<u8>, key_size: usize, method: CryptMethod, encrypt_metadata: bool) -> Decoder {
-        Decoder {
-            key_size,
-            key,
-            method,
-            encrypt_indirect_object: None,
-            metadata_indirect_object: None,
-            encrypt_metadata,
+fn check_password_rev_2(document_u: &[u8], key: &[u8]) -> bool {
+    compute_u_rev_2(key) == document_u
+}
+
+fn compute_u_rev_3_4(id: &[u8], key: &[u8]) -> [u8; 16] {
+    // algorithm 5
+    // a) we derived the key already.
+
+    // b)
+    let mut hash = md5::Context::new();
+    hash.consume(PADDING);
+
+    // c)
+    hash.consume(id);
+
+    // d)
+    let mut data = *hash.compute();
+    Rc4::encrypt(key, &mut data);
+
+    // e)
+    for i in 1u8..=19 {
+        let mut key = key.to_owned();
+        for b in &mut key {
+            *b ^= i;
         }
+        Rc4::encrypt(&key, &mut data);
     }
 
-    pub fn from_password(dict: &CryptDict, id: &[u8], pass: &[u8]) -> Result<Decoder> {
-        fn compute_u_rev_2(key: &[u8]) -> Vec<u8> {
-            // algorithm 4
-            let mut data = PADDING.to_vec();
-            Rc4::encrypt(key, &mut data);
-            data
-        }
+    // f)
+    data
+}
 
-        fn check_password_rev_2(document_u: &[u8], key: &[u8]) -> bool {
-            compute_u_rev_2(key) == document_u
-        }
+fn check_password_rev_3_4(document_u: &[u8], id: &[u8], key: &[u8]) -> bool {
+    document_u.starts_with(&compute_u_rev_3_4(id, key))
+}
 
-        fn compute_u_rev_3_4(id: &[u8], key: &[u8]) -> [u8; 16] {
-            // algorithm 5
-            // a) we derived the key already.
+fn check_password_rc4(revision: u32, document_u: &[u8], id: &[u8], key: &[u8]) -> bool {
+    if revision == 2 {
+        check_password_rev_2(document_u, key)
+    } else {
+        check_password_rev_3_4(document_u, id, key)
+    }
+}
 
-            // b)
-            let mut hash = md5::Context::new();
-            hash.consume(PADDING);
+fn key_derivation_user_password_rc4(
+    revision: u32,
+    key_size: usize,
+    dict: &CryptDict,
+    id: &[u8],
+    pass: &[u8],
+) -> Vec<u8> {
+    let o = dict.o.as_bytes();
+    let p = dict.p;
+    // 7.6.3.3 - Algorithm 2
+    // a) and b)
+    let mut hash = md5::Context::new();
+    if pass.len() < 32 {
+        hash.consume(pass);
+        hash.consume(&PADDING[..32 - pass.len()]);
+    } else {
+        hash.consume(&pass[..32]);
+    }
 
-            // c)
-            hash.consume(id);
+    // c)
+    hash.consume(o);
 
-            // d)
-            let mut data = *hash.compute();
-            Rc4::encrypt(key, &mut data);
+    // d)
+    hash.consume(p.to_le_bytes());
 
-            // e)
-            for i in 1u8..=19 {
-                let mut key = key.to_owned();
-                for b in &mut key {
-                    *b ^= i;
-                }
-                Rc4::encrypt(&key, &mut data);
-            }
+    // e)
+    hash.consume(id);
 
-            // f)
-            data
-        }
+    // f)
+    if revision >= 4 && !dict.encrypt_metadata {
+        hash.consume([0xff, 0xff, 0xff, 0xff]);
+    }
 
-        fn check_password_rev_3_4(document_u: &[u8], id: &[u8], key: &[u8]) -> bool {
-            document_u.starts_with(&compute_u_rev_3_4(id, key))
-        }
+    // g)
+    let mut data = *hash.compute();
 
-        fn check_password_rc4(revision: u32, document_u: &[u8], id: &[u8], key: &[u8]) -> bool {
-            if revision == 2 {
-                check_password_rev_2(document_u, key)
-            } else {
-                check_password_rev_3_4(document_u, id, key)
-            }
+    // h)
+    if revision >= 3 {
+        for _ in 0..50 {
+            data = *md5::compute(&data[..std::cmp::min(key_size, 16)]);
         }
+    }
 
-        fn key_derivation_user_password_rc4(
-            revision: u32,
-            key_size: usize,
-            dict: &CryptDict,
-            id: &[u8],
-            pass: &[u8],
-        ) -> Vec<u8> {
-            let o = dict.o.as_bytes();
-            let p = dict.p;
-            // 7.6.3.3 - Algorithm 2
-            // a) and b)
-            let mut hash = md5::Context::new();
-            if pass.len() < 32 {
-                hash.consume(pass);
-                hash.consume(&PADDING[..32 - pass.len()]);
-            } else {
-                hash.consume(&pass[..32]);
-            }
+    let mut key = vec![0u8; key_size.max(16)];
+    key[..16].copy_from_slice(&data);
+    key
+}
 
-            // c)
-            hash.consume(o);
+fn key_derivation_owner_password_rc4(
+    revision: u32,
+    key_size: usize,
+    pass: &[u8],
+) -> Result<Vec<u8>> {
+    if key_size > 16 {
+        bail!("key size > 16");
+    }
 
-            // d)
-            hash.consume(p.to_le_bytes());
+    let mut hash = md5::Context::new();
+    if pass.len() < 32 {
+        hash.consume(pass);
+        hash.consume(&PADDING[..32 - pass.len()]);
+    } else {
+        hash.consume(&pass[..32]);
+    }
 
-            // e)
-            hash.consume(id);
+    if revision >= 3 {
+        for _ in 0..50 {
+            let digest = *std::mem::replace(&mut hash, md5::Context::new()).compute();
+            hash.consume(digest);
+        }
+    }
 
-            // f)
-            if revision >= 4 && !dict.encrypt_metadata {
-                hash.consume([0xff, 0xff, 0xff, 0xff]);
-            }
+    let digest = &hash.compute()[..key_size];
+    Ok(digest.to_vec())
+}
 
-            // g)
-            let mut data = *hash.compute();
+impl Decoder {
+    pub fn default(dict: &CryptDict, id: &[u8]) -> Result<Decoder> {
+        Decoder::from_password(dict, id, b"")
+    }
 
-            // h)
-            if revision >= 3 {
-                for _ in 0..50 {
-                    data = *md5::compute(&data[..std::cmp::min(key_size, 16)]);
-                }
-            }
+    /// The permissions granted to a public-key recipient.
+    pub fn permissions(&self) -> Option<Permissions> {
+        self.permissions
+    }
+    /// Whether the owner password was given, which allows removing or changing the encryption.
+    pub fn is_owner(&self) -> bool {
+        self.owner
+    }
 
-            let mut key = vec![0u8; key_size.max(16)];
-            key[..16].copy_from_slice(&data);
-            key
+    /// The lowest header version that allows this encryption:
+    /// AES-256 needs 2.0, AES 1.6 and RC4 with keys longer than 40 bits 1.4.
+    pub(crate) fn min_version(&self) -> (u8, u8) {
+        match self.method {
+            CryptMethod::AESV3 => (2, 0),
+            CryptMethod::AESV2 => (1, 6),
+            CryptMethod::V2 if self.key_size > 5 => (1, 4),
+            _ => (1, 1),
         }
+    }
 
-        fn key_derivation_owner_password_rc4(
-            revision: u32,
-            key_size: usize,
-            pass: &[u8],
-        ) -> Result<Vec<u8>> {
-            if key_size > 16 {
-                bail!("key size > 16");
-            }
-
-            let mut hash = md5::Context::new();
-            if pass.len() < 32 {
-                hash.consume(pass);
-                hash.consume(&PADDING[..32 - pass.len()]);
-            } else {
-                hash.consume(&pass[..32]);
-            }
-
-            if revision >= 3 {
-                for _ in 0..50 {
-                    let digest = *std::mem::replace(&mut hash, md5::Context::new()).compute();
-                    hash.consume(digest);
-                }
-            }
+    fn key(&self) -> &[u8] {
+        match self.method {
+            CryptMethod::AESV3 => &self.key[.. std::cmp::min(self.key_size, self.key.len())],
+            _ => &self.key[.. std::cmp::min(self.key_size, 16)],
+        }
+    }
 
-            let digest = &hash.compute()[..key_size];
-            Ok(digest.to_vec())
+    pub fn new(key: Vec<u8>, key_size: usize, method: CryptMethod, encrypt_metadata: bool) -> Decoder {
+        Decoder {
+            key_size,
+            key,
+            method,
+            encrypt_indirect_object: None,
+            metadata_indirect_object: None,
+            encrypt_metadata,
+            permissions: None,
+            owner: false,
         }
+    }
 
+    pub fn from_password(dict: &CryptDict, id: &[u8], pass: &[u8]) -> Result<Decoder> {
         let (key_bits, method) = match dict.v {
             1 => (40, CryptMethod::V2),
             2 => {
@@ -330,33 +606,34 @@ impl Decoder {
         };
         if level <= 4 {
             let key_size = key_bits as usize / 8;
-            let key = key_derivation_user_password_rc4(level, key_size, dict, id, pass);
-
-            if check_password_rc4(level, dict.u.as_bytes(), id, &key[..std::cmp::min(key_size, 16)]) {
-                let decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata);
+            // the owner password unwraps the user password from /O
+            let password_wrap_key = key_derivation_owner_password_rc4(level, key_size, pass)?;
+            let mut data = dict.o.as_bytes().to_vec();
+            let rounds = if level == 2 { 1u8 } else { 20u8 };
+            for round in 0..rounds {
+                let mut round_key = password_wrap_key.clone();
+                for byte in round_key.iter_mut() {
+                    *byte ^= round;
+                }
+                Rc4::encrypt(&round_key, &mut data);
+            }
+            let unwrapped_user_password = data;
+
+            let key = key_derivation_user_password_rc4(
+                level,
+                key_size,
+                dict,
+                id,
+                &unwrapped_user_password,
+            );
+
+            if check_password_rc4(level, dict.u.as_bytes(), id, &key[..key_size]) {
+                let mut decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata);
+                decoder.owner = true;
                 Ok(decoder)
             } else {
-                let password_wrap_key = key_derivation_owner_password_rc4(level, key_size, pass)?;
-                let mut data = dict.o.as_bytes().to_vec();
-                let rounds = if level == 2 { 1u8 } else { 20u8 };
-                for round in 0..rounds {
-                    let mut round_key = password_wrap_key.clone();
-                    for byte in round_key.iter_mut() {
-                        *byte ^= round;
-                    }
-                    Rc4::encrypt(&round_key, &mut data);
-                }
-                let unwrapped_user_password = data;
-
-                let key = key_derivation_user_password_rc4(
-                    level,
-                    key_size,
-                    dict,
-                    id,
-                    &unwrapped_user_password,
-                );
-
-                if check_password_rc4(level, dict.u.as_bytes(), id, &key[..key_size]) {
+                let key = key_derivation_user_password_rc4(level, key_size, dict, id, pass);
+                if check_password_rc4(level, dict.u.as_bytes(), id, &key[..std::cmp::min(key_size, 16)]) {
                     let decoder = Decoder::new(key, key_size, method, dict.encrypt_metadata);
                     Ok(decoder)
                 } else {
@@ -411,21 +688,24 @@ impl Decoder {
             .as_bytes()
             .to_vec();
 
-            let (intermediate_key, mut wrapped_key) = if level == 6 {
-                let user_hash_computed =
-                    Self::revision_6_kdf(password_encoded, user_validation_salt, b"");
-                if user_hash_computed == user_hash {
+            // the owner password is checked first, so a password that is both counts as the owner's
+            let (intermediate_key, mut wrapped_key, owner) = if level == 6 {
+                let owner_hash_computed =
+                    Self::revision_6_kdf(password_encoded, owner_validation_salt, u);
+                if owner_hash_computed == owner_hash {
                     (
-                        Self::revision_6_kdf(password_encoded, user_key_salt, b"").into(),
-                        ue,
+                        Self::revision_6_kdf(password_encoded, owner_key_salt, u).into(),
+                        oe,
+                        true,
                     )
                 } else {
-                    let owner_hash_computed =
-                        Self::revision_6_kdf(password_encoded, owner_validation_salt, u);
-                    if owner_hash_computed == owner_hash {
+                    let user_hash_computed =
+                        Self::revision_6_kdf(password_encoded, user_validation_salt, b"");
+                    if user_hash_computed == user_hash {
                         (
-                            Self::revision_6_kdf(password_encoded, owner_key_salt, u).into(),
-                            oe,
+                            Self::revision_6_kdf(password_encoded, user_key_salt, b"").into(),
+                            ue,
+                            false,
                         )
                     } else {
                         err!(PdfError::InvalidPassword);
@@ -434,28 +714,28 @@ impl Decoder {
             } else {
                 // level == 5
 
-                let mut user_check_hash = Sha256::new();
-                user_check_hash.update(password_encoded);
-                user_check_hash.update(user_validation_salt);
-                let user_hash_computed = user_check_hash.finalize();
+                let mut owner_check_hash = Sha256::new();
+                owner_check_hash.update(password_encoded);
+                owner_check_hash.update(owner_validation_salt);
+                owner_check_hash.update(u);
+                let owner_hash_computed = owner_check_hash.finalize();
                 #[allow(clippy::branches_sharing_code)]
-                if user_hash_computed.as_slice() == user_hash {
+                if owner_hash_computed.as_slice() == owner_hash {
                     let mut intermediate_kdf_hash = Sha256::new();
                     intermediate_kdf_hash.update(password_encoded);
-                    intermediate_kdf_hash.update(user_key_salt);
-                    (intermediate_kdf_hash.finalize(), ue)
+                    intermediate_kdf_hash.update(owner_key_salt);
+                    intermediate_kdf_hash.update(u);
+                    (intermediate_kdf_hash.finalize(), oe, true)
                 } else {
-                    let mut owner_check_hash = Sha256::new();
-                    owner_check_hash.update(password_encoded);
-                    owner_check_hash.update(owner_validation_salt);
-                    owner_check_hash.update(u);
-                    let owner_hash_computed = owner_check_hash.finalize();
-                    if owner_hash_computed.as_slice() == owner_hash {
+                    let mut user_check_hash = Sha256::new();
+                    user_check_hash.update(password_encoded);
+                    user_check_hash.update(user_validation_salt);
+                    let user_hash_computed = user_check_hash.finalize();
+                    if user_hash_computed.as_slice() == user_hash {
                         let mut intermediate_kdf_hash = Sha256::new();
                         intermediate_kdf_hash.update(password_encoded);
-                        intermediate_kdf_hash.update(owner_key_salt);
-                        intermediate_kdf_hash.update(u);
-                        (intermediate_kdf_hash.finalize(), oe)
+                        intermediate_kdf_hash.update(user_key_salt);
+                        (intermediate_kdf_hash.finalize(), ue, false)
                     } else {
                         err!(PdfError::InvalidPassword);
                     }
@@ -467,7 +747,8 @@ impl Decoder {
                 .decrypt_padded_mut::<NoPadding>(&mut wrapped_key)
                 .map_err(|_| PdfError::InvalidPassword));
 
-            let decoder = Decoder::new(key_slice.into(),  32, method, dict.encrypt_metadata);
+            let mut decoder = Decoder::new(key_slice.into(),  32, method, dict.encrypt_metadata);
+            decoder.owner = owner;
             Ok(decoder)
         } else {
             err!(format!("unsupported V value {}", level).into())
@@ -538,19 +819,33 @@ impl Decoder {
         hash
     }
 
-    pub fn decrypt<'buf>(&self, id: PlainRef, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
-        if self.encrypt_indirect_object == Some(id) {
-            // Strings inside the /Encrypt dictionary are not encrypted
-            return Ok(data);
-        }
+    /// Whether strings and streams of object `id` are stored in plain text.
+    fn is_exempt(&self, id: PlainRef) -> bool {
+        // Strings inside the /Encrypt dictionary are not encrypted
+        self.encrypt_indirect_object == Some(id)
+            // Strings inside the /Metadata dictionary are not encrypted when /EncryptMetadata is false
+            || (!self.encrypt_metadata && self.metadata_indirect_object == Some(id))
+    }
 
-        if !self.encrypt_metadata && self.metadata_indirect_object == Some(id) {
-            // Strings inside the /Metadata dictionary are not encrypted when /EncryptMetadata is
-            // false
-            return Ok(data);
-        }
+    /// Algorithm 1 b) and c): the key used for the object `id`.
+    fn object_key(&self, id: PlainRef) -> ([u8; 16], usize) {
+        let aes = matches!(self.method, CryptMethod::AESV2);
+        let mut key = [0; 16 + 5 + 4];
+        let n = self.key().len();
+        key[..n].copy_from_slice(self.key());
+        key[n..n + 3].copy_from_slice(&id.id.to_le_bytes()[..3]);
+        key[n + 3..n + 5].copy_from_slice(&id.gen.to_le_bytes()[..2]);
+        let len = if aes {
+            key[n + 5..n + 9].copy_from_slice(b"sAlT");
+            n + 9
+        } else {
+            n + 5
+        };
+        (*md5::compute(&key[..len]), (n + 5).min(16))
+    }
 
-        if data.is_empty() {
+    pub fn decrypt<'buf>(&self, id: PlainRef, data: &'buf mut [u8]) -> Result<&'buf [u8]> {
+        if self.is_exempt(id) || data.is_empty() {
             return Ok(data);
         }
 
@@ -560,34 +855,19 @@ impl Decoder {
         match self.method {
             CryptMethod::None => unreachable!(),
             CryptMethod::V2 => {
-                // b)
-                let mut key = [0; 16 + 5];
-                let n = self.key().len();
-                key[..n].copy_from_slice(self.key());
-                key[n..n + 3].copy_from_slice(&id.id.to_le_bytes()[..3]);
-                key[n + 3..n + 5].copy_from_slice(&id.gen.to_le_bytes()[..2]);
-
-                // c)
-                let key = *md5::compute(&key[..n + 5]);
+                // b) and c)
+                let (key, n) = self.object_key(id);
 
                 // d)
-                Rc4::encrypt(&key[..(n + 5).min(16)], data);
+                Rc4::encrypt(&key[..n], data);
                 Ok(data)
             }
             CryptMethod::AESV2 => {
-                // b)
-                let mut key = [0; 32 + 5 + 4];
-                let n = std::cmp::min(self.key_size, 16);
-                key[..n].copy_from_slice(self.key());
-                key[n..n + 3].copy_from_slice(&id.id.to_le_bytes()[..3]);
-                key[n + 3..n + 5].copy_from_slice(&id.gen.to_le_bytes()[..2]);
-                key[n + 5..n + 9].copy_from_slice(b"sAlT");
-
-                // c)
-                let key = *md5::compute(&key[..n + 9]);
+                // b) and c)
+                let (key, n) = self.object_key(id);
 
                 // d)
-                let key = &key[..(n + 5).min(16)];
+                let key = &key[..n];
                 if data.len() < 16 {
                     return Err(PdfError::DecryptionFailure);
                 }
@@ -611,6 +891,82 @@ impl Decoder {
             }
         }
     }
+
+    /// The inverse of `decrypt`. AES ciphertexts are prefixed with a random IV.
+    pub fn encrypt(&self, id: PlainRef, data: &[u8]) -> Result<Vec<u8>> {
+        if self.is_exempt(id) || data.is_empty() {
+            return Ok(data.to_vec());
+        }
+
+        match self.method {
+            CryptMethod::None => Ok(data.to_vec()),
+            CryptMethod::V2 => {
+                let (key, n) = self.object_key(id);
+                let mut out = data.to_vec();
+                Rc4::encrypt(&key[..n], &mut out);
+                Ok(out)
+            }
+            CryptMethod::AESV2 => {
+                let (key, n) = self.object_key(id);
+                let iv = random_iv()?;
+                let cipher = t!(Aes128CbcEnc::new_from_slices(&key[..n], &iv).map_err(|_| PdfError::DecryptionFailure));
+                Ok(aes_encrypt(cipher, iv, data))
+            }
+            CryptMethod::AESV3 => {
+                let iv = random_iv()?;
+                let cipher = t!(Aes256CbcEnc::new_from_slices(self.key(), &iv).map_err(|_| PdfError::DecryptionFailure));
+                Ok(aes_encrypt(cipher, iv, data))
+            }
+        }
+    }
+}
+
+fn random_iv() -> Result<[u8; 16]> {
+    let mut iv = [0; 16];
+    fill_random(&mut iv)?;
+    Ok(iv)
+}
+
+fn fill_random(buf: &mut [u8]) -> Result<()> {
+    getrandom::getrandom(buf).map_err(|e| other!("can't generate random bytes: {}", e))
+}
+
+/// The first 32 bytes of the password, filled up with the padding string.
+fn pad_password(pass: &[u8]) -> [u8; 32] {
+    let mut padded = PADDING;
+    let n = pass.len().min(32);
+    padded[..n].copy_from_slice(&pass[..n]);
+    padded[n..].copy_from_slice(&PADDING[..32 - n]);
+    padded
+}
+
+/// SASLprep the password and truncate it to 127 bytes, as revision 6 requires.
+fn prepare_password_rev_6(pass: &[u8]) -> Result<Vec<u8>> {
+    let unicode = t!(String::from_utf8(pass.to_vec()).map_err(|_| PdfError::InvalidPassword));
+    let prepped = t!(stringprep::saslprep(&unicode).map_err(|_| PdfError::InvalidPassword));
+    let mut bytes = prepped.as_bytes().to_vec();
+    bytes.truncate(127);
+    Ok(bytes)
+}
+
+/// AES-256 without IV and padding, used to wrap the file key and /Perms.
+fn aes256_wrap(key: &[u8; 32], data: &[u8]) -> Vec<u8> {
+    let mut out = data.to_vec();
+    // the key has the right size and the data is a multiple of the block size
+    Aes256CbcEnc::new_from_slices(key, &[0; 16]).unwrap()
+        .encrypt_padded_mut::<NoPadding>(&mut out, data.len()).unwrap();
+    out
+}
+
+fn aes_encrypt(cipher: impl BlockEncryptMut, iv: [u8; 16], data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(16 + data.len() + 16);
+    out.extend_from_slice(&iv);
+    out.extend_from_slice(data);
+    out.resize(16 + (data.len() / 16 + 1) * 16, 0);
+    // the buffer always has room for the padding block
+    let len = cipher.encrypt_padded_mut::<Pkcs7>(&mut out[16..], data.len()).unwrap().len();
+    out.truncate(16 + len);
+    out
 }
 impl fmt::Debug for Decoder {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -0,0 +1,224 @@
+//! Public-key security handler (`/Filter /Adobe.PubSec`), 7.6.5
+//!
+//! Each entry of `/Recipients` is a CMS EnvelopedData, addressed to one or more certificates.
+//! It contains a 20 byte seed followed by the 4 byte permissions of that recipient.
+//! The file key is derived from the seed and all `/Recipients` strings.
+
+use aes::cipher::{BlockDecryptMut, InnerIvInit, KeyIvInit};
+use aes::cipher::block_padding::Pkcs7;
+use cms::content_info::ContentInfo;
+use cms::enveloped_data::{EncryptedContentInfo, EnvelopedData, RecipientIdentifier, RecipientInfo};
+use der::{Decode, DecodePem, SliceReader, Sequence};
+use der::asn1::OctetString;
+use der::oid::ObjectIdentifier;
+use rsa::{Oaep, Pkcs1v15Encrypt, RsaPrivateKey};
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::DecodePrivateKey;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use x509_cert::Certificate;
+
+use crate::error::{PdfError, Result};
+use crate::primitive::PdfString;
+use super::{CryptDict, CryptMethod, Decoder, Permissions};
+
+const ENVELOPED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.3");
+const RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const RSAES_OAEP: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.7");
+const AES128_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.1.2");
+const AES192_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.1.22");
+const AES256_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.1.42");
+const DES_EDE3_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.3.7");
+const RC2_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.3.2");
+const SUBJECT_KEY_IDENTIFIER: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.14");
+
+/// RFC 8018 B.2.3
+#[derive(Sequence)]
+struct Rc2CbcParameter {
+    version: Option<u32>,
+    iv: OctetString,
+}
+
+/// The private key and certificate of a recipient of a document encrypted with
+/// the public-key security handler.
+pub struct Recipient {
+    key: RsaPrivateKey,
+    certificate: Certificate,
+}
+impl Recipient {
+    /// The key may be PKCS#8 or PKCS#1. Key and certificate may be PEM or DER encoded.
+    pub fn new(private_key: &[u8], certificate: &[u8]) -> Result<Recipient> {
+        let key = match std::str::from_utf8(private_key) {
+            Ok(pem) if pem.contains("-----BEGIN") => RsaPrivateKey::from_pkcs8_pem(pem).ok()
+                .or_else(|| RsaPrivateKey::from_pkcs1_pem(pem).ok()),
+            _ => RsaPrivateKey::from_pkcs8_der(private_key).ok()
+                .or_else(|| RsaPrivateKey::from_pkcs1_der(private_key).ok()),
+        };
+        let key = key.ok_or_else(|| other!("can't read the private key as PKCS#8 or PKCS#1 RSA key"))?;
+
+        let certificate = if certificate.trim_ascii_start().starts_with(b"-----BEGIN") {
+            Certificate::from_pem(certificate.trim_ascii())
+        } else {
+            Certificate::from_der(certificate)
+        };
+        let certificate = certificate.map_err(|e| other!("can't read the certificate: {}", e))?;
+
+        Ok(Recipient { key, certificate })
+    }
+
+    fn is_addressed(&self, rid: &RecipientIdentifier) -> bool {
+        let tbs = &self.certificate.tbs_certificate;
+        match rid {
+            RecipientIdentifier::IssuerAndSerialNumber(id) => id.issuer == tbs.issuer && id.serial_number == tbs.serial_number,
+            RecipientIdentifier::SubjectKeyIdentifier(id) => tbs.extensions.iter().flatten()
+                .filter(|ext| ext.extn_id == SUBJECT_KEY_IDENTIFIER)
+                .any(|ext| OctetString::from_der(ext.extn_value.as_bytes()).is_ok_and(|key_id| key_id == id.0)),
+        }
+    }
+
+    /// The content of the envelope, if it is addressed to this recipient.
+    fn open(&self, envelope: &[u8]) -> Result<Option<Vec<u8>>> {
+        // the string may be padded after the DER data
+        let mut reader = SliceReader::new(envelope).map_err(der_error)?;
+        let info = ContentInfo::decode(&mut reader).map_err(der_error)?;
+        if info.content_type != ENVELOPED_DATA {
+            bail!("recipient is not a CMS EnvelopedData, but {}", info.content_type);
+        }
+        let data: EnvelopedData = info.content.decode_as().map_err(der_error)?;
+
+        for recipient in data.recip_infos.0.iter() {
+            let ktri = match recipient {
+                RecipientInfo::Ktri(ref ktri) if self.is_addressed(&ktri.rid) => ktri,
+                _ => continue
+            };
+            let enc_key = ktri.enc_key.as_bytes();
+            let oid = ktri.key_enc_alg.oid;
+            let key = if oid == RSA_ENCRYPTION {
+                self.key.decrypt(Pkcs1v15Encrypt, enc_key)
+            } else if oid == RSAES_OAEP {
+                self.key.decrypt(Oaep::new::<Sha1>(), enc_key)
+            } else {
+                bail!("unsupported key encryption algorithm {}", oid);
+            };
+            let key = key.map_err(|_| PdfError::DecryptionFailure)?;
+            return decrypt_content(&data.encrypted_content, &key).map(Some);
+        }
+        Ok(None)
+    }
+}
+
+fn der_error(e: der::Error) -> PdfError {
+    other!("invalid CMS data: {}", e)
+}
+
+fn decrypt_content(info: &EncryptedContentInfo, key: &[u8]) -> Result<Vec<u8>> {
+    let mut data = try_opt!(info.encrypted_content.as_ref()).as_bytes().to_vec();
+    let params = try_opt!(info.content_enc_alg.parameters.as_ref());
+    let oid = info.content_enc_alg.oid;
+
+    fn cbc<C: BlockDecryptMut>(cipher: C, data: &mut [u8]) -> Result<&[u8]> {
+        cipher.decrypt_padded_mut::<Pkcs7>(data).map_err(|_| PdfError::DecryptionFailure)
+    }
+    let invalid_key = |_| PdfError::DecryptionFailure;
+
+    let plain = if oid == RC2_CBC {
+        let params: Rc2CbcParameter = params.decode_as().map_err(der_error)?;
+        let bits = match params.version {
+            None => 32,
+            Some(160) => 40,
+            Some(120) => 64,
+            Some(58) => 128,
+            Some(v) if v >= 256 => v as usize,
+            Some(v) => bail!("invalid RC2 parameter version {}", v),
+        };
+        let cipher = rc2::Rc2::new_with_eff_key_len(key, bits);
+        cbc(cbc::Decryptor::inner_iv_slice_init(cipher, params.iv.as_bytes()).map_err(invalid_key)?, &mut data)?
+    } else {
+        let iv: OctetString = params.decode_as().map_err(der_error)?;
+        let iv = iv.as_bytes();
+        if oid == AES128_CBC {
+            cbc(cbc::Decryptor::<aes::Aes128>::new_from_slices(key, iv).map_err(invalid_key)?, &mut data)?
+        } else if oid == AES192_CBC {
+            cbc(cbc::Decryptor::<aes::Aes192>::new_from_slices(key, iv).map_err(invalid_key)?, &mut data)?
+        } else if oid == AES256_CBC {
+            cbc(cbc::Decryptor::<aes::Aes256>::new_from_slices(key, iv).map_err(invalid_key)?, &mut data)?
+        } else if oid == DES_EDE3_CBC {
+            cbc(cbc::Decryptor::<des::TdesEde3>::new_from_slices(key, iv).map_err(invalid_key)?, &mut data)?
+        } else {
+            bail!("unsupported content encryption algorithm {}", oid);
+        }
+    };
+    Ok(plain.to_vec())
+}
+
+impl Decoder {
+    /// Open a document encrypted with the public-key security handler.
+    pub fn from_recipient(dict: &CryptDict, recipient: &Recipient) -> Result<Decoder> {
+        let sub_filter = dict.sub_filter.as_ref().map(|n| n.as_str());
+        let (recipients, method, key_bits, encrypt_metadata): (&[PdfString], _, _, _) = match sub_filter {
+            Some("adbe.pkcs7.s3") | Some("adbe.pkcs7.s4") => (
+                dict.recipients.as_deref().unwrap_or_default(),
+                CryptMethod::V2,
+                dict.bits,
+                true,
+            ),
+            Some("adbe.pkcs7.s5") => {
+                let name = try_opt!(dict.default_crypt_filter.as_ref());
+                let filter = dict.crypt_filters.get(name.as_str())
+                    .ok_or_else(|| other!("missing crypt filter entry {:?}", name))?;
+                let bits = match filter.method {
+                    CryptMethod::AESV3 => 256,
+                    CryptMethod::AESV2 => 128,
+                    _ => filter.length.map(|n| if n <= 16 { 8 * n } else { n }).unwrap_or(dict.bits),
+                };
+                (filter.recipients.as_deref().unwrap_or_default(), filter.method, bits, filter.encrypt_metadata)
+            }
+            f => bail!("unsupported public-key security handler {:?}", f),
+        };
+
+        let mut content = None;
+        for envelope in recipients {
+            if let Some(c) = recipient.open(envelope.as_bytes())? {
+                content = Some(c);
+                break;
+            }
+        }
+        let content = content.ok_or(PdfError::NotARecipient)?;
+        if content.len() < 20 {
+            bail!("the recipient seed is too short");
+        }
+        let permissions = match content.get(20..24) {
+            Some(p) => Permissions::from_p(i32::from_be_bytes(p.try_into().unwrap())),
+            None => Permissions::all(),
+        };
+
+        // 7.6.5.3, with SHA-256 for AES-256
+        let key_size = key_bits as usize / 8;
+        let mut key = if let CryptMethod::AESV3 = method {
+            let mut hash = Sha256::new();
+            hash.update(&content[..20]);
+            recipients.iter().for_each(|r| hash.update(r.as_bytes()));
+            if !encrypt_metadata {
+                hash.update([0xff; 4]);
+            }
+            hash.finalize().to_vec()
+        } else {
+            let mut hash = Sha1::new();
+            hash.update(&content[..20]);
+            recipients.iter().for_each(|r| hash.update(r.as_bytes()));
+            if !encrypt_metadata {
+                hash.update([0xff; 4]);
+            }
+            hash.finalize().to_vec()
+        };
+        if key_size > key.len() {
+            bail!("unsupported key length {}", key_bits);
+        }
+        key.truncate(key_size);
+
+        let mut decoder = Decoder::new(key, key_size, method, encrypt_metadata);
+        decoder.permissions = Some(permissions);
+        decoder.owner = permissions.contains(Permissions::all());
+        Ok(decoder)
+    }
+}
@@ -6,7 +6,8 @@ use itertools::Itertools;
 use crate as pdf;
 use crate::error::*;
 use crate::object::{Object, Resolve, Stream};
-use crate::primitive::{Primitive, Dictionary};
+use crate::primitive::{Primitive, Dictionary, Name};
+use std::sync::Arc;
 use std::convert::{TryFrom, TryInto};
 use std::io::{Read, Write};
 use once_cell::sync::OnceCell;
@@ -92,7 +93,9 @@ pub enum StreamFilter {
     CCITTFaxDecode (CCITTFaxDecodeParams),
     JBIG2Decode(JBIG2DecodeParams),
     Crypt,
-    RunLengthDecode
+    RunLengthDecode,
+    /// A filter that is not in the PDF specification, decoded by a filter registered in `Decoders`
+    Other(Name, Dictionary),
 }
 impl StreamFilter {
     pub fn from_kind_and_params(kind: &str, params: Dictionary, r: &impl Resolve) -> Result<StreamFilter> {
@@ -109,7 +112,7 @@ impl StreamFilter {
            "JBIG2Decode" => StreamFilter::JBIG2Decode(JBIG2DecodeParams::from_primitive(params, r)?),
            "Crypt" => StreamFilter::Crypt,
            "RunLengthDecode" => StreamFilter::RunLengthDecode,
-           ty => bail!("Unrecognized filter type {:?}", ty),
+           ty => StreamFilter::Other(ty.into(), params.into_dictionary()?),
        } 
        )
     }
@@ -257,140 +260,277 @@ fn encode_85(data: &[u8]) -> Vec<u8> {
     buf
 }
 
-fn inflate_bytes_zlib(data: &[u8]) -> Result<Vec<u8>> {
-    use libflate::zlib::Decoder;
-    let mut decoder = Decoder::new(data)?;
+fn check_stream_size(len: usize, limit: usize) -> Result<()> {
+    if len > limit {
+        return Err(PdfError::StreamTooLarge { max: limit });
+    }
+    Ok(())
+}
+
+/// Collects the output of a decoder, failing once it grows beyond `limit` bytes.
+struct LimitedWriter<'a> {
+    out: &'a mut Vec<u8>,
+    limit: usize,
+}
+impl Write for LimitedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // keep one byte more than allowed, so the caller can tell
+        let n = buf.len().min(self.limit.saturating_add(1) - self.out.len());
+        self.out.extend_from_slice(&buf[.. n]);
+        if n < buf.len() {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        Ok(n)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn read_limited(reader: impl Read, limit: usize) -> Result<Vec<u8>> {
     let mut decoded = Vec::new();
-    decoder.read_to_end(&mut decoded)?;
+    reader.take((limit as u64).saturating_add(1)).read_to_end(&mut decoded)?;
+    check_stream_size(decoded.len(), limit)?;
     Ok(decoded)
 }
 
-fn inflate_bytes(data: &[u8]) -> Result<Vec<u8>> {
+fn inflate_bytes_zlib(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    use libflate::zlib::Decoder;
+    read_limited(Decoder::new(data)?, limit)
+}
+
+fn inflate_bytes(data: &[u8], limit: usize) -> Result<Vec<u8>> {
     use libflate::deflate::Decoder;
-    let mut decoder = Decoder::new(data);
-    let mut decoded = Vec::new();
-    decoder.read_to_end(&mut decoded)?;
-    Ok(decoded)
+    read_limited(Decoder::new(data), limit)
 }
 
 pub fn flate_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
+    flate_decode_limited(data, params, usize::MAX)
+}
+fn flate_decode_limited(data: &[u8], params: &LZWFlateParams, limit: usize) -> Result<Vec<u8>> {
+    let decoded = match inflate_bytes_zlib(data, limit) {
+        Ok(data) => data,
+        Err(e @ PdfError::StreamTooLarge { .. }) => return Err(e),
+        Err(_) => match inflate_bytes(data, limit) {
+            Ok(data) => data,
+            Err(e @ PdfError::StreamTooLarge { .. }) => return Err(e),
+            Err(_) => {
+                dump_data(data);
+                bail!("can't inflate");
+            }
+        }
+    };
+    unpredict(decoded, params)
+}
+fn flate_encode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
+    use libflate::zlib::Encoder;
+    let data = predict(data, params)?;
+    let mut encoder = Encoder::new(Vec::new())?;
+    encoder.write_all(&data)?;
+    Ok(encoder.finish().into_result()?)
+}
 
-    let predictor = params.predictor as usize;
+/// Bytes per row and per pixel (at least one) of the data a predictor works on.
+fn predictor_layout(params: &LZWFlateParams) -> Result<(usize, usize)> {
+    if params.n_components < 1 || params.columns < 1 || !matches!(params.bits_per_component, 1 | 2 | 4 | 8 | 16) {
+        bail!("invalid predictor parameters {:?}", params);
+    }
     let n_components = params.n_components as usize;
-    let columns = params.columns as usize;
-    let stride = columns * n_components;
+    let bits_per_component = params.bits_per_component as usize;
+    let stride = (params.columns as usize * n_components * bits_per_component).div_ceil(8);
+    let bpp = (n_components * bits_per_component / 8).max(1);
+    Ok((stride, bpp))
+}
+
+/// Undo the predictor of `params`, 2 for TIFF and 10 or more for PNG.
+fn unpredict(data: Vec<u8>, params: &LZWFlateParams) -> Result<Vec<u8>> {
+    match params.predictor {
+        2 => {
+            let (stride, _) = predictor_layout(params)?;
+            let mut data = data;
+            for row in data.chunks_mut(stride) {
+                tiff_predictor(row, params, false)?;
+            }
+            Ok(data)
+        }
+        p if p >= 10 => {
+            let (stride, bpp) = predictor_layout(params)?;
+            // each row starts with the PNG filter type
+            let rows = data.len() / (stride + 1);
+            let mut out = vec![0; rows * stride];
+            // no larger than the data, for a stride that is made up
+            let null_row = vec![0; stride.min(data.len())];
+            for (i, row) in data.chunks_exact(stride + 1).enumerate() {
+                let predictor = PredictorType::from_u8(row[0])?;
+                let (prev, current) = out.split_at_mut(i * stride);
+                let prev = match i {
+                    0 => &null_row[..],
+                    _ => &prev[(i - 1) * stride ..],
+                };
+                unfilter(predictor, bpp, prev, &row[1 ..], &mut current[.. stride]);
+            }
+            Ok(out)
+        }
+        _ => Ok(data),
+    }
+}
 
+/// Apply the predictor of `params`. For predictor 15 the best PNG filter is chosen for
+/// each row, by the smallest sum of absolute differences.
+fn predict(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
+    use self::PredictorType::*;
+    match params.predictor {
+        2 => {
+            let (stride, _) = predictor_layout(params)?;
+            let mut data = data.to_vec();
+            for row in data.chunks_mut(stride) {
+                tiff_predictor(row, params, true)?;
+            }
+            Ok(data)
+        }
+        p @ 10 ..= 15 => {
+            let (stride, bpp) = predictor_layout(params)?;
+            if !data.len().is_multiple_of(stride) {
+                bail!("{} bytes are not a multiple of rows of {} bytes", data.len(), stride);
+            }
+            let mut out = Vec::with_capacity(data.len() / stride * (stride + 1));
+            // no larger than the data, for a stride that is made up
+            let null_row = vec![0; stride.min(data.len())];
+            let mut prev = &null_row[..];
+            for row in data.chunks_exact(stride) {
+                let candidates: &[PredictorType] = match p {
+                    10 => &[NoFilter],
+                    11 => &[Sub],
+                    12 => &[Up],
+                    13 => &[Avg],
+                    14 => &[Paeth],
+                    _ => &[NoFilter, Sub, Up, Avg, Paeth],
+                };
+                let (method, filtered) = candidates.iter().map(|&method| {
+                    let mut current = row.to_vec();
+                    filter(method, bpp, prev, &mut current);
+                    (method, current)
+                }).min_by_key(|(_, current)| current.iter().map(|&b| (b as i8).unsigned_abs() as u32).sum::<u32>()).unwrap();
+                out.push(method as u8);
+                out.extend_from_slice(&filtered);
+                prev = row;
+            }
+            Ok(out)
+        }
+        1 => Ok(data.to_vec()),
+        p => bail!("invalid predictor {}", p),
+    }
+}
 
-    // First flate decode
-    let decoded = {
-        if let Ok(data) = inflate_bytes_zlib(data) {
-            data
-        } else if let Ok(data) = inflate_bytes(data) {
-            data
-        } else {
-            dump_data(data);
-            bail!("can't inflate");
+/// Horizontal differencing of one row, for 8 and 16 bits per component.
+fn tiff_predictor(row: &mut [u8], params: &LZWFlateParams, encode: bool) -> Result<()> {
+    let n = params.n_components as usize;
+    match params.bits_per_component {
+        8 if encode => for i in (n .. row.len()).rev() {
+            row[i] = row[i].wrapping_sub(row[i - n]);
         }
-    };
-    // Then unfilter (PNG)
-    // For this, take the old out as input, and write output to out
-
-    if predictor > 10 {
-        let inp = decoded; // input buffer
-        let rows = inp.len() / (stride+1);
-        
-        // output buffer
-        let mut out = vec![0; rows * stride];
-    
-        // Apply inverse predictor
-        let null_vec = vec![0; stride];
-        
-        let mut in_off = 0; // offset into input buffer
-        
-        let mut out_off = 0; // offset into output buffer
-        let mut last_out_off = 0; // last offset to output buffer
-        
-        while in_off + stride < inp.len() {
-            let predictor = PredictorType::from_u8(inp[in_off])?;
-            in_off += 1; // +1 because the first byte on each row is predictor
-            
-            let row_in = &inp[in_off .. in_off + stride];
-            let (prev_row, row_out) = if out_off == 0 {
-                (&null_vec[..], &mut out[out_off .. out_off+stride])
+        8 => for i in n .. row.len() {
+            row[i] = row[i].wrapping_add(row[i - n]);
+        }
+        16 => {
+            let mut samples: Vec<u16> = row.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+            if encode {
+                for i in (n .. samples.len()).rev() {
+                    samples[i] = samples[i].wrapping_sub(samples[i - n]);
+                }
             } else {
-                let (prev, curr) = out.split_at_mut(out_off);
-                (&prev[last_out_off ..], &mut curr[.. stride])
-            };
-            unfilter(predictor, n_components, prev_row, row_in, row_out);
-            
-            last_out_off = out_off;
-            
-            in_off += stride;
-            out_off += stride;
-        }
-        Ok(out)
-    } else {
-        Ok(decoded)
+                for i in n .. samples.len() {
+                    samples[i] = samples[i].wrapping_add(samples[i - n]);
+                }
+            }
+            for (c, v) in row.chunks_exact_mut(2).zip(samples) {
+                c.copy_from_slice(&v.to_be_bytes());
+            }
+        }
+        bpc => bail!("the TIFF predictor is not supported for {} bits per component", bpc),
     }
-}
-fn flate_encode(data: &[u8]) -> Vec<u8> {
-    use libflate::deflate::Encoder;
-    let mut encoded = Vec::new();
-    let mut encoder = Encoder::new(&mut encoded);
-    encoder.write_all(data).unwrap();
-    encoded
+    Ok(())
 }
 
-pub fn dct_decode(data: &[u8], _params: &DCTDecodeParams) -> Result<Vec<u8>> {
+pub fn dct_decode(data: &[u8], params: &DCTDecodeParams) -> Result<Vec<u8>> {
+    dct_decode_limited(data, params, usize::MAX)
+}
+fn dct_decode_limited(data: &[u8], _params: &DCTDecodeParams, limit: usize) -> Result<Vec<u8>> {
     use jpeg_decoder::Decoder;
     let mut decoder = Decoder::new(data);
+    decoder.read_info()?;
+    if let Some(info) = decoder.info() {
+        let size = info.width as usize * info.height as usize * info.pixel_format.pixel_bytes();
+        check_stream_size(size, limit)?;
+    }
     let pixels = decoder.decode()?;
     Ok(pixels)
 }
 
 pub fn lzw_decode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
+    lzw_decode_limited(data, params, usize::MAX)
+}
+fn lzw_decode_limited(data: &[u8], params: &LZWFlateParams, limit: usize) -> Result<Vec<u8>> {
     use weezl::{BitOrder, decode::Decoder};
     let mut out = vec![];
 
     let mut decoder = if params.early_change != 0 {
-        Decoder::with_tiff_size_switch(BitOrder::Msb, 9)
+        Decoder::with_tiff_size_switch(BitOrder::Msb, 8)
     } else {
-        Decoder::new(BitOrder::Msb, 9)
+        Decoder::new(BitOrder::Msb, 8)
     };
 
-    decoder
-        .into_stream(&mut out)
-        .decode_all(data).status?;
-    Ok(out)
+    let status = decoder
+        .into_stream(LimitedWriter { out: &mut out, limit })
+        .decode_all(data).status;
+    check_stream_size(out.len(), limit)?;
+    status?;
+    unpredict(out, params)
 }
 fn lzw_encode(data: &[u8], params: &LZWFlateParams) -> Result<Vec<u8>> {
     use weezl::{BitOrder, encode::Encoder};
-    if params.early_change != 0 {
-        bail!("encoding early_change != 0 is not supported");
-    }
+    let data = predict(data, params)?;
+    let mut encoder = if params.early_change != 0 {
+        Encoder::with_tiff_size_switch(BitOrder::Msb, 8)
+    } else {
+        Encoder::new(BitOrder::Msb, 8)
+    };
     let mut compressed = vec![];
-    Encoder::new(BitOrder::Msb, 9)
+    encoder
         .into_stream(&mut compressed)
-        .encode_all(data).status?;
+        .encode_all(&data[..]).status?;
     Ok(compressed)
 }
 
 pub fn fax_decode(data: &[u8], params: &CCITTFaxDecodeParams) -> Result<Vec<u8>> {
+    fax_decode_limited(data, params, usize::MAX)
+}
+fn fax_decode_limited(data: &[u8], params: &CCITTFaxDecodeParams, limit: usize) -> Result<Vec<u8>> {
     use fax::{Color, decoder::{pels, decode_g4}};
 
     if params.k < 0 {
         let columns = params.columns as usize;
         let rows = params.rows as usize;
+        check_stream_size(columns * rows, limit)?;
 
         let height = if params.rows == 0 { None } else { Some(params.rows as u16)};
         let mut buf = Vec::with_capacity(columns * rows);
+        let mut exceeded = false;
         decode_g4(data.iter().cloned(), columns as u16, height, |line| {
+            // the decoder can't be stopped, but the lines are dropped
+            if exceeded || buf.len() + columns > limit {
+                exceeded = true;
+                return;
+            }
             buf.extend(pels(line, columns as u16).map(|c| match c {
                 Color::Black => 0,
                 Color::White => 255
             }));
             assert_eq!(buf.len() % columns, 0, "len={}, columns={}", buf.len(), columns);
         }).ok_or(PdfError::Other { msg: "faxdecode failed".into() })?;
+        if exceeded {
+            return Err(PdfError::StreamTooLarge { max: limit });
+        }
         assert_eq!(buf.len() % columns, 0, "len={}, columns={}", buf.len(), columns);
 
         if rows != 0 && buf.len() != columns * rows {
@@ -398,11 +538,63 @@ pub fn fax_decode(data: &[u8], params: &CCITTFaxDecodeParams) -> Result<Vec<u8>>
         }
         Ok(buf)
     } else {
-        unimplemented!()
+        bail!("only CCITT group 4 (K < 0) is supported, not K = {}", params.k)
+    }
+}
+
+/// The inverse of `fax_decode`: one byte per pixel, where values below 128 are black.
+pub fn fax_encode(data: &[u8], params: &CCITTFaxDecodeParams) -> Result<Vec<u8>> {
+    use fax::{Color, VecWriter, encoder::Encoder};
+
+    if params.k >= 0 {
+        bail!("only CCITT group 4 (K < 0) is supported, not K = {}", params.k);
+    }
+    let columns = params.columns as usize;
+    if columns == 0 || columns > u16::MAX as usize || !data.len().is_multiple_of(columns) {
+        bail!("{} pixels are not a multiple of rows of {} columns", data.len(), columns);
+    }
+    let color = |&p: &u8| if p < 128 { Color::Black } else { Color::White };
+    let mut encoder = Encoder::new(VecWriter::new());
+    for row in data.chunks_exact(columns) {
+        let Ok(()) = encoder.encode_line(row.iter().map(color), columns as u16);
+    }
+    let Ok(writer) = encoder.finish();
+    Ok(writer.finish())
+}
+
+pub fn run_length_encode(data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(data.len() + data.len() / 128 + 1);
+    let mut literal_start = 0;
+    let mut i = 0;
+    let flush = |buf: &mut Vec<u8>, literal: &[u8]| {
+        for chunk in literal.chunks(128) {
+            buf.push(chunk.len() as u8 - 1);
+            buf.extend_from_slice(chunk);
+        }
+    };
+    while i < data.len() {
+        let run = data[i ..].iter().take(128).take_while(|&&b| b == data[i]).count();
+        // runs of two are only worth it between other runs
+        if run >= 3 || run == 2 && literal_start == i {
+            flush(&mut buf, &data[literal_start .. i]);
+            buf.push((257 - run) as u8);
+            buf.push(data[i]);
+            i += run;
+            literal_start = i;
+        } else {
+            i += run;
+        }
     }
+    flush(&mut buf, &data[literal_start ..]);
+    // EOD
+    buf.push(128);
+    buf
 }
 
 pub fn run_length_decode(data: &[u8]) -> Result<Vec<u8>> {
+    run_length_decode_limited(data, usize::MAX)
+}
+fn run_length_decode_limited(data: &[u8], limit: usize) -> Result<Vec<u8>> {
     // Used <http://benno.id.au/refs/PDFReference15_v5.pdf> as specification
     let mut buf = Vec::new();
     let d = data;
@@ -424,62 +616,158 @@ pub fn run_length_decode(data: &[u8]) -> Result<Vec<u8>> {
         } else {
             break; // EOD
         }
+        check_stream_size(buf.len(), limit)?;
     }
 
     Ok(buf)
 }
 
 pub type DecodeFn = dyn Fn(&[u8]) -> Result<Vec<u8>> + Sync + Send + 'static;
+/// Decodes data of a custom filter, given its decode parameters.
+pub type FilterFn = dyn Fn(&[u8], &Dictionary) -> Result<Vec<u8>> + Sync + Send + 'static;
+
 static JPX_DECODER: OnceCell<Box<DecodeFn>> = OnceCell::new();
 static JBIG2_DECODER: OnceCell<Box<DecodeFn>> = OnceCell::new();
 
+/// Set the JPX decoder used by all documents that don't have one in their `Decoders`.
+/// Only the first call has an effect.
 pub fn set_jpx_decoder(f: Box<DecodeFn>) {
     let _ = JPX_DECODER.set(f);
 }
+/// Set the JBIG2 decoder used by all documents that don't have one in their `Decoders`.
+/// Only the first call has an effect.
 pub fn set_jbig2_decoder(f: Box<DecodeFn>) {
     let _ = JBIG2_DECODER.set(f);
 }
 
-pub fn jpx_decode(data: &[u8]) -> Result<Vec<u8>> {
-    JPX_DECODER.get().ok_or_else(|| PdfError::Other { msg: "jp2k decoder not set".into()})?(data)
+/// Decoders for the filters this crate can't decode itself, set per document with
+/// `FileOptions::decoders`.
+///
+/// JPX and JBIG2 fall back to the decoders set with `set_jpx_decoder` and `set_jbig2_decoder`.
+/// ```
+/// # use pdf::enc::Decoders;
+/// let decoders = Decoders::new()
+///     .jpx(|data| Ok(data.to_vec()))
+///     .filter("RotDecode", |data, _params| Ok(data.iter().map(|b| b.wrapping_sub(13)).collect()));
+/// ```
+#[derive(Clone, Default)]
+pub struct Decoders {
+    jpx: Option<Arc<DecodeFn>>,
+    jbig2: Option<Arc<DecodeFn>>,
+    filters: Vec<(Name, Arc<FilterFn>)>,
 }
-pub fn jbig2_decode(data: &[u8], globals: &[u8]) -> Result<Vec<u8>> {
-    let data = [
-        // file header
-        // &[0x97, 0x4A, 0x42, 0x32, 0x0D, 0x0A, 0x1A, 0x0A, 0x01, 0x00, 0x00, 0x00, 0x01],
+impl Decoders {
+    pub const fn new() -> Self {
+        Decoders { jpx: None, jbig2: None, filters: Vec::new() }
+    }
+    pub fn jpx(self, f: impl Fn(&[u8]) -> Result<Vec<u8>> + Sync + Send + 'static) -> Self {
+        Decoders { jpx: Some(Arc::new(f)), .. self }
+    }
+    /// The decoder is given a JBIG2 stream in the embedded format, with the globals (if any)
+    /// in front of the page data.
+    pub fn jbig2(self, f: impl Fn(&[u8]) -> Result<Vec<u8>> + Sync + Send + 'static) -> Self {
+        Decoders { jbig2: Some(Arc::new(f)), .. self }
+    }
+    /// Decode the filter `name`, which is not one of the standard filters.
+    /// A later filter with the same name replaces the earlier one.
+    pub fn filter(mut self, name: &str, f: impl Fn(&[u8], &Dictionary) -> Result<Vec<u8>> + Sync + Send + 'static) -> Self {
+        self.filters.retain(|(n, _)| n != name);
+        self.filters.push((name.into(), Arc::new(f)));
+        self
+    }
+
+    pub fn jpx_decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.jpx {
+            Some(ref f) => f(data),
+            None => JPX_DECODER.get().ok_or_else(|| PdfError::Other { msg: "jp2k decoder not set".into()})?(data),
+        }
+    }
+    pub fn jbig2_decode(&self, data: &[u8], globals: &[u8]) -> Result<Vec<u8>> {
+        let data = [
+            // file header
+            // &[0x97, 0x4A, 0x42, 0x32, 0x0D, 0x0A, 0x1A, 0x0A, 0x01, 0x00, 0x00, 0x00, 0x01],
+
+            globals,
+            data,
+
+            // end of page
+            &[0x00, 0x00, 0x00, 0x03, 0x31, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00],
+
+            // end of stream
+            &[0x00, 0x00, 0x00, 0x04, 0x33, 0x01, 0x00, 0x00, 0x00, 0x00],
+        ].concat();
+        match self.jbig2 {
+            Some(ref f) => f(&data),
+            None => JBIG2_DECODER.get().ok_or_else(|| PdfError::Other { msg: "jbig2 decoder not set".into()})?(&data),
+        }
+    }
 
-        globals,
-        data,
+    /// Like `decode`, using the decoders of `self`. JBIG2 streams with globals need a resolver
+    /// and can only be decoded with `ImageXObject::image_data`.
+    pub fn decode(&self, data: &[u8], filter: &StreamFilter) -> Result<Vec<u8>> {
+        self.decode_limited(data, filter, usize::MAX)
+    }
+    /// Like `decode`, but fails with `PdfError::StreamTooLarge` when the output would grow
+    /// beyond `limit` bytes. The built-in decoders stop there, external ones are checked afterwards.
+    pub fn decode_limited(&self, data: &[u8], filter: &StreamFilter, limit: usize) -> Result<Vec<u8>> {
+        let decoded = match *filter {
+            StreamFilter::ASCIIHexDecode => decode_hex(data),
+            StreamFilter::ASCII85Decode => decode_85(data),
+            StreamFilter::LZWDecode(ref params) => lzw_decode_limited(data, params, limit),
+            StreamFilter::FlateDecode(ref params) => flate_decode_limited(data, params, limit),
+            StreamFilter::RunLengthDecode => run_length_decode_limited(data, limit),
+            StreamFilter::DCTDecode(ref params) => dct_decode_limited(data, params, limit),
+            StreamFilter::CCITTFaxDecode(ref params) => fax_decode_limited(data, params, limit),
+            StreamFilter::JPXDecode => self.jpx_decode(data),
+            StreamFilter::JBIG2Decode(JBIG2DecodeParams { globals: None }) => self.jbig2_decode(data, &[]),
+            StreamFilter::Other(ref name, ref params) => match self.filters.iter().find(|(n, _)| n == name) {
+                Some((_, f)) => f(data, params),
+                None => bail!("no decoder for the filter {}", name),
+            }
+            _ => bail!("unimplemented {filter:?}"),
+        }?;
+        check_stream_size(decoded.len(), limit)?;
+        Ok(decoded)
+    }
+}
+impl std::fmt::Debug for Decoders {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Decoders")
+            .field("jpx", &self.jpx.is_some())
+            .field("jbig2", &self.jbig2.is_some())
+            .field("filters", &self.filters.iter().map(|(name, _)| name).collect::<Vec<_>>())
+            .finish()
+    }
+}
 
-        // end of page
-        &[0x00, 0x00, 0x00, 0x03, 0x31, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00],
+/// Decoders without any per-document entries, using only the global ones.
+pub(crate) static GLOBAL_DECODERS: Decoders = Decoders::new();
 
-        // end of stream
-        &[0x00, 0x00, 0x00, 0x04, 0x33, 0x01, 0x00, 0x00, 0x00, 0x00],
-    ].concat();
-    JBIG2_DECODER.get().ok_or_else(|| PdfError::Other { msg: "jbig2 decoder not set".into()})?(&data)
+pub fn jpx_decode(data: &[u8]) -> Result<Vec<u8>> {
+    GLOBAL_DECODERS.jpx_decode(data)
+}
+pub fn jbig2_decode(data: &[u8], globals: &[u8]) -> Result<Vec<u8>> {
+    GLOBAL_DECODERS.jbig2_decode(data, globals)
 }
 
 pub fn decode(data: &[u8], filter: &StreamFilter) -> Result<Vec<u8>> {
-    match *filter {
-        StreamFilter::ASCIIHexDecode => decode_hex(data),
-        StreamFilter::ASCII85Decode => decode_85(data),
-        StreamFilter::LZWDecode(ref params) => lzw_decode(data, params),
-        StreamFilter::FlateDecode(ref params) => flate_decode(data, params),
-        StreamFilter::RunLengthDecode => run_length_decode(data),
-        StreamFilter::DCTDecode(ref params) => dct_decode(data, params),
-
-        _ => bail!("unimplemented {filter:?}"),
-    }
+    GLOBAL_DECODERS.decode(data, filter)
 }
 
+/// Encode `data` with `filter`, the inverse of `decode`.
+///
+/// Image encodings like DCT and JPX can't be produced here, the data has to be encoded
+/// already and is passed through.
 pub fn encode(data: &[u8], filter: &StreamFilter) -> Result<Vec<u8>> {
     match *filter {
         StreamFilter::ASCIIHexDecode => Ok(encode_hex(data)),
         StreamFilter::ASCII85Decode => Ok(encode_85(data)),
         StreamFilter::LZWDecode(ref params) => lzw_encode(data, params),
-        StreamFilter::FlateDecode (ref _params) => Ok(flate_encode(data)),
-        _ => unimplemented!(),
+        StreamFilter::FlateDecode(ref params) => flate_encode(data, params),
+        StreamFilter::RunLengthDecode => Ok(run_length_encode(data)),
+        StreamFilter::CCITTFaxDecode(ref params) => fax_encode(data, params),
+        StreamFilter::DCTDecode(_) | StreamFilter::JPXDecode => Ok(data.to_vec()),
+        StreamFilter::JBIG2Decode(_) | StreamFilter::Crypt | StreamFilter::Other(..) => bail!("can't encode {filter:?}"),
     }
 }
 
@@ -489,7 +777,6 @@ pub fn encode(data: &[u8], filter: &StreamFilter) -> Result<Vec<u8>> {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
-#[allow(dead_code)]
 pub enum PredictorType {
     NoFilter = 0,
     Sub = 1,
@@ -584,7 +871,6 @@ pub fn unfilter(filter: PredictorType, bpp: usize, prev: &[u8], inp: &[u8], out:
     }
 }
 
-#[allow(unused)]
 pub fn filter(method: PredictorType, bpp: usize, previous: &[u8], current: &mut [u8]) {
     use self::PredictorType::*;
     let len  = current.len();
@@ -603,7 +889,7 @@ pub fn filter(method: PredictorType, bpp: usize, previous: &[u8], current: &mut
         }
         Avg => {
             for i in (bpp..len).rev() {
-                current[i] = current[i].wrapping_sub(current[i - bpp].wrapping_add(previous[i]) / 2);
+                current[i] = current[i].wrapping_sub(((current[i - bpp] as u16 + previous[i] as u16) / 2) as u8);
             }
 
             for i in 0..bpp {
@@ -648,9 +934,110 @@ mod tests {
         */
     }
 
+    #[test]
+    fn lzw_external() {
+        // the example from section 7.4.4.2 of the PDF specification
+        let encoded = [0x80, 0x0B, 0x60, 0x50, 0x22, 0x0C, 0x0C, 0x85, 0x01];
+        let params = LZWFlateParams::default();
+        assert_eq!(lzw_decode(&encoded, &params).unwrap(), b"-----A---B");
+        assert_eq!(lzw_encode(b"-----A---B", &params).unwrap(), encoded);
+    }
+
     #[test]
     fn run_length_decode_test() {
         let x = run_length_decode(&[254, b'a', 255, b'b', 2, b'c', b'b', b'c', 254, b'a', 128]).unwrap();
         assert_eq!(b"aaabbcbcaaa", x.as_slice());
     }
+
+    #[test]
+    fn run_length_encode_test() {
+        let cases: [&[u8]; 5] = [b"", b"a", b"aaabbcbcaaa", &[7; 300], b"abcdddddddddde"];
+        for case in cases {
+            assert_eq!(case, run_length_decode(&run_length_encode(case)).unwrap().as_slice());
+        }
+        assert_eq!(run_length_encode(b"abbbc"), [0, b'a', 254, b'b', 0, b'c', 128]);
+    }
+
+    #[test]
+    fn predictors() {
+        let data: Vec<u8> = (0 .. 240u32).map(|i| (i * i % 251) as u8).collect();
+        for predictor in [1, 2, 10, 11, 12, 13, 14, 15] {
+            for (n_components, bits_per_component, columns) in [(1, 8, 24), (3, 8, 8), (2, 16, 6), (1, 1, 80), (1, 4, 12)] {
+                if predictor == 2 && bits_per_component < 8 {
+                    continue;
+                }
+                let params = LZWFlateParams { predictor, n_components, bits_per_component, columns, early_change: 1 };
+                for filter in [StreamFilter::FlateDecode(params.clone()), StreamFilter::LZWDecode(params.clone())] {
+                    let encoded = encode(&data, &filter).unwrap();
+                    assert_eq!(data, decode(&encoded, &filter).unwrap(), "{:?}", filter);
+                }
+            }
+        }
+        let params = LZWFlateParams { predictor: 2, bits_per_component: 4, ..LZWFlateParams::default() };
+        assert!(encode(&data, &StreamFilter::FlateDecode(params)).is_err());
+    }
+
+    #[test]
+    fn fax_g4() {
+        let params = CCITTFaxDecodeParams {
+            k: -1, end_of_line: false, encoded_byte_align: false, columns: 16, rows: 4,
+            end_of_block: true, black_is_1: false, damaged_rows_before_error: 0,
+        };
+        let data: Vec<u8> = (0 .. 64).map(|i| if (i / 3 + i / 16) % 2 == 0 { 0 } else { 255 }).collect();
+        let encoded = encode(&data, &StreamFilter::CCITTFaxDecode(params.clone())).unwrap();
+        assert_eq!(data, decode(&encoded, &StreamFilter::CCITTFaxDecode(params.clone())).unwrap());
+
+        let params = CCITTFaxDecodeParams { k: 0, ..params };
+        assert!(encode(&data, &StreamFilter::CCITTFaxDecode(params.clone())).is_err());
+        assert!(decode(&encoded, &StreamFilter::CCITTFaxDecode(params)).is_err());
+    }
+
+    #[test]
+    fn decoders() {
+        let decoders = Decoders::new()
+            .jpx(|data| Ok(data.repeat(2)))
+            .filter("Double", |data, _| Ok(data.repeat(2)))
+            .filter("Double", |data, _| Ok(data.repeat(3)));
+        assert_eq!(decoders.decode(b"ab", &StreamFilter::JPXDecode).unwrap(), b"abab");
+        assert_eq!(decoders.decode(b"ab", &StreamFilter::Other("Double".into(), Dictionary::new())).unwrap(), b"ababab");
+        assert!(decoders.decode(b"ab", &StreamFilter::Other("Half".into(), Dictionary::new())).is_err());
+        assert!(matches!(
+            StreamFilter::from_kind_and_params("Double", Dictionary::new(), &crate::object::NoResolve).unwrap(),
+            StreamFilter::Other(ref name, _) if name == "Double"
+        ));
+    }
+
+    #[test]
+    fn decode_limits() {
+        let data = vec![0; 100_000];
+        let params = LZWFlateParams::default();
+        let fax = CCITTFaxDecodeParams {
+            k: -1, end_of_line: false, encoded_byte_align: false, columns: 100, rows: 0,
+            end_of_block: true, black_is_1: false, damaged_rows_before_error: 0,
+        };
+        let filters = [
+            StreamFilter::FlateDecode(params.clone()),
+            StreamFilter::LZWDecode(params),
+            StreamFilter::RunLengthDecode,
+            StreamFilter::CCITTFaxDecode(fax),
+        ];
+        for filter in filters {
+            let encoded = encode(&data, &filter).unwrap();
+            let decoders = Decoders::new();
+            assert_eq!(decoders.decode_limited(&encoded, &filter, data.len()).unwrap().len(), data.len());
+            assert!(matches!(
+                decoders.decode_limited(&encoded, &filter, 1000),
+                Err(PdfError::StreamTooLarge { max: 1000 })
+            ), "{:?}", filter);
+        }
+    }
+
+    #[test]
+    fn unsupported_encodings() {
+        let jpeg = b"\xff\xd8\xff\xd9";
+        assert_eq!(encode(jpeg, &StreamFilter::DCTDecode(DCTDecodeParams { color_transform: None })).unwrap(), jpeg);
+        assert_eq!(encode(jpeg, &StreamFilter::JPXDecode).unwrap(), jpeg);
+        assert!(encode(jpeg, &StreamFilter::JBIG2Decode(JBIG2DecodeParams { globals: None })).is_err());
+        assert!(encode(jpeg, &StreamFilter::Crypt).is_err());
+    }
 }
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use once_cell::sync::Lazy;
 use istring::SmallString;
 use crate as pdf;
 use crate::object::{Object, Resolve, ObjectWrite, DeepClone};
@@ -100,9 +101,177 @@ impl Encoding {
             differences: HashMap::new()
         }
     }
+    /// The unicode text of the single byte `code`, using the differences and then the base encoding.
+    pub fn decode(&self, code: u8) -> Option<String> {
+        match self.differences.get(&(code as u32)) {
+            Some(name) => glyph_name_to_unicode(name),
+            None => self.base.decode(code).map(String::from),
+        }
+    }
+}
+impl BaseEncoding {
+    /// The character that `code` stands for.
+    ///
+    /// Only the standard latin encodings are known, the others give `None`.
+    pub fn decode(&self, code: u8) -> Option<char> {
+        match *self {
+            BaseEncoding::StandardEncoding => match code {
+                0x27 => Some('\u{2019}'),
+                0x60 => Some('\u{2018}'),
+                0x20 ..= 0x7E => Some(code as char),
+                _ => STANDARD_HIGH.iter().find(|&&(c, _)| c == code).map(|&(_, ch)| ch),
+            },
+            BaseEncoding::WinAnsiEncoding => match code {
+                0x20 ..= 0x7E => Some(code as char),
+                0x80 ..= 0x9F => WIN_ANSI_HIGH[code as usize - 0x80],
+                0xA0 => Some(' '),
+                0xAD => Some('-'),
+                0xA1 ..= 0xFF => Some(code as char),
+                _ => None,
+            },
+            BaseEncoding::MacRomanEncoding => match code {
+                0x20 ..= 0x7E => Some(code as char),
+                0x80 ..= 0xFF => MAC_ROMAN_HIGH.chars().nth(code as usize - 0x80),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Map a glyph name to unicode text, following the Adobe Glyph List conventions.
+///
+/// Knows the names of the standard latin encodings, `uniXXXX` and `uXXXX[XX]` names,
+/// ligatures of several names joined by `_`, and ignores suffixes after a `.`.
+pub fn glyph_name_to_unicode(name: &str) -> Option<String> {
+    let name = name.split('.').next().unwrap();
+    if name.contains('_') {
+        return name.split('_').map(glyph_name_to_unicode).collect();
+    }
+    if let Some(&c) = GLYPH_NAMES.get(name) {
+        return Some(c.into());
+    }
+    let hex = |s: &str| u32::from_str_radix(s, 16).ok().and_then(char::from_u32);
+    if let Some(codes) = name.strip_prefix("uni") {
+        if !codes.is_empty() && codes.len() % 4 == 0 {
+            return (0 .. codes.len()).step_by(4).map(|i| codes.get(i .. i + 4).and_then(hex)).collect();
+        }
+    }
+    if let Some(code) = name.strip_prefix('u') {
+        if (4 ..= 6).contains(&code.len()) {
+            return hex(code).map(String::from);
+        }
+    }
+    None
 }
+
+// the names of the characters 0x20 to 0x7E
+const ASCII_NAMES: [&str; 95] = [
+    "space", "exclam", "quotedbl", "numbersign", "dollar", "percent", "ampersand", "quotesingle",
+    "parenleft", "parenright", "asterisk", "plus", "comma", "hyphen", "period", "slash",
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+    "colon", "semicolon", "less", "equal", "greater", "question", "at",
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+    "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+    "bracketleft", "backslash", "bracketright", "asciicircum", "underscore", "grave",
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m",
+    "n", "o", "p", "q", "r", "s", "t", "u", "v", "w", "x", "y", "z",
+    "braceleft", "bar", "braceright", "asciitilde",
+];
+
+// the names of the characters 0xA0 to 0xFF
+const LATIN1_NAMES: [&str; 96] = [
+    "nbspace", "exclamdown", "cent", "sterling", "currency", "yen", "brokenbar", "section",
+    "dieresis", "copyright", "ordfeminine", "guillemotleft", "logicalnot", "sfthyphen", "registered", "macron",
+    "degree", "plusminus", "twosuperior", "threesuperior", "acute", "mu", "paragraph", "periodcentered",
+    "cedilla", "onesuperior", "ordmasculine", "guillemotright", "onequarter", "onehalf", "threequarters", "questiondown",
+    "Agrave", "Aacute", "Acircumflex", "Atilde", "Adieresis", "Aring", "AE", "Ccedilla",
+    "Egrave", "Eacute", "Ecircumflex", "Edieresis", "Igrave", "Iacute", "Icircumflex", "Idieresis",
+    "Eth", "Ntilde", "Ograve", "Oacute", "Ocircumflex", "Otilde", "Odieresis", "multiply",
+    "Oslash", "Ugrave", "Uacute", "Ucircumflex", "Udieresis", "Yacute", "Thorn", "germandbls",
+    "agrave", "aacute", "acircumflex", "atilde", "adieresis", "aring", "ae", "ccedilla",
+    "egrave", "eacute", "ecircumflex", "edieresis", "igrave", "iacute", "icircumflex", "idieresis",
+    "eth", "ntilde", "ograve", "oacute", "ocircumflex", "otilde", "odieresis", "divide",
+    "oslash", "ugrave", "uacute", "ucircumflex", "udieresis", "yacute", "thorn", "ydieresis",
+];
+
+// the other names used by the standard latin encodings
+const OTHER_NAMES: &[(&str, char)] = &[
+    ("Euro", '\u{20AC}'), ("quotesinglbase", '\u{201A}'), ("florin", '\u{0192}'), ("quotedblbase", '\u{201E}'),
+    ("ellipsis", '\u{2026}'), ("dagger", '\u{2020}'), ("daggerdbl", '\u{2021}'), ("circumflex", '\u{02C6}'),
+    ("perthousand", '\u{2030}'), ("Scaron", '\u{0160}'), ("guilsinglleft", '\u{2039}'), ("OE", '\u{0152}'),
+    ("Zcaron", '\u{017D}'), ("quoteleft", '\u{2018}'), ("quoteright", '\u{2019}'), ("quotedblleft", '\u{201C}'),
+    ("quotedblright", '\u{201D}'), ("bullet", '\u{2022}'), ("endash", '\u{2013}'), ("emdash", '\u{2014}'),
+    ("tilde", '\u{02DC}'), ("trademark", '\u{2122}'), ("scaron", '\u{0161}'), ("guilsinglright", '\u{203A}'),
+    ("oe", '\u{0153}'), ("zcaron", '\u{017E}'), ("Ydieresis", '\u{0178}'), ("fraction", '\u{2044}'),
+    ("fi", '\u{FB01}'), ("fl", '\u{FB02}'), ("dotlessi", '\u{0131}'), ("breve", '\u{02D8}'),
+    ("dotaccent", '\u{02D9}'), ("ring", '\u{02DA}'), ("hungarumlaut", '\u{02DD}'), ("ogonek", '\u{02DB}'),
+    ("caron", '\u{02C7}'), ("Lslash", '\u{0141}'), ("lslash", '\u{0142}'), ("minus", '\u{2212}'),
+    ("notequal", '\u{2260}'), ("infinity", '\u{221E}'), ("lessequal", '\u{2264}'), ("greaterequal", '\u{2265}'),
+    ("partialdiff", '\u{2202}'), ("summation", '\u{2211}'), ("product", '\u{220F}'), ("pi", '\u{03C0}'),
+    ("integral", '\u{222B}'), ("Omega", '\u{2126}'), ("radical", '\u{221A}'), ("approxequal", '\u{2248}'),
+    ("Delta", '\u{2206}'), ("lozenge", '\u{25CA}'), ("apple", '\u{F8FF}'), ("ff", '\u{FB00}'),
+    ("ffi", '\u{FB03}'), ("ffl", '\u{FB04}'),
+];
+
+static GLYPH_NAMES: Lazy<HashMap<&'static str, char>> = Lazy::new(|| {
+    let ascii = ASCII_NAMES.iter().zip(' ' ..= '~');
+    let latin1 = LATIN1_NAMES.iter().zip('\u{A0}' ..= '\u{FF}');
+    ascii.chain(latin1).map(|(&name, c)| (name, c))
+        .chain(OTHER_NAMES.iter().copied())
+        .collect()
+});
+
+// StandardEncoding from 0xA1 on
+const STANDARD_HIGH: &[(u8, char)] = &[
+    (0xA1, '¡'), (0xA2, '¢'), (0xA3, '£'), (0xA4, '\u{2044}'), (0xA5, '¥'), (0xA6, 'ƒ'), (0xA7, '§'), (0xA8, '¤'),
+    (0xA9, '\''), (0xAA, '“'), (0xAB, '«'), (0xAC, '‹'), (0xAD, '›'), (0xAE, '\u{FB01}'), (0xAF, '\u{FB02}'),
+    (0xB1, '–'), (0xB2, '†'), (0xB3, '‡'), (0xB4, '·'), (0xB6, '¶'), (0xB7, '•'), (0xB8, '‚'), (0xB9, '„'),
+    (0xBA, '”'), (0xBB, '»'), (0xBC, '…'), (0xBD, '‰'), (0xBF, '¿'),
+    (0xC1, '`'), (0xC2, '´'), (0xC3, 'ˆ'), (0xC4, '˜'), (0xC5, '¯'), (0xC6, '˘'), (0xC7, '˙'), (0xC8, '¨'),
+    (0xCA, '˚'), (0xCB, '¸'), (0xCD, '˝'), (0xCE, '˛'), (0xCF, 'ˇ'), (0xD0, '—'),
+    (0xE1, 'Æ'), (0xE3, 'ª'), (0xE8, 'Ł'), (0xE9, 'Ø'), (0xEA, 'Œ'), (0xEB, 'º'),
+    (0xF1, 'æ'), (0xF5, 'ı'), (0xF8, 'ł'), (0xF9, 'ø'), (0xFA, 'œ'), (0xFB, 'ß'),
+];
+
+// WinAnsiEncoding from 0x80 to 0x9F
+const WIN_ANSI_HIGH: [Option<char>; 32] = [
+    Some('€'), None, Some('‚'), Some('ƒ'), Some('„'), Some('…'), Some('†'), Some('‡'),
+    Some('ˆ'), Some('‰'), Some('Š'), Some('‹'), Some('Œ'), None, Some('Ž'), None,
+    None, Some('‘'), Some('’'), Some('“'), Some('”'), Some('•'), Some('–'), Some('—'),
+    Some('˜'), Some('™'), Some('š'), Some('›'), Some('œ'), None, Some('ž'), Some('Ÿ'),
+];
+
+// MacRomanEncoding from 0x80 on
+const MAC_ROMAN_HIGH: &str = "ÄÅÇÉÑÖÜáàâäãåçéèêëíìîïñóòôöõúùûü†°¢£§•¶ß®©™´¨≠ÆØ∞±≤≥¥µ∂∑∏π∫ªºΩæø\
+    ¿¡¬√ƒ≈∆«»…\u{A0}ÀÃÕŒœ–—“”‘’÷◊ÿŸ⁄¤‹›\u{FB01}\u{FB02}‡·‚„‰ÂÊÁËÈÍÎÏÌÓÔ\u{F8FF}ÒÚÛÙıˆ˜¯˘˙˚¸˝˛ˇ";
 impl DeepClone for Encoding {
     fn deep_clone(&self, cloner: &mut impl pdf::object::Cloner) -> Result<Self> {
         Ok(self.clone())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode() {
+        assert_eq!(MAC_ROMAN_HIGH.chars().count(), 128);
+        assert_eq!(BaseEncoding::MacRomanEncoding.decode(0xFF), Some('ˇ'));
+        assert_eq!(BaseEncoding::WinAnsiEncoding.decode(0x80), Some('€'));
+        assert_eq!(BaseEncoding::StandardEncoding.decode(0x27), Some('’'));
+
+        let mut encoding = Encoding::standard();
+        encoding.differences.insert(0x41, "Adieresis".into());
+        encoding.differences.insert(0x42, "f_f_i".into());
+        encoding.differences.insert(0x43, "uni00410042".into());
+        encoding.differences.insert(0x44, "a.sc".into());
+        encoding.differences.insert(0x45, "g123".into());
+        assert_eq!(encoding.decode(0x41).as_deref(), Some("Ä"));
+        assert_eq!(encoding.decode(0x42).as_deref(), Some("ffi"));
+        assert_eq!(encoding.decode(0x43).as_deref(), Some("AB"));
+        assert_eq!(encoding.decode(0x44).as_deref(), Some("a"));
+        assert_eq!(encoding.decode(0x45), None);
+        assert_eq!(encoding.decode(0x46).as_deref(), Some("F"));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::object::ObjNr;
+use crate::object::{ObjNr, PlainRef};
 use std::io;
 use std::error::Error;
 use crate::parser::ParseFlags;
@@ -131,6 +131,18 @@ pub enum PdfError {
     #[snafu(display("Invalid password"))]
     InvalidPassword,
 
+    #[snafu(display("The document is encrypted and requires a password"))]
+    PasswordRequired,
+
+    #[snafu(display("The document is encrypted for certificate recipients and requires a private key"))]
+    RecipientRequired,
+
+    #[snafu(display("The certificate is not among the recipients of the document"))]
+    NotARecipient,
+
+    #[snafu(display("Removing or changing the encryption requires the owner password"))]
+    OwnerPasswordRequired,
+
     #[snafu(display("Decryption failure"))]
     DecryptionFailure,
 
@@ -149,6 +161,13 @@ pub enum PdfError {
     #[snafu(display("{}", msg))]
     Other { msg: String },
 
+    #[snafu(display("{}, caused by\n  {}", location, source))]
+    Located {
+        location: Location,
+        #[snafu(source)]
+        source: Box<PdfError>
+    },
+
     #[snafu(display("NoneError at {}:{}:{}:{}", file, line, column, context))]
     NoneError { file: &'static str, line: u32, column: u32, context: Context },
 
@@ -180,6 +199,29 @@ pub enum PdfError {
     #[snafu(display("Max nesting depth reached"))]
     MaxDepth,
 
+    //////////////////
+    // Limits
+    #[snafu(display("Decoded stream exceeds the limit of {} bytes", max))]
+    StreamTooLarge { max: usize },
+
+    #[snafu(display("Decoded streams exceed the limit of {} bytes for the document", max))]
+    DecodedBytesExceeded { max: usize },
+
+    #[snafu(display("{} objects exceed the limit of {}", count, max))]
+    TooManyObjects { count: usize, max: usize },
+
+    #[snafu(display("Xref table of {} entries exceeds the limit of {}", size, max))]
+    XRefTooLarge { size: usize, max: usize },
+
+    #[snafu(display("Array exceeds the limit of {} elements", max))]
+    ArrayTooLong { max: usize },
+
+    #[snafu(display("Dictionary exceeds the limit of {} entries", max))]
+    DictionaryTooLong { max: usize },
+
+    #[snafu(display("String exceeds the limit of {} bytes", max))]
+    StringTooLong { max: usize },
+
     #[snafu(display("Invalid"))]
     Invalid,
 }
@@ -187,10 +229,94 @@ impl PdfError {
     pub fn is_eof(&self) -> bool {
         match self {
             PdfError::EOF => true,
-            PdfError::Try { ref source, .. } => source.is_eof(),
+            PdfError::Try { ref source, .. } |
+            PdfError::Located { ref source, .. } => source.is_eof(),
+            _ => false
+        }
+    }
+    /// Whether this error, or the error that caused it, is due to one of the `Limits`.
+    pub fn is_limit_exceeded(&self) -> bool {
+        match self {
+            PdfError::StreamTooLarge { .. } |
+            PdfError::DecodedBytesExceeded { .. } |
+            PdfError::TooManyObjects { .. } |
+            PdfError::XRefTooLarge { .. } |
+            PdfError::ArrayTooLong { .. } |
+            PdfError::DictionaryTooLong { .. } |
+            PdfError::StringTooLong { .. } => true,
+            PdfError::Try { ref source, .. } |
+            PdfError::FromPrimitive { ref source, .. } |
+            PdfError::Located { ref source, .. } => source.is_limit_exceeded(),
+            PdfError::Shared { ref source } => source.is_limit_exceeded(),
             _ => false
         }
     }
+
+    /// Where in the document the error happened, collected from all the errors that caused it.
+    /// The path starts at the outermost object, the object and offset are the innermost ones known.
+    pub fn location(&self) -> Option<Location> {
+        let mut location: Option<Location> = None;
+        let mut error = self;
+        loop {
+            error = match error {
+                PdfError::Located { location: inner, source } => {
+                    let merged = location.get_or_insert_with(Location::default);
+                    merged.path.extend(inner.path.iter().cloned());
+                    merged.object = inner.object.or(merged.object);
+                    merged.offset = inner.offset.or(merged.offset);
+                    source
+                }
+                PdfError::Try { source, .. } |
+                PdfError::FromPrimitive { source, .. } => source,
+                PdfError::Shared { source } => source,
+                _ => return location,
+            };
+        }
+    }
+    /// The error without the `Located` wrappers around it.
+    pub fn without_location(&self) -> &PdfError {
+        match self {
+            PdfError::Located { source, .. } => source.without_location(),
+            e => e
+        }
+    }
+
+    /// Prepend the dictionary key `key` to the path of the error.
+    pub fn with_key(self, key: &str) -> PdfError {
+        self.locate(|l| l.path.insert(0, PathSegment::Key(key.into())))
+    }
+    /// Prepend the array index `index` to the path of the error.
+    pub fn with_index(self, index: usize) -> PdfError {
+        self.locate(|l| l.path.insert(0, PathSegment::Index(index)))
+    }
+    /// Record the object being read, unless the error already happened in a nested one.
+    pub fn in_object(self, r: PlainRef) -> PdfError {
+        self.locate(|l| { l.object.get_or_insert(r); })
+    }
+    /// Record the byte offset of the primitive that failed to parse, unless a nested one is known.
+    pub fn at_offset(self, offset: usize) -> PdfError {
+        self.locate(|l| { l.offset.get_or_insert(offset); })
+    }
+    // Update the location of the error, or add one.
+    // Errors shared through the cache can't be changed, those get a new location around them.
+    fn locate(self, f: impl FnOnce(&mut Location)) -> PdfError {
+        fn find(e: &mut PdfError) -> Option<&mut Location> {
+            match e {
+                PdfError::Located { location, .. } => Some(location),
+                PdfError::Try { source, .. } |
+                PdfError::FromPrimitive { source, .. } => find(source),
+                _ => None
+            }
+        }
+        let mut error = self;
+        if let Some(location) = find(&mut error) {
+            f(location);
+            return error;
+        }
+        let mut location = Location::default();
+        f(&mut location);
+        PdfError::Located { location, source: Box::new(error) }
+    }
 }
 datasize::non_dynamic_const_heap_size!(PdfError, 0);
 
@@ -202,6 +328,48 @@ impl globalcache::ValueSize for PdfError {
     }
 }
 
+/// Where in the document an error happened.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    /// The object being read.
+    pub object: Option<PlainRef>,
+    /// Byte offset of the primitive that failed to parse. It is relative to the decoded stream for content
+    /// streams and objects in object streams, and to the file otherwise.
+    pub offset: Option<usize>,
+    /// Dictionary keys and array indices leading to the value, like `Root/AcroForm/Fields[3]/DA`.
+    pub path: Vec<PathSegment>,
+}
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut parts = vec![];
+        if !self.path.is_empty() {
+            let mut path = String::new();
+            for segment in &self.path {
+                match segment {
+                    PathSegment::Key(key) if path.is_empty() => path.push_str(key),
+                    PathSegment::Key(key) => { path.push('/'); path.push_str(key); }
+                    PathSegment::Index(index) => path.push_str(&format!("[{}]", index)),
+                }
+            }
+            parts.push(format!("at {}", path));
+        }
+        if let Some(r) = self.object {
+            parts.push(format!("in object {} {} R", r.id, r.gen));
+        }
+        if let Some(offset) = self.offset {
+            parts.push(format!("at byte {}", offset));
+        }
+        write!(f, "Error {}", parts.join(" "))
+    }
+}
+
+/// One step of a [`Location`] path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
 #[derive(Debug)]
 pub struct Context(pub Vec<(&'static str, String)>);
 impl std::fmt::Display for Context {
@@ -1,22 +1,24 @@
 //! This is kind of the entry-point of the type-safe PDF functionality.
 use std::marker::PhantomData;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::path::Path;
 use std::io::Write;
 
 use crate as pdf;
 use crate::error::*;
 use crate::object::*;
-use crate::primitive::{Primitive, Dictionary, PdfString};
+use crate::primitive::{Primitive, Dictionary, PdfString, PdfStream, StreamInner};
 use crate::backend::Backend;
 use crate::any::*;
 use crate::parser::{Lexer, parse_with_lexer};
-use crate::parser::{parse_indirect_object, parse, ParseFlags};
-use crate::xref::{XRef, XRefTable, XRefInfo};
+use crate::parser::{parse_indirect_object, parse, ParseFlags, check_xref_size};
+use crate::xref::{XRef, XRefTable};
 use crate::crypt::Decoder;
-use crate::crypt::CryptDict;
-use crate::enc::{StreamFilter, decode};
+use crate::repair;
+use crate::crypt::{CryptDict, Encryption, Permissions};
+use crate::enc::{StreamFilter, Decoders};
 use std::ops::Range;
 use datasize::DataSize;
 
@@ -78,6 +80,9 @@ pub struct Storage<B, OC, SC, L> {
 
     decoder:    Option<Decoder>,
     options:    ParseOptions,
+    decoders:   Decoders,
+    // decoded bytes of all streams, for `Limits::max_decoded_bytes`
+    decoded_bytes: AtomicUsize,
 
     backend:    B,
 
@@ -101,6 +106,8 @@ where
             refs: XRefTable::new(0),
             decoder: None,
             options: ParseOptions::strict(),
+            decoders: Decoders::new(),
+            decoded_bytes: AtomicUsize::new(0),
             backend: Vec::from(&b"%PDF-1.7\n"[..]),
             start_offset: 0,
             log
@@ -119,11 +126,22 @@ where
         self.backend
     }
     pub fn version(&self) -> Result<String> {
-        Ok(String::from_utf8(self.backend.read(self.start_offset+1..self.start_offset+8)?.to_owned())?)
+        Ok(String::from_utf8(self.backend.read(self.start_offset+1..self.start_offset+8)?.into_owned())?)
     }
     pub fn resolver(&self) -> impl Resolve + '_ {
         StorageResolver::new(self)
     }
+    /// The number of entries of the xref table, including promised objects.
+    pub(crate) fn num_objects(&self) -> usize {
+        self.refs.len()
+    }
+    /// Drop the objects created or promised since there were `num_objects` of them.
+    pub(crate) fn truncate(&mut self, num_objects: usize) {
+        self.refs.truncate(num_objects);
+        self.changes.retain(|&id, _| (id as usize) < num_objects);
+        self.cache.clear();
+        self.stream_cache.clear();
+    }
     pub fn with_cache(backend: B, options: ParseOptions, object_cache: OC, stream_cache: SC, log: L) -> Result<Self> {
         let start_offset = backend.locate_start_offset()?;
 
@@ -136,31 +154,74 @@ where
             changes: HashMap::new(),
             decoder: None,
             options,
+            decoders: Decoders::new(),
+            decoded_bytes: AtomicUsize::new(0),
             log
         })
     }
     fn decode(&self, id: PlainRef, range: Range<usize>, filters: &[StreamFilter]) -> Result<Arc<[u8]>> {
-        let data = self.backend.read(range)?;
-
-        let mut data = Vec::from(data);
+        let mut data = self.backend.read(range)?.into_owned();
         if let Some(ref decoder) = self.decoder {
             data = Vec::from(t!(decoder.decrypt(id, &mut data)));
         }
+        if filters.is_empty() {
+            return Ok(data.into());
+        }
+        let limit = self.options.limits.max_stream_size.unwrap_or(usize::MAX);
         for filter in filters {
-            data = t!(decode(&data, filter), filter);
+            data = t!(self.decoders.decode_limited(&data, filter, limit), filter);
         }
+        self.count_decoded(data.len())?;
         Ok(data.into())
     }
+    fn count_decoded(&self, len: usize) -> Result<()> {
+        let total = self.decoded_bytes.fetch_add(len, Ordering::Relaxed) + len;
+        if let Some(max) = self.options.limits.max_decoded_bytes {
+            if total > max {
+                return Err(PdfError::DecodedBytesExceeded { max });
+            }
+        }
+        Ok(())
+    }
 
     pub fn load_storage_and_trailer(&mut self) -> Result<Dictionary> {
         self.load_storage_and_trailer_password(b"")
     }
 
     pub fn load_storage_and_trailer_password(&mut self, password: &[u8]) -> Result<Dictionary> {
+        self.load_storage_and_trailer_with(password, None)
+    }
+
+    /// Load a file encrypted with the public-key security handler,
+    /// given the PEM or DER encoded private key and certificate of a recipient.
+    pub fn load_storage_and_trailer_recipient(&mut self, private_key: &[u8], certificate: &[u8]) -> Result<Dictionary> {
+        self.load_storage_and_trailer_with(b"", Some((private_key, certificate)))
+    }
+
+    fn load_storage_and_trailer_with(&mut self, password: &[u8], recipient: Option<(&[u8], &[u8])>) -> Result<Dictionary> {
 
         let resolver = StorageResolver::new(self);
-        let (refs, trailer) = t!(self.backend.read_xref_table_and_trailer(self.start_offset, &resolver));
+        let (refs, trailer, repaired) = match self.backend.read_xref_table_and_trailer(self.start_offset, &resolver) {
+            Ok((refs, trailer)) => (refs, Some(trailer), false),
+            Err(e) if self.options.allow_xref_error => {
+                warn!("can't read xref table, rebuilding it: {}", e);
+                let data = t!(self.backend.read(self.start_offset ..));
+                let refs = t!(repair::build_xref_table(&data), e);
+                t!(check_xref_size(refs.len(), &resolver));
+                (refs, None, true)
+            }
+            Err(e) => t!(Err(e)),
+        };
         self.refs = refs;
+        self.check_object_count()?;
+        let trailer = match trailer {
+            Some(trailer) => trailer,
+            None => {
+                self.add_object_stream_entries()?;
+                let data = t!(self.backend.read(self.start_offset ..));
+                t!(repair::build_trailer(&data, &self.refs, &StorageResolver::new(self)))
+            }
+        };
 
         if let Some(crypt) = trailer.get("Encrypt") {
             let key = trailer
@@ -181,10 +242,36 @@ where
             let resolver = StorageResolver::new(self);
             let dict = CryptDict::from_primitive(crypt.clone(), &resolver)?;
 
-            self.decoder = Some(t!(Decoder::from_password(&dict, key, password)));
+            // password errors are returned as-is, so callers can tell them apart
+            self.decoder = Some(match (dict.filter().as_str(), recipient) {
+                ("Adobe.PubSec", None) => return Err(PdfError::RecipientRequired),
+                #[cfg(feature = "pubsec")]
+                ("Adobe.PubSec", Some((private_key, certificate))) => {
+                    let recipient = t!(crate::crypt::Recipient::new(private_key, certificate));
+                    match Decoder::from_recipient(&dict, &recipient) {
+                        Ok(decoder) => decoder,
+                        Err(e @ PdfError::NotARecipient) => return Err(e),
+                        Err(e) => t!(Err(e)),
+                    }
+                }
+                #[cfg(not(feature = "pubsec"))]
+                ("Adobe.PubSec", Some(_)) => bail!("the public-key security handler needs the `pubsec` feature"),
+                _ => match Decoder::from_password(&dict, key, password) {
+                    Ok(decoder) => decoder,
+                    Err(PdfError::InvalidPassword) if password.is_empty() => return Err(PdfError::PasswordRequired),
+                    Err(e @ PdfError::InvalidPassword) => return Err(e),
+                    Err(e) => t!(Err(e)),
+                }
+            });
             if let Primitive::Reference(reference) = crypt {
                 self.decoder.as_mut().unwrap().encrypt_indirect_object = Some(*reference);
             }
+            if repaired {
+                // encrypted object streams can only be read with the decoder
+                self.cache.clear();
+                self.stream_cache.clear();
+                self.add_object_stream_entries()?;
+            }
             if let Some(Primitive::Reference(catalog_ref)) = trailer.get("Root") {
                 let resolver = StorageResolver::new(self);
                 let catalog = t!(t!(resolver.resolve(*catalog_ref)).resolve(&resolver)?.into_dictionary());
@@ -195,10 +282,30 @@ where
         }
         Ok(trailer)
     }
+    fn add_object_stream_entries(&mut self) -> Result<()> {
+        let entries = t!(repair::object_stream_entries(&self.refs, &StorageResolver::new(self)));
+        for (id, entry) in entries {
+            t!(check_xref_size(id as usize + 1, &StorageResolver::new(self)));
+            while self.refs.len() <= id as usize {
+                self.refs.push(XRef::Free { next_obj_nr: 0, gen_nr: 0 });
+            }
+            self.refs.set(id, entry);
+        }
+        self.check_object_count()
+    }
+    fn check_object_count(&self) -> Result<()> {
+        if let Some(max) = self.options.limits.max_objects {
+            let count = self.refs.iter().count();
+            if count > max {
+                return Err(PdfError::TooManyObjects { count, max });
+            }
+        }
+        Ok(())
+    }
     pub fn scan(&self) -> impl Iterator<Item = Result<ScanItem>> + '_ {
         let xref_offset = self.backend.locate_xref_offset().unwrap();
-        let slice = self.backend.read(self.start_offset .. xref_offset).unwrap();
-        let mut lexer = Lexer::with_offset(slice, 0);
+        let data = self.backend.read(self.start_offset .. xref_offset).unwrap();
+        let mut next_pos = 0;
         
         fn skip_xref(lexer: &mut Lexer) -> Result<()> {
             while lexer.next()? != "trailer" {
@@ -209,11 +316,13 @@ where
 
         let resolver = StorageResolver::new(self);
         std::iter::from_fn(move || {
-            loop {
+            let mut lexer = Lexer::with_offset(&data, 0);
+            lexer.set_pos(next_pos);
+            let item = loop {
                 let pos = lexer.get_pos();
                 match parse_indirect_object(&mut lexer, &resolver, self.decoder.as_ref(), ParseFlags::all()) {
-                    Ok((r, p)) => return Some(Ok(ScanItem::Object(r, p))),
-                    Err(e) if e.is_eof() => return None,
+                    Ok((r, p)) => break Some(Ok(ScanItem::Object(r, p))),
+                    Err(e) if e.is_eof() => break None,
                     Err(e) => {
                         lexer.set_pos(pos);
                         if let Ok(s) = lexer.next() {
@@ -221,10 +330,10 @@ where
                             match &*s {
                                 b"xref" => {
                                     if let Err(e) = skip_xref(&mut lexer) {
-                                        return Some(Err(e));
+                                        break Some(Err(e));
                                     }
                                     if let Ok(trailer) = parse_with_lexer(&mut lexer, &NoResolve, ParseFlags::DICT).and_then(|p| p.into_dictionary()) {
-                                        return Some(Ok(ScanItem::Trailer(trailer)));
+                                        break Some(Ok(ScanItem::Trailer(trailer)));
                                     }
                                 }
                                 b"startxref" if lexer.next().is_ok() => {
@@ -233,10 +342,12 @@ where
                                 _ => {}
                             }
                         }
-                        return Some(Err(e));
+                        break Some(Err(e));
                     }
                 }
-            }
+            };
+            next_pos = lexer.get_pos();
+            item
         })
     }
     fn resolve_ref(&self, r: PlainRef, flags: ParseFlags, resolve: &impl Resolve) -> Result<Primitive> {
@@ -244,8 +355,8 @@ where
             Some((p, _)) => Ok((*p).clone()),
             None => match t!(self.refs.get(r.id)) {
                 XRef::Raw {pos, ..} => {
-                    let mut lexer = Lexer::with_offset(t!(self.backend.read(self.start_offset + pos ..)), self.start_offset + pos);
-                    let p = t!(parse_indirect_object(&mut lexer, resolve, self.decoder.as_ref(), flags)).1;
+                    let p = self.backend.lex_at(self.start_offset + pos, |lexer| parse_indirect_object(lexer, resolve, self.decoder.as_ref(), flags));
+                    let p = t!(p.map_err(|e| e.in_object(r))).1;
                     Ok(p)
                 }
                 XRef::Stream {stream_id, index} => {
@@ -257,7 +368,7 @@ where
 
                     let (data, range) = t!(obj_stream.get_object_slice(index, resolve));
                     let slice = data.get(range.clone()).ok_or_else(|| other!("invalid range {:?}, but only have {} bytes", range, data.len()))?;
-                    parse(slice, resolve, flags)
+                    parse(slice, resolve, flags).map_err(|e| e.in_object(r))
                 }
                 XRef::Free {..} => err!(PdfError::FreeObject {obj_nr: r.id}),
                 XRef::Promised => unimplemented!(),
@@ -329,7 +440,7 @@ where
                 Err(e) => {
                     let p = self.resolve(key);
                     warn!("failed to decode {p:?} as {}", std::any::type_name::<T>());
-                    Err(Arc::new(e))
+                    Err(Arc::new(e.in_object(key)))
                 }
             }
         });
@@ -357,6 +468,12 @@ where
         self.storage.stream_cache.get_or_compute(id, || self.storage.decode(id, range, filters).map_err(Arc::new))
         .map_err(|e| e.into())
     }
+    fn decoders(&self) -> &Decoders {
+        &self.storage.decoders
+    }
+    fn count_decoded(&self, len: usize) -> Result<()> {
+        self.storage.count_decoded(len)
+    }
 }
 
 impl<B, OC, SC, L> Updater for Storage<B, OC, SC, L>
@@ -433,47 +550,295 @@ where
     L: Log
 {
     pub fn save(&mut self, trailer: &mut Trailer) -> Result<&[u8]> {
+        self.save_with(trailer, &SaveOptions::default())
+    }
+
+    /// Append the changes as an incremental update, written according to `options`.
+    pub fn save_with(&mut self, trailer: &mut Trailer, options: &SaveOptions) -> Result<&[u8]> {
+        let (update, refs, trailer_dict) = self.write_update(trailer, options)?;
+        self.backend.extend_from_slice(&update);
+        self.refs = refs;
+
+        // update trailer which may have change now.
+        self.cache.clear();
+        *trailer = Trailer::from_dict(trailer_dict, &self.resolver())?;
+
+        Ok(&self.backend)
+    }
+}
+
+impl<B, OC, SC, L> Storage<B, OC, SC, L>
+where
+    B: Backend,
+    OC: Cache<Result<AnySync, Arc<PdfError>>>,
+    SC: Cache<Result<Arc<[u8]>, Arc<PdfError>>>,
+    L: Log
+{
+    /// Write the file with the changes appended as an incremental update to `out`.
+    ///
+    /// The data of the backend is copied as it is, so it never has to be in memory as a whole.
+    /// Unlike `save`, this leaves the backend and the xref table alone.
+    pub fn save_to_writer(&mut self, trailer: &mut Trailer, options: &SaveOptions, out: &mut impl Write) -> Result<()> {
+        let (update, _, _) = self.write_update(trailer, options)?;
+        self.backend.copy_to(.., out)?;
+        out.write_all(&update)?;
+        Ok(())
+    }
+
+    /// The incremental update that follows the backend data, with the xref table
+    /// and trailer dictionary of the updated file.
+    fn write_update(&mut self, trailer: &mut Trailer, options: &SaveOptions) -> Result<(Vec<u8>, XRefTable, Dictionary)> {
         // writing the trailer generates another id for the info dictionary
         trailer.size = (self.refs.len() + 2) as _;
         let trailer_dict = trailer.to_dict(self)?;
-        
-        let xref_promise = self.promise::<Stream<XRefInfo>>();
 
-        let mut changes: Vec<_> = self.changes.iter().collect();
-        changes.sort_unstable_by_key(|&(id, _)| id);
+        let changes: Vec<_> = self.changes.iter().map(|(&id, &(ref p, gen))| (PlainRef { id, gen }, p.clone())).collect();
+        self.write_changes(changes, trailer_dict, options)
+    }
+
+    /// Whether the last cross-reference section of the backend is an xref stream.
+    fn ends_with_xref_stream(&self) -> bool {
+        let pos = match self.backend.locate_xref_offset() {
+            Ok(pos) => self.start_offset + pos,
+            Err(_) => return false,
+        };
+        match self.backend.read(pos .. self.backend.len().min(pos + 32)) {
+            Ok(data) => !data.trim_ascii_start().starts_with(b"xref"),
+            Err(_) => false,
+        }
+    }
 
-        for &(&id, &(ref primitive, gen)) in changes.iter() {
-            let pos = self.backend.len();
-            self.refs.set(id, XRef::Raw { pos: pos as _, gen_nr: gen });
-            writeln!(self.backend, "{} {} obj", id, gen)?;
-            primitive.serialize(&mut self.backend)?;
-            writeln!(self.backend, "endobj")?;
+    /// Like `write_update`, for the given `changes` instead of the changes of the storage.
+    fn write_changes(&self, mut changes: Vec<(PlainRef, Primitive)>, mut trailer_dict: Dictionary, options: &SaveOptions) -> Result<(Vec<u8>, XRefTable, Dictionary)> {
+        changes.sort_unstable_by_key(|&(r, _)| r.id);
+        if options.xref_table {
+            options.check_xref_table()?;
+            // the trailer of a classic table isn't merged with the /Prev xref stream by all readers
+            if self.ends_with_xref_stream() {
+                bail!("the document uses an xref stream, which can't be followed by a classic xref table");
+            }
+            // unchanged objects stay where they are
+            let changed = |id: ObjNr| changes.binary_search_by_key(&id, |&(r, _)| r.id).is_ok();
+            if let Some(id) = (0 .. self.refs.len() as ObjNr).find(|&id| matches!(self.refs.get(id), Ok(XRef::Stream { .. })) && !changed(id)) {
+                bail!("object {} is in an object stream, which a classic xref table can't refer to", id);
+            }
         }
 
-        let xref_pos = self.backend.len();
-        self.refs.set(xref_promise.get_inner().id, XRef::Raw { pos: xref_pos, gen_nr: 0 });
-        // only write up to the xref stream obj id
-        let stream = self.refs.write_stream(xref_promise.get_inner().id as usize + 1)?;
+        // new objects of an encrypted file have to be encrypted as well
+        let offset = self.backend.len();
+        let mut refs = self.refs.clone();
+        let mut out = Vec::new();
+        let entries = write_objects(&mut out, offset, &changes, refs.len() as _, options, self.decoder.as_ref(), &StorageResolver::new(self))?;
+        for (id, entry) in entries {
+            match id as usize {
+                n if n < refs.len() => refs.set(id, entry),
+                _ => refs.push(entry),
+            }
+        }
+
+        write_xref(&mut out, offset, &mut refs, &mut trailer_dict, options)?;
+        Ok((out, refs, trailer_dict))
+    }
+}
+
+/// How a document is written.
+#[derive(Debug, Clone, Default)]
+pub struct SaveOptions {
+    objects_per_stream: Option<usize>,
+    xref_table: bool,
+}
+impl SaveOptions {
+    pub fn new() -> Self {
+        SaveOptions::default()
+    }
+    /// Pack objects that are not streams into Flate compressed object streams,
+    /// with at most `objects_per_stream` objects in each of them.
+    pub fn object_streams(mut self, objects_per_stream: usize) -> Self {
+        self.objects_per_stream = Some(objects_per_stream.max(1));
+        self
+    }
+    /// Write a classic cross-reference table and trailer dictionary instead of an
+    /// xref stream, as understood by PDF 1.4 readers.
+    ///
+    /// Rewritten documents get a `%PDF-1.4` header unless their encryption needs a later version.
+    /// Saving fails if the document needs object streams, or if an incremental update
+    /// would follow an xref stream.
+    pub fn xref_table(mut self, xref_table: bool) -> Self {
+        self.xref_table = xref_table;
+        self
+    }
+    fn check_xref_table(&self) -> Result<()> {
+        if self.objects_per_stream.is_some() {
+            bail!("object streams can't be written with a classic xref table");
+        }
+        Ok(())
+    }
+}
 
-        writeln!(self.backend, "{} {} obj", xref_promise.get_inner().id, 0)?;
-        let mut xref_and_trailer = stream.to_pdf_stream(&mut NoUpdate)?;
-        for (k, v) in trailer_dict.iter() {
+/// Write the cross-reference section for `refs` and the `trailer`, followed by `startxref`,
+/// to `out`, which starts at `offset` in the file.
+///
+/// An xref stream gets the next free object number.
+fn write_xref(out: &mut Vec<u8>, offset: usize, refs: &mut XRefTable, trailer: &mut Dictionary, options: &SaveOptions) -> Result<()> {
+    let xref_pos = offset + out.len();
+    if options.xref_table {
+        trailer.insert("Size", Primitive::Integer(refs.len() as _));
+        refs.write_table(refs.len(), out)?;
+        writeln!(out, "trailer")?;
+        Primitive::Dictionary(trailer.clone()).serialize(out)?;
+    } else {
+        let xref_id = refs.len() as ObjNr;
+        refs.push(XRef::Raw { pos: xref_pos, gen_nr: 0 });
+        trailer.insert("Size", Primitive::Integer(refs.len() as _));
+        let mut xref_and_trailer = refs.write_stream(refs.len())?.to_pdf_stream(&mut NoUpdate)?;
+        for (k, v) in trailer.iter() {
             xref_and_trailer.info.insert(k.clone(), v.clone());
         }
+        writeln!(out, "{} 0 obj", xref_id)?;
+        xref_and_trailer.serialize(out)?;
+        writeln!(out, "endobj")?;
+    }
+    write!(out, "\nstartxref\n{}\n%%EOF", xref_pos)?;
+    Ok(())
+}
 
-        xref_and_trailer.serialize(&mut self.backend)?;
-        writeln!(self.backend, "endobj")?;
+/// Write `objects` to `out`, which starts at `offset` in the file, and return their xref entries.
+///
+/// If object streams are enabled, the objects that can be compressed are packed into new
+/// object streams with ids from `next_id` on, which are part of the returned entries.
+fn write_objects(out: &mut Vec<u8>, offset: usize, objects: &[(PlainRef, Primitive)], next_id: ObjNr, options: &SaveOptions, decoder: Option<&Decoder>, resolve: &impl Resolve) -> Result<Vec<(ObjNr, XRef)>> {
+    let encrypt_ref = decoder.and_then(|d| d.encrypt_indirect_object);
+    let mut entries = Vec::with_capacity(objects.len());
+    let mut packed = Vec::new();
+    for &(r, ref p) in objects {
+        // only objects of generation 0 can be stored in object streams, and neither streams nor the encryption dictionary
+        let compressible = r.gen == 0 && Some(r) != encrypt_ref && !matches!(p, Primitive::Stream(_));
+        if options.objects_per_stream.is_some() && compressible {
+            packed.push((r.id, p));
+            continue;
+        }
+        entries.push((r.id, XRef::Raw { pos: offset + out.len(), gen_nr: r.gen }));
+        write_object(out, r, p, decoder, resolve)?;
+    }
+    if let Some(objects_per_stream) = options.objects_per_stream {
+        for (stream_id, chunk) in (next_id ..).zip(packed.chunks(objects_per_stream)) {
+            for (index, &(id, _)) in chunk.iter().enumerate() {
+                entries.push((id, XRef::Stream { stream_id, index }));
+            }
+            let stream = ObjectStream::pack(chunk.iter().copied())?.to_pdf_stream(&mut NoUpdate)?;
+            entries.push((stream_id, XRef::Raw { pos: offset + out.len(), gen_nr: 0 }));
+            write_object(out, PlainRef { id: stream_id, gen: 0 }, &Primitive::Stream(stream), decoder, resolve)?;
+        }
+    }
+    Ok(entries)
+}
 
-        let _ = self.fulfill(xref_promise, stream)?;
+/// The major and minor version of a `PDF-x.y` header.
+fn parse_version(version: &str) -> Option<(u8, u8)> {
+    let (major, minor) = version.strip_prefix("PDF-")?.split_once('.')?;
+    Some((major.parse().ok()?, minor.trim().parse().ok()?))
+}
 
-        write!(self.backend, "\nstartxref\n{}\n%%EOF", xref_pos).unwrap();
+/// What happens to the encryption of a document when it is rewritten.
+#[derive(Clone, Copy)]
+enum Protection<'a> {
+    Decrypt,
+    Keep,
+    Encrypt(&'a Encryption),
+}
 
-        // update trailer which may have change now.
-        self.cache.clear();
-        *trailer = Trailer::from_dict(trailer_dict, &self.resolver())?;
+/// Keep the objects that can be reached from the references in `trailer` and number them
+/// densely, in the order they are reached. References to missing objects become null.
+fn compact_objects(objects: Vec<(PlainRef, Primitive)>, trailer: &mut Dictionary) -> Vec<(PlainRef, Primitive)> {
+    let mut remaining: HashMap<ObjNr, Primitive> = objects.into_iter().map(|(r, p)| (r.id, p)).collect();
+    let mut new_ids = HashMap::new();
+    let mut reached = Vec::new();
+    let mut queue = VecDeque::new();
+    trailer.values().for_each(|p| for_each_ref(p, &mut |r| queue.push_back(r.id)));
+    while let Some(id) = queue.pop_front() {
+        if let Some(p) = remaining.remove(&id) {
+            new_ids.insert(id, reached.len() as ObjNr + 1);
+            for_each_ref(&p, &mut |r| queue.push_back(r.id));
+            reached.push(p);
+        }
+    }
 
-        Ok(&self.backend)
+    let mut renumber = |r: PlainRef| match new_ids.get(&r.id) {
+        Some(&id) => Primitive::Reference(PlainRef { id, gen: 0 }),
+        None => Primitive::Null
+    };
+    *trailer = replace_dict_refs(std::mem::take(trailer), &mut renumber);
+    reached.into_iter().enumerate()
+        .map(|(i, p)| (PlainRef { id: i as ObjNr + 1, gen: 0 }, replace_refs(p, &mut renumber)))
+        .collect()
+}
+
+fn for_each_ref(p: &Primitive, f: &mut impl FnMut(PlainRef)) {
+    match *p {
+        Primitive::Reference(r) => f(r),
+        Primitive::Array(ref parts) => parts.iter().for_each(|p| for_each_ref(p, f)),
+        Primitive::Dictionary(ref dict) => dict.values().for_each(|p| for_each_ref(p, f)),
+        Primitive::Stream(ref stream) => stream.info.values().for_each(|p| for_each_ref(p, f)),
+        _ => {}
+    }
+}
+fn replace_refs(p: Primitive, f: &mut impl FnMut(PlainRef) -> Primitive) -> Primitive {
+    match p {
+        Primitive::Reference(r) => f(r),
+        Primitive::Array(parts) => Primitive::Array(parts.into_iter().map(|p| replace_refs(p, f)).collect()),
+        Primitive::Dictionary(dict) => Primitive::Dictionary(replace_dict_refs(dict, f)),
+        Primitive::Stream(PdfStream { info, inner }) => Primitive::Stream(PdfStream { info: replace_dict_refs(info, f), inner }),
+        p => p
+    }
+}
+fn replace_dict_refs(dict: Dictionary, f: &mut impl FnMut(PlainRef) -> Primitive) -> Dictionary {
+    let mut new = Dictionary::new();
+    for (key, val) in dict {
+        new.insert(key, replace_refs(val, f));
+    }
+    new
+}
+
+/// Write `p` as the indirect object `r`, encrypted if a decoder is given.
+fn write_object(out: &mut Vec<u8>, r: PlainRef, p: &Primitive, decoder: Option<&Decoder>, resolve: &impl Resolve) -> Result<()> {
+    let p = match (decoder, p) {
+        (Some(decoder), p) => encrypt_primitive(decoder, r, p, resolve)?,
+        // stream data may still live in the old file
+        (None, Primitive::Stream(stream)) => {
+            let data = stream.raw_data(resolve)?;
+            let mut info = stream.info.clone();
+            info.insert("Length", Primitive::Integer(data.len() as _));
+            Primitive::Stream(PdfStream { info, inner: StreamInner::Pending { data } })
+        }
+        (None, p) => p.clone(),
+    };
+    writeln!(out, "{} {} obj", r.id, r.gen)?;
+    p.serialize(out)?;
+    writeln!(out, "endobj")?;
+    Ok(())
+}
+
+/// Encrypt all strings and stream data of the object `id`, as it has to be written to an encrypted file.
+fn encrypt_primitive(decoder: &Decoder, id: PlainRef, p: &Primitive, resolve: &impl Resolve) -> Result<Primitive> {
+    Ok(match *p {
+        Primitive::String(ref s) => Primitive::String(PdfString::new(decoder.encrypt(id, s.as_bytes())?.into())),
+        Primitive::Array(ref parts) => Primitive::Array(parts.iter().map(|p| encrypt_primitive(decoder, id, p, resolve)).collect::<Result<_>>()?),
+        Primitive::Dictionary(ref dict) => Primitive::Dictionary(encrypt_dict(decoder, id, dict, resolve)?),
+        Primitive::Stream(ref stream) => {
+            let mut info = encrypt_dict(decoder, id, &stream.info, resolve)?;
+            let data = decoder.encrypt(id, &stream.raw_data(resolve)?)?;
+            info.insert("Length", Primitive::Integer(data.len() as _));
+            Primitive::Stream(PdfStream { info, inner: StreamInner::Pending { data: data.into() } })
+        }
+        ref p => p.clone()
+    })
+}
+fn encrypt_dict(decoder: &Decoder, id: PlainRef, dict: &Dictionary, resolve: &impl Resolve) -> Result<Dictionary> {
+    let mut new = Dictionary::new();
+    for (key, val) in dict.iter() {
+        new.insert(key.clone(), encrypt_primitive(decoder, id, val, resolve)?);
     }
+    Ok(new)
 }
 
 #[cfg(feature="cache")]
@@ -486,6 +851,10 @@ pub type CachedFile<B> = File<B, ObjectCache, StreamCache, NoLog>;
 pub struct File<B, OC, SC, L> {
     storage:        Storage<B, OC, SC, L>,
     pub trailer:    Trailer,
+    // the object the trailer /Info was read from
+    info_ref:       Option<PlainRef>,
+    // the trailer as read from the file
+    trailer_dict:   Dictionary,
 }
 impl<B, OC, SC, L> Updater for File<B, OC, SC, L>
 where
@@ -522,6 +891,11 @@ where
     pub fn save(&mut self) -> Result<Vec<u8>> {
         Ok(self.storage.save(&mut self.trailer)?.to_vec())
     }
+
+    /// Like `save`, with the changes written according to `options`.
+    pub fn save_with(&mut self, options: &SaveOptions) -> Result<Vec<u8>> {
+        Ok(self.storage.save_with(&mut self.trailer, options)?.to_vec())
+    }
 }
 
 
@@ -530,7 +904,9 @@ pub struct FileOptions<'a, OC, SC, L> {
     sc: SC,
     log: L,
     password: &'a [u8],
+    recipient: Option<(&'a [u8], &'a [u8])>,
     parse_options: ParseOptions,
+    decoders: Decoders,
 }
 impl FileOptions<'static, NoCache, NoCache, NoLog> {
     pub fn uncached() -> Self {
@@ -538,7 +914,9 @@ impl FileOptions<'static, NoCache, NoCache, NoLog> {
             oc: NoCache,
             sc: NoCache,
             password: b"",
+            recipient: None,
             parse_options: ParseOptions::strict(),
+            decoders: Decoders::new(),
             log: NoLog,
         }
     }
@@ -551,7 +929,9 @@ impl FileOptions<'static, ObjectCache, StreamCache, NoLog> {
             oc: SyncCache::new(),
             sc: SyncCache::new(),
             password: b"",
+            recipient: None,
             parse_options: ParseOptions::strict(),
+            decoders: Decoders::new(),
             log: NoLog
         }
     }
@@ -568,29 +948,45 @@ where
             .. self
         }
     }
+    /// The private key and certificate of a recipient of a document encrypted with the
+    /// public-key security handler. Both may be PEM or DER encoded.
+    pub fn recipient(self, private_key: &'a [u8], certificate: &'a [u8]) -> FileOptions<'a, OC, SC, L> {
+        FileOptions {
+            recipient: Some((private_key, certificate)),
+            .. self
+        }
+    }
     pub fn cache<O, S>(self, oc: O, sc: S) -> FileOptions<'a, O, S, L> {
-        let FileOptions { oc: _, sc: _, password, parse_options, log } = self;
+        let FileOptions { oc: _, sc: _, password, recipient, parse_options, decoders, log } = self;
         FileOptions {
             oc,
             sc,
             password,
+            recipient,
             parse_options,
+            decoders,
             log,
         }
     }
     pub fn log<Log>(self, log: Log) -> FileOptions<'a, OC, SC, Log> {
-        let FileOptions { oc, sc, password, parse_options, .. } = self;
+        let FileOptions { oc, sc, password, recipient, parse_options, decoders, .. } = self;
         FileOptions {
             oc,
             sc,
             password,
+            recipient,
             parse_options,
+            decoders,
             log,
         }
     }
     pub fn parse_options(self, parse_options: ParseOptions) -> Self {
         FileOptions { parse_options, .. self }
     }
+    /// Decoders for JPX, JBIG2 and custom filters, used instead of the global ones.
+    pub fn decoders(self, decoders: Decoders) -> Self {
+        FileOptions { decoders, .. self }
+    }
 
     /// open a file
     pub fn open(self, path: impl AsRef<Path>) -> Result<File<Vec<u8>, OC, SC, L>> {
@@ -598,14 +994,16 @@ where
         self.load(data)
     }
     pub fn storage(self) -> Storage<Vec<u8>, OC, SC, L> {
-        let FileOptions { oc, sc, log, .. } = self;
-        Storage::empty(oc, sc, log)
+        let FileOptions { oc, sc, log, decoders, .. } = self;
+        let mut storage = Storage::empty(oc, sc, log);
+        storage.decoders = decoders;
+        storage
     }
 
     /// load data from the given backend
     pub fn load<B: Backend>(self, backend: B) -> Result<File<B, OC, SC, L>> {
-        let FileOptions { oc, sc, password, parse_options, log } = self;
-        File::load_data(backend, password, parse_options, oc, sc, log)
+        let FileOptions { oc, sc, password, recipient, parse_options, decoders, log } = self;
+        File::load_data(backend, password, recipient, parse_options, decoders, oc, sc, log)
     }
 }
 
@@ -617,19 +1015,26 @@ where
     SC: Cache<Result<Arc<[u8]>, Arc<PdfError>>>,
     L: Log,
 {
-    fn load_data(backend: B, password: &[u8], options: ParseOptions, object_cache: OC, stream_cache: SC, log: L) -> Result<Self> {
+    #[allow(clippy::too_many_arguments)]
+    fn load_data(backend: B, password: &[u8], recipient: Option<(&[u8], &[u8])>, options: ParseOptions, decoders: Decoders, object_cache: OC, stream_cache: SC, log: L) -> Result<Self> {
         let mut storage = Storage::with_cache(backend, options, object_cache, stream_cache, log)?;
-        let trailer = storage.load_storage_and_trailer_password(password)?;
+        storage.decoders = decoders;
+        let trailer = storage.load_storage_and_trailer_with(password, recipient)?;
 
+        let info_ref = match trailer.get("Info") {
+            Some(&Primitive::Reference(r)) => Some(r),
+            _ => None
+        };
         let resolver = StorageResolver::new(&storage);
+        let trailer_dict = trailer.clone();
         let trailer = t!(Trailer::from_primitive(
             Primitive::Dictionary(trailer),
             &resolver,
         ));
-        Ok(File { storage, trailer })
+        Ok(File { storage, trailer, info_ref, trailer_dict })
     }
     pub fn new(storage: Storage<B, OC, SC, L>, trailer: Trailer) -> Self {
-        File { storage, trailer }
+        File { storage, trailer, info_ref: None, trailer_dict: Dictionary::new() }
     }
     pub fn resolver(&self) -> impl Resolve + '_ {
         StorageResolver::new(&self.storage)
@@ -656,18 +1061,268 @@ where
         Ok(())
     }
 
+    /// Read the catalog again, after objects it refers to were changed with `update()`.
+    pub(crate) fn reload_catalog(&mut self) -> Result<()> {
+        self.storage.cache.clear();
+        let root = self.resolver().get(self.trailer.root.get_ref())?;
+        self.trailer.root = root;
+        Ok(())
+    }
+
     pub fn set_options(&mut self, options: ParseOptions) {
         self.storage.options = options;
     }
 
+    /// Replace the decoders for JPX, JBIG2 and custom filters.
+    pub fn set_decoders(&mut self, decoders: Decoders) {
+        self.storage.decoders = decoders;
+        self.storage.stream_cache.clear();
+    }
+
     pub fn scan(&self) -> impl Iterator<Item = Result<ScanItem>> + '_ {
         self.storage.scan()
     }
 
+    /// The cross-reference table the document was read with.
+    pub fn xref_table(&self) -> &XRefTable {
+        &self.storage.refs
+    }
+
+    /// The trailer dictionary as it was read from the file.
+    /// It is empty for documents that were created with `File::new`.
+    pub fn trailer_dict(&self) -> &Dictionary {
+        &self.trailer_dict
+    }
+
     pub fn log(&self) -> &L {
         &self.storage.log
     }
 
+    /// The access permissions of the document. Unencrypted documents permit everything.
+    pub fn permissions(&self) -> Permissions {
+        if let Some(permissions) = self.storage.decoder.as_ref().and_then(|d| d.permissions()) {
+            return permissions;
+        }
+        match self.trailer.encrypt_dict {
+            Some(ref dict) => dict.permissions(),
+            None => Permissions::all(),
+        }
+    }
+
+    /// Write the document with the changes as an incremental update to `out`.
+    ///
+    /// The original bytes are copied from the backend as they are, so this works
+    /// for any backend and doesn't need the document in memory.
+    pub fn save_to_writer(&mut self, options: &SaveOptions, out: &mut impl Write) -> Result<()> {
+        self.storage.save_to_writer(&mut self.trailer, options, out)
+    }
+
+    /// Write the document with `changes` on top of the changes of the file as an
+    /// incremental update to `out`.
+    ///
+    /// The file itself is left as it is, so that any number of updates can be written
+    /// from a shared file at the same time. The info dictionary of the file is kept.
+    pub fn save_changes_to_writer(&self, changes: impl IntoIterator<Item=(PlainRef, Primitive)>, options: &SaveOptions, out: &mut impl Write) -> Result<()> {
+        let storage = &self.storage;
+        let mut all: HashMap<ObjNr, (PlainRef, Primitive)> = storage.changes.iter()
+            .map(|(&id, &(ref p, gen))| (id, (PlainRef { id, gen }, p.clone())))
+            .collect();
+        for (r, p) in changes {
+            all.insert(r.id, (r, p));
+        }
+
+        let mut trailer = Dictionary::new();
+        trailer.insert("Root", Primitive::Reference(self.trailer.root.get_ref().get_inner()));
+        if let Some(r) = self.info_ref {
+            trailer.insert("Info", Primitive::Reference(r));
+        }
+        if let Some(ref dict) = self.trailer.encrypt_dict {
+            trailer.insert("Encrypt", Primitive::Reference(dict.get_ref().get_inner()));
+        }
+        if !self.trailer.id.is_empty() {
+            trailer.insert("ID", Primitive::Array(self.trailer.id.iter().cloned().map(Primitive::String).collect()));
+        }
+
+        let (update, _, _) = storage.write_changes(all.into_values().collect(), trailer, options)?;
+        storage.backend.copy_to(.., out)?;
+        out.write_all(&update)?;
+        Ok(())
+    }
+
+    /// Write a copy of the document without encryption.
+    ///
+    /// Every object and stream is written decrypted and `/Encrypt` is removed from the trailer.
+    /// An encrypted file has to be opened with the owner password, otherwise this fails
+    /// with `PdfError::OwnerPasswordRequired`.
+    pub fn save_decrypted(&self) -> Result<Vec<u8>> {
+        self.check_owner()?;
+        self.rewrite(Protection::Decrypt, false, &SaveOptions::default())
+    }
+
+    /// Write a copy of the document encrypted with new passwords, security revision and permissions.
+    /// Like `save_decrypted`, this needs the owner password of an encrypted file.
+    pub fn save_encrypted(&self, encryption: &Encryption) -> Result<Vec<u8>> {
+        self.check_owner()?;
+        self.rewrite(Protection::Encrypt(encryption), false, &SaveOptions::default())
+    }
+
+    /// Whether the document is unencrypted or was opened with the owner password.
+    pub fn is_owner(&self) -> bool {
+        self.storage.decoder.as_ref().is_none_or(|d| d.is_owner())
+    }
+
+    fn check_owner(&self) -> Result<()> {
+        match self.is_owner() {
+            true => Ok(()),
+            false => Err(PdfError::OwnerPasswordRequired),
+        }
+    }
+
+    /// Write the document as a new file with a single revision.
+    ///
+    /// Only the objects that can be reached from the root, info and encrypt entries of
+    /// the trailer are kept, and they are renumbered densely. Superseded versions of
+    /// updated objects are dropped. The encryption of the document is kept.
+    pub fn save_compact(&self, options: &SaveOptions) -> Result<Vec<u8>> {
+        self.rewrite(Protection::Keep, true, options)
+    }
+
+    /// Write all objects into a new file with a single cross-reference section.
+    fn rewrite(&self, protection: Protection, compact: bool, options: &SaveOptions) -> Result<Vec<u8>> {
+        let storage = &self.storage;
+        let resolver = StorageResolver::new(storage);
+
+        let mut file_id = self.trailer.id.clone();
+        if file_id.is_empty() {
+            let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+            let hash = md5::compute(format!("{:?} {}", time, storage.refs.len()));
+            file_id = vec![PdfString::new(hash.0.to_vec().into()); 2];
+        }
+        let old_encrypt_ref = storage.decoder.as_ref().and_then(|d| d.encrypt_indirect_object);
+        let (crypt_dict, mut decoder) = match protection {
+            Protection::Encrypt(e) => {
+                let (dict, decoder) = e.build(file_id[0].as_bytes())?;
+                (Some(dict.to_primitive(&mut NoUpdate)?), Some(decoder))
+            }
+            Protection::Keep => match (&storage.decoder, &self.trailer.encrypt_dict) {
+                (Some(decoder), Some(dict)) => {
+                    let dict = match old_encrypt_ref {
+                        Some(r) => t!(resolver.resolve(r)),
+                        None => dict.to_primitive(&mut NoUpdate)?,
+                    };
+                    (Some(dict), Some(decoder.clone()))
+                }
+                _ => (None, None)
+            },
+            Protection::Decrypt => (None, None)
+        };
+
+        if options.xref_table {
+            options.check_xref_table()?;
+        }
+        // xref streams need 1.5, while a classic xref table is all a 1.4 reader needs,
+        // so the header is lowered to 1.4 unless the encryption needs more
+        let source_version = parse_version(&storage.version()?).unwrap_or((1, 4));
+        let mut version = match options.xref_table {
+            true => source_version.min((1, 4)),
+            false => source_version.max((1, 5)),
+        };
+        if let Some(ref decoder) = decoder {
+            version = version.max(decoder.min_version());
+        }
+        let mut out = Vec::new();
+        writeln!(out, "%PDF-{}.{}", version.0, version.1)?;
+        out.extend_from_slice(b"%\xE2\xE3\xCF\xD3\n");
+
+        let mut objects = Vec::new();
+        let mut info_written = false;
+        for id in 1 .. storage.refs.len() as ObjNr {
+            let r = match storage.changes.get(&id) {
+                Some(&(_, gen)) => Some(PlainRef { id, gen }),
+                None => match storage.refs.get(id)? {
+                    XRef::Raw { gen_nr, .. } => Some(PlainRef { id, gen: gen_nr }),
+                    XRef::Stream { .. } => Some(PlainRef { id, gen: 0 }),
+                    _ => None
+                }
+            };
+            let primitive = match r {
+                Some(r) if Some(r) == self.info_ref => match self.trailer.info_dict {
+                    Some(ref info) => {
+                        info_written = true;
+                        Some(info.to_primitive(&mut NoUpdate)?)
+                    }
+                    None => None
+                },
+                Some(r) if Some(r) == old_encrypt_ref => None,
+                Some(r) => match t!(resolver.resolve(r), r) {
+                    // objects in object streams are written on their own and the xref stream is replaced
+                    Primitive::Stream(ref s) if matches!(s.info.get("Type"), Some(Primitive::Name(n)) if n == "ObjStm" || n == "XRef") => None,
+                    p => Some(p)
+                },
+                None => None
+            };
+            if let (Some(r), Some(p)) = (r, primitive) {
+                objects.push((r, p));
+            }
+        }
+
+        let mut trailer = Dictionary::new();
+        trailer.insert("Root", Primitive::Reference(self.trailer.root.get_ref().get_inner()));
+        let mut next_id = storage.refs.len().max(1) as ObjNr;
+        if let (Some(info), false) = (&self.trailer.info_dict, info_written) {
+            let r = PlainRef { id: next_id, gen: 0 };
+            next_id += 1;
+            objects.push((r, info.to_primitive(&mut NoUpdate)?));
+            trailer.insert("Info", Primitive::Reference(r));
+        } else if let Some(r) = self.info_ref.filter(|_| info_written) {
+            trailer.insert("Info", Primitive::Reference(r));
+        }
+
+        if compact {
+            objects = compact_objects(objects, &mut trailer);
+            next_id = objects.len() as ObjNr + 1;
+        }
+        if let Some(ref mut decoder) = decoder {
+            // the new encryption dictionary is written on its own, and the metadata may have moved
+            decoder.encrypt_indirect_object = None;
+            decoder.metadata_indirect_object = match trailer.get("Root") {
+                Some(&Primitive::Reference(root)) => objects.iter()
+                    .find(|(r, _)| r.id == root.id)
+                    .and_then(|(_, p)| match p {
+                        Primitive::Dictionary(catalog) => match catalog.get("Metadata") {
+                            Some(&Primitive::Reference(r)) => Some(r),
+                            _ => None
+                        },
+                        _ => None
+                    }),
+                _ => None
+            };
+        }
+
+        let mut refs = XRefTable::new(0);
+        for _ in 1 .. next_id {
+            refs.push(XRef::Free { next_obj_nr: 0, gen_nr: 0 });
+        }
+        for (id, entry) in write_objects(&mut out, 0, &objects, next_id, options, decoder.as_ref(), &resolver)? {
+            match id as usize {
+                n if n < refs.len() => refs.set(id, entry),
+                _ => refs.push(entry),
+            }
+        }
+        if let Some(ref dict) = crypt_dict {
+            // the encryption dictionary itself is never encrypted
+            let r = PlainRef { id: refs.len() as _, gen: 0 };
+            refs.push(XRef::Raw { pos: out.len(), gen_nr: 0 });
+            write_object(&mut out, r, dict, None, &resolver)?;
+            trailer.insert("Encrypt", Primitive::Reference(r));
+        }
+        trailer.insert("ID", Primitive::Array(file_id.into_iter().map(Primitive::String).collect()));
+
+        write_xref(&mut out, 0, &mut refs, &mut trailer, options)?;
+
+        Ok(out)
+    }
+
     /// the version string in the file header.
     /// if the version field in the Catalog is set, this should be used instead.
     pub fn version(&self) -> Result<String> {
@@ -0,0 +1,916 @@
+//! Turning image XObjects into 8-bit pixels, finding the images of pages, and adding images.
+//!
+//! [`ImageXObject::pixels`] unpacks the samples, applies the `/Decode` array, maps the color
+//! space to gray or RGB and adds the alpha channel of a `/SMask` or `/Mask`. The result can be
+//! handed to any PNG or TIFF encoder.
+//!
+//! [`ImageXObject::from_jpeg`] and [`ImageXObject::from_png`] go the other way and embed image
+//! files, mostly without decoding them. `File::add_image` and `File::set_button_image` draw
+//! such an image on a page or as the appearance of a button.
+//!
+//! Color management is not done: ICC based and calibrated color spaces are treated like the
+//! device color space with the same number of components, and CMYK is converted naively.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::any::AnySync;
+use crate::backend::Backend;
+use crate::build::resolve_dict;
+use crate::content::Matrix;
+use crate::enc::{self, DCTDecodeParams, LZWFlateParams, StreamFilter};
+use crate::error::*;
+use crate::file::{Cache, File, Log};
+use crate::interpret::{interpret_page, GraphicsState, Visitor};
+use crate::object::*;
+use crate::primitive::{Dictionary, Primitive};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    Gray,
+    Rgb,
+    Rgba,
+}
+impl PixelFormat {
+    pub fn channels(self) -> usize {
+        match self {
+            PixelFormat::Gray => 1,
+            PixelFormat::Rgb => 3,
+            PixelFormat::Rgba => 4,
+        }
+    }
+}
+
+/// 8-bit pixels, row by row from the top, without padding.
+#[derive(Debug, Clone)]
+pub struct Pixels {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub data: Vec<u8>,
+}
+
+impl ImageXObject {
+    /// Decode the image into gray, RGB or RGBA pixels.
+    ///
+    /// Image masks are black where they paint and transparent elsewhere.
+    pub fn pixels(&self, resolve: &impl Resolve) -> Result<Pixels> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let data = self.image_data(resolve)?;
+        // these decoders produce one byte per sample
+        let decoded = matches!(self.inner.filters.last(),
+            Some(StreamFilter::DCTDecode(_) | StreamFilter::CCITTFaxDecode(_) | StreamFilter::JBIG2Decode(_) | StreamFilter::JPXDecode));
+        let bpc = match self.bits_per_component {
+            _ if decoded => 8,
+            _ if self.image_mask => 1,
+            Some(bpc @ (1 | 2 | 4 | 8 | 16)) => bpc as u32,
+            Some(bpc) => bail!("invalid BitsPerComponent {}", bpc),
+            None => 8,
+        };
+        // `image_data` already inverts 1-bit images for this decode array
+        let decode = match self.decode {
+            Some(ref decode) if !(decode == &[1.0, 0.0] && self.bits_per_component == Some(1)) => Some(decode.as_slice()),
+            _ => None,
+        };
+
+        if self.image_mask {
+            let size = buffer_size(resolve, width, height, 4)?;
+            let samples = unpack(&data, width, height, 1, bpc)?;
+            let mut pixels = Vec::with_capacity(size);
+            for s in samples {
+                let painted = if bpc == 1 { s == 0 } else { s < 128 };
+                pixels.extend_from_slice(&[0, 0, 0, if painted { 255 } else { 0 }]);
+            }
+            return Ok(Pixels { width: self.width, height: self.height, format: PixelFormat::Rgba, data: pixels });
+        }
+
+        let cs = match self.color_space {
+            Some(ref cs) => cs.clone(),
+            // JPX images may leave it out
+            None => match data.len() / buffer_size(resolve, width, height, 1)?.max(1) {
+                1 => ColorSpace::DeviceGray,
+                3 => ColorSpace::DeviceRGB,
+                4 => ColorSpace::DeviceCMYK,
+                _ => bail!("image without color space"),
+            },
+        };
+        let n = components(&cs)?;
+        // the samples, before they are converted
+        buffer_size(resolve, width, height, n)?;
+        let gray = is_gray(&cs);
+        let channels = if gray { 1 } else { 3 };
+        let size = buffer_size(resolve, width, height, channels)?;
+        let samples = unpack(&data, width, height, n, bpc)?;
+        let max = ((1u32 << bpc) - 1) as f32;
+        let ranges: Vec<(f32, f32)> = (0 .. n).map(|i| match decode {
+            Some(d) if d.len() >= 2 * n => (d[2 * i], d[2 * i + 1]),
+            _ if matches!(cs, ColorSpace::Indexed(..)) => (0., max),
+            _ => (0., 1.),
+        }).collect();
+
+        let mut color = Vec::with_capacity(size);
+        let mut input = [0.; 32];
+        for pixel in samples.chunks_exact(n) {
+            for (i, (&s, &(lo, hi))) in pixel.iter().zip(ranges.iter()).enumerate() {
+                input[i] = lo + s as f32 * (hi - lo) / max;
+            }
+            let rgb = to_rgb(&cs, &input[.. n])?;
+            color.extend(rgb[.. channels].iter().map(|&c| (c.clamp(0., 1.) * 255. + 0.5) as u8));
+        }
+
+        let alpha = match (self.smask, self.mask.as_ref()) {
+            (Some(smask), _) => {
+                let mask = mask_image((*resolve.get(smask)?).clone()).pixels(resolve)?;
+                Some(resample(&mask, PixelFormat::Gray, self.width, self.height))
+            }
+            (None, Some(Primitive::Array(ranges))) => {
+                // color key masking, on the samples before decoding
+                let ranges: Vec<u32> = ranges.iter().map(|p| p.as_u32()).collect::<Result<_>>()?;
+                if ranges.len() < 2 * n {
+                    bail!("color key mask needs {} values", 2 * n);
+                }
+                Some(samples.chunks_exact(n).map(|pixel| {
+                    let masked = pixel.iter().enumerate().all(|(i, &s)| (ranges[2 * i] ..= ranges[2 * i + 1]).contains(&(s as u32)));
+                    if masked { 0 } else { 255 }
+                }).collect())
+            }
+            (None, Some(p)) => {
+                let mask = mask_image(ImageXObject::from_primitive(p.clone(), resolve)?.inner).pixels(resolve)?;
+                Some(resample(&mask, PixelFormat::Rgba, self.width, self.height))
+            }
+            (None, None) => None,
+        };
+
+        let (format, data) = match alpha {
+            None => (if gray { PixelFormat::Gray } else { PixelFormat::Rgb }, color),
+            Some(alpha) => {
+                let mut data = Vec::with_capacity(buffer_size(resolve, width, height, 4)?);
+                for (pixel, a) in color.chunks_exact(channels).zip(alpha) {
+                    match *pixel {
+                        [g] => data.extend_from_slice(&[g, g, g, a]),
+                        [r, g, b] => data.extend_from_slice(&[r, g, b, a]),
+                        _ => unreachable!(),
+                    }
+                }
+                (PixelFormat::Rgba, data)
+            }
+        };
+        Ok(Pixels { width: self.width, height: self.height, format, data })
+    }
+}
+
+impl ImageXObject {
+    /// An image from a JPEG file, which is embedded as it is.
+    pub fn from_jpeg(data: &[u8]) -> Result<ImageXObject> {
+        use jpeg_decoder::{Decoder, PixelFormat};
+
+        let mut decoder = Decoder::new(data);
+        decoder.read_info()?;
+        let info = try_opt!(decoder.info());
+        let (color_space, decode) = match info.pixel_format {
+            PixelFormat::L8 => (ColorSpace::DeviceGray, None),
+            PixelFormat::RGB24 => (ColorSpace::DeviceRGB, None),
+            // Adobe applications write inverted CMYK values
+            PixelFormat::CMYK32 if is_adobe_jpeg(data) => (ColorSpace::DeviceCMYK, Some([1., 0.].repeat(4))),
+            PixelFormat::CMYK32 => (ColorSpace::DeviceCMYK, None),
+            PixelFormat::L16 => bail!("JPEG images with 16 bits per sample can't be embedded"),
+        };
+        let dict = ImageDict {
+            width: info.width as u32,
+            height: info.height as u32,
+            color_space: Some(color_space),
+            bits_per_component: Some(8),
+            decode,
+            ..Default::default()
+        };
+        let filter = StreamFilter::DCTDecode(DCTDecodeParams { color_transform: None });
+        Ok(ImageXObject { inner: Stream::from_compressed(dict, data.to_vec(), vec![filter]) })
+    }
+
+    /// An image from a PNG file.
+    ///
+    /// The compressed data is embedded as it is, unless there is an alpha channel. That is
+    /// split off into a soft mask, which is created with `update`. Palettes become `Indexed`
+    /// color spaces. Interlaced images are not supported.
+    pub fn from_png(data: &[u8], update: &mut impl Updater) -> Result<ImageXObject> {
+        let png = Png::parse(data)?;
+        let (width, height, depth) = (png.width, png.height, png.depth);
+        let (colors, color_space) = match png.color_type {
+            0 | 4 => (1, ColorSpace::DeviceGray),
+            2 | 6 => (3, ColorSpace::DeviceRGB),
+            3 => {
+                let palette = try_opt!(png.palette);
+                if palette.len() < 3 || palette.len() > 3 * 256 {
+                    bail!("PNG palette has {} bytes", palette.len());
+                }
+                let hival = (palette.len() / 3 - 1) as u8;
+                (1, ColorSpace::Indexed(Box::new(ColorSpace::DeviceRGB), hival, palette.into()))
+            }
+            t => bail!("invalid PNG color type {}", t),
+        };
+        let has_alpha = png.color_type & 4 != 0;
+        let valid_depth = match png.color_type {
+            0 => matches!(depth, 1 | 2 | 4 | 8 | 16),
+            3 => matches!(depth, 1 | 2 | 4 | 8),
+            _ => matches!(depth, 8 | 16),
+        };
+        if !valid_depth {
+            bail!("invalid PNG bit depth {} for color type {}", depth, png.color_type);
+        }
+        // the IDAT chunks are a Flate stream, with a PNG predictor on each row
+        // the IHDR size, which the data has to fill
+        let samples_len = |n_components: usize| -> Result<usize> {
+            image_len(width as usize, height as usize, n_components, depth as u32)
+        };
+        let params = |n_components: usize| LZWFlateParams {
+            predictor: 15,
+            n_components: n_components as i32,
+            bits_per_component: depth as i32,
+            columns: width as i32,
+            early_change: 1,
+        };
+        let mut dict = ImageDict {
+            width,
+            height,
+            color_space: Some(color_space),
+            bits_per_component: Some(depth as i32),
+            ..Default::default()
+        };
+
+        if !has_alpha {
+            match (png.color_type, png.transparency) {
+                (3, Some(alpha)) => {
+                    let indices = enc::flate_decode(&png.data, &params(1))?;
+                    let len = samples_len(1)?;
+                    if indices.len() < len {
+                        bail!("PNG image data is {} bytes short", len - indices.len());
+                    }
+                    let alpha: Vec<u8> = unpack(&indices, width as usize, height as usize, 1, depth as u32)?
+                        .into_iter()
+                        .map(|i| alpha.get(i as usize).copied().unwrap_or(255))
+                        .collect();
+                    dict.smask = Some(soft_mask(&alpha, 8, width, height, update)?);
+                }
+                // the color that is transparent, as 16-bit values
+                (_, Some(key)) => {
+                    let key: Vec<Primitive> = key.chunks_exact(2).take(colors)
+                        .map(|v| u16::from_be_bytes([v[0], v[1]]) as i32)
+                        .flat_map(|v| [Primitive::Integer(v), Primitive::Integer(v)])
+                        .collect();
+                    dict.mask = Some(Primitive::Array(key));
+                }
+                _ => {}
+            }
+            let filter = StreamFilter::FlateDecode(params(colors));
+            return Ok(ImageXObject { inner: Stream::from_compressed(dict, png.data, vec![filter]) });
+        }
+
+        let samples = enc::flate_decode(&png.data, &params(colors + 1))?;
+        let len = samples_len(colors + 1)?;
+        if samples.len() < len {
+            bail!("PNG image data is {} bytes short", len - samples.len());
+        }
+        let bytes = depth as usize / 8;
+        let mut color = Vec::with_capacity(samples.len() / (colors + 1) * colors);
+        let mut alpha = Vec::with_capacity(samples.len() / (colors + 1));
+        for pixel in samples.chunks_exact((colors + 1) * bytes) {
+            let (c, a) = pixel.split_at(colors * bytes);
+            color.extend_from_slice(c);
+            alpha.extend_from_slice(a);
+        }
+        dict.smask = Some(soft_mask(&alpha, depth as i32, width, height, update)?);
+        Ok(ImageXObject { inner: flate_stream(dict, &color)? })
+    }
+}
+
+fn flate_stream(dict: ImageDict, data: &[u8]) -> Result<Stream<ImageDict>> {
+    let filter = StreamFilter::FlateDecode(LZWFlateParams::default());
+    let data = enc::encode(data, &filter)?;
+    Ok(Stream::from_compressed(dict, data, vec![filter]))
+}
+
+fn soft_mask(alpha: &[u8], bpc: i32, width: u32, height: u32, update: &mut impl Updater) -> Result<Ref<Stream<ImageDict>>> {
+    let dict = ImageDict {
+        width,
+        height,
+        color_space: Some(ColorSpace::DeviceGray),
+        bits_per_component: Some(bpc),
+        ..Default::default()
+    };
+    Ok(update.create(flate_stream(dict, alpha)?)?.get_ref())
+}
+
+/// Whether the JPEG file has an `APP14` segment written by Adobe.
+fn is_adobe_jpeg(data: &[u8]) -> bool {
+    let mut pos = 2;
+    while let Some(&[0xFF, marker, a, b]) = data.get(pos .. pos + 4) {
+        // the image data follows the start of scan
+        if marker == 0xDA {
+            break;
+        }
+        if marker == 0xEE && data[pos + 4 ..].starts_with(b"Adobe") {
+            return true;
+        }
+        pos += 2 + u16::from_be_bytes([a, b]) as usize;
+    }
+    false
+}
+
+/// The chunks of a PNG file that are needed to embed it.
+struct Png {
+    width: u32,
+    height: u32,
+    depth: u8,
+    color_type: u8,
+    palette: Option<Vec<u8>>,
+    transparency: Option<Vec<u8>>,
+    /// the `IDAT` chunks
+    data: Vec<u8>,
+}
+impl Png {
+    fn parse(data: &[u8]) -> Result<Png> {
+        if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            bail!("not a PNG file");
+        }
+        let mut png = Png { width: 0, height: 0, depth: 0, color_type: 0, palette: None, transparency: None, data: vec![] };
+        let mut pos = 8;
+        while let Some(header) = data.get(pos .. pos + 8) {
+            let len = u32::from_be_bytes(header[.. 4].try_into().unwrap()) as usize;
+            let chunk = match data.get(pos + 8 .. pos + 8 + len) {
+                Some(chunk) => chunk,
+                None => bail!("PNG chunk exceeds the file"),
+            };
+            match &header[4 ..] {
+                b"IHDR" if len >= 13 => {
+                    png.width = u32::from_be_bytes(chunk[0 .. 4].try_into().unwrap());
+                    png.height = u32::from_be_bytes(chunk[4 .. 8].try_into().unwrap());
+                    png.depth = chunk[8];
+                    png.color_type = chunk[9];
+                    if chunk[12] != 0 {
+                        bail!("interlaced PNG images are not supported");
+                    }
+                }
+                b"PLTE" => png.palette = Some(chunk.to_vec()),
+                b"tRNS" => png.transparency = Some(chunk.to_vec()),
+                b"IDAT" => png.data.extend_from_slice(chunk),
+                b"IEND" => break,
+                _ => {}
+            }
+            // length, type, data and CRC
+            pos += 12 + len;
+        }
+        if png.width == 0 || png.height == 0 || png.data.is_empty() {
+            bail!("PNG file without image");
+        }
+        Ok(png)
+    }
+}
+
+/// The mask `inner` of an image on its own. The `/SMask` and `/Mask` of a mask are ignored,
+/// so a mask that refers back to its image can't recurse.
+fn mask_image(mut inner: Stream<ImageDict>) -> ImageXObject {
+    inner.info.info.smask = None;
+    inner.info.info.mask = None;
+    ImageXObject { inner }
+}
+
+/// The number of values of `width` × `height` pixels with `channels` each. Like a decoded
+/// stream, it has to stay within `Limits::max_stream_size`.
+fn buffer_size(resolve: &impl Resolve, width: usize, height: usize, channels: usize) -> Result<usize> {
+    let size = match width.checked_mul(height).and_then(|n| n.checked_mul(channels)) {
+        Some(size) => size,
+        None => bail!("image of {}x{} pixels is too large", width, height),
+    };
+    match resolve.options().limits.max_stream_size {
+        Some(max) if size > max => Err(PdfError::StreamTooLarge { max }),
+        _ => Ok(size),
+    }
+}
+
+/// The bytes of `height` rows of `width` × `n` samples with `bpc` bits each,
+/// where each row starts at a byte boundary.
+fn image_len(width: usize, height: usize, n: usize, bpc: u32) -> Result<usize> {
+    let row_bytes = width.checked_mul(n).and_then(|s| s.checked_mul(bpc as usize)).map(|bits| bits.div_ceil(8));
+    match row_bytes.and_then(|row_bytes| row_bytes.checked_mul(height)) {
+        Some(len) => Ok(len),
+        None => bail!("image of {}x{} pixels is too large", width, height),
+    }
+}
+
+/// The samples of each row, which starts at a byte boundary.
+fn unpack(data: &[u8], width: usize, height: usize, n: usize, bpc: u32) -> Result<Vec<u16>> {
+    let len = image_len(width, height, n, bpc)?;
+    if data.len() < len {
+        bail!("image data is {} bytes short", len - data.len());
+    }
+    // `image_len` checked these products
+    let per_row = width * n;
+    let row_bytes = (per_row * bpc as usize).div_ceil(8);
+    let mut samples = Vec::with_capacity(per_row * height);
+    for y in 0 .. height {
+        let row = &data[y * row_bytes ..];
+        for i in 0 .. per_row {
+            let s = match bpc {
+                8 => row[i] as u16,
+                16 => u16::from_be_bytes([row[2 * i], row[2 * i + 1]]),
+                _ => {
+                    let bit = i * bpc as usize;
+                    let shift = 8 - bpc as usize - bit % 8;
+                    (row[bit / 8] >> shift) as u16 & ((1 << bpc) - 1)
+                }
+            };
+            samples.push(s);
+        }
+    }
+    Ok(samples)
+}
+
+/// One alpha value per pixel of a `width` × `height` image, from the mask `mask`,
+/// which is scaled to the size of the image.
+fn resample(mask: &Pixels, format: PixelFormat, width: u32, height: u32) -> Vec<u8> {
+    let channels = mask.format.channels();
+    // gray masks give alpha by their gray level, image masks by their alpha channel
+    let offset = if format == PixelFormat::Rgba { channels - 1 } else { 0 };
+    let mut alpha = Vec::with_capacity(width as usize * height as usize);
+    for y in 0 .. height as usize {
+        let my = y * mask.height as usize / height as usize;
+        for x in 0 .. width as usize {
+            let mx = x * mask.width as usize / width as usize;
+            let i = (my * mask.width as usize + mx) * channels + offset;
+            alpha.push(mask.data.get(i).copied().unwrap_or(255));
+        }
+    }
+    alpha
+}
+
+/// The number of components of `cs`, after checking that `to_rgb` can convert its colors.
+fn components(cs: &ColorSpace) -> Result<usize> {
+    let n = match *cs {
+        ColorSpace::DeviceGray | ColorSpace::CalGray(_) => 1,
+        ColorSpace::DeviceRGB | ColorSpace::CalRGB(_) => 3,
+        ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_) => 4,
+        ColorSpace::Indexed(ref base, _, _) => {
+            components(base)?;
+            1
+        }
+        ColorSpace::Separation(_, ref alt, _) => {
+            components(alt)?;
+            1
+        }
+        ColorSpace::DeviceN { ref names, ref alt, .. } => {
+            components(alt)?;
+            names.len()
+        }
+        ColorSpace::Icc(ref icc) => {
+            let n = icc.components as usize;
+            match icc.alternate {
+                Some(ref alt) if components(alt)? != n => {
+                    bail!("ICC based color space with {} components has an alternate with {}", n, components(alt)?)
+                }
+                Some(_) => {}
+                None if !matches!(n, 1 | 3 | 4) => bail!("ICC based color space with {} components", n),
+                None => {}
+            }
+            n
+        }
+        ref cs => bail!("unsupported image color space {:?}", cs),
+    };
+    if !(1 ..= 32).contains(&n) {
+        bail!("color space with {} components", n);
+    }
+    Ok(n)
+}
+
+fn is_gray(cs: &ColorSpace) -> bool {
+    match *cs {
+        ColorSpace::DeviceGray | ColorSpace::CalGray(_) => true,
+        ColorSpace::Icc(ref icc) => match icc.alternate {
+            Some(ref alt) => is_gray(alt),
+            None => icc.components == 1,
+        },
+        ColorSpace::Indexed(ref base, _, _) => is_gray(base),
+        ColorSpace::Separation(_, ref alt, _) => is_gray(alt),
+        ColorSpace::DeviceN { ref alt, .. } => is_gray(alt),
+        _ => false,
+    }
+}
+
+/// Components between 0 and 1, except for the index into a palette.
+fn to_rgb(cs: &ColorSpace, c: &[f32]) -> Result<[f32; 3]> {
+    let cmyk = |c: &[f32]| {
+        let k = 1. - c[3];
+        [(1. - c[0]) * k, (1. - c[1]) * k, (1. - c[2]) * k]
+    };
+    Ok(match *cs {
+        ColorSpace::DeviceGray | ColorSpace::CalGray(_) => [c[0]; 3],
+        ColorSpace::DeviceRGB | ColorSpace::CalRGB(_) => [c[0], c[1], c[2]],
+        ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_) => cmyk(c),
+        ColorSpace::Icc(ref icc) => match icc.alternate {
+            Some(ref alt) => to_rgb(alt, c)?,
+            None => match c.len() {
+                1 => [c[0]; 3],
+                3 => [c[0], c[1], c[2]],
+                4 => cmyk(c),
+                n => bail!("ICC based color space with {} components", n),
+            }
+        },
+        ColorSpace::Indexed(ref base, hival, ref lookup) => {
+            let n = components(base)?;
+            let index = (c[0].round().max(0.) as usize).min(hival as usize);
+            let mut input = [0.; 32];
+            for (i, x) in input[.. n].iter_mut().enumerate() {
+                *x = lookup.get(index * n + i).copied().unwrap_or(0) as f32 / 255.;
+            }
+            to_rgb(base, &input[.. n])?
+        }
+        ColorSpace::Separation(_, ref alt, ref tint) | ColorSpace::DeviceN { ref alt, ref tint, .. } => {
+            let mut out = [0.; 32];
+            let n = components(alt)?;
+            tint.apply(c, &mut out[.. n])?;
+            to_rgb(alt, &out[.. n])?
+        }
+        ref cs => bail!("unsupported image color space {:?}", cs),
+    })
+}
+
+/// An image drawn on a page.
+#[derive(Debug, Clone)]
+pub struct PageImage {
+    pub image: ImageXObject,
+    /// set for images in the content stream
+    pub inline: bool,
+    /// maps the unit square to the user space of the page
+    pub transform: Matrix,
+}
+
+impl Page {
+    /// The images drawn by the page and its form XObjects, each time they are drawn.
+    pub fn images(&self, resolve: &impl Resolve) -> Result<Vec<PageImage>> {
+        struct Images(Vec<PageImage>);
+        impl Visitor for Images {
+            fn image(&mut self, image: &ImageXObject, inline: bool, state: &GraphicsState) -> Result<()> {
+                self.0.push(PageImage { image: image.clone(), inline, transform: state.ctm });
+                Ok(())
+            }
+        }
+        let mut images = Images(vec![]);
+        interpret_page(self, resolve, &mut images)?;
+        Ok(images.0)
+    }
+}
+
+impl<B, OC, SC, L> File<B, OC, SC, L>
+where
+    B: Backend,
+    OC: Cache<Result<AnySync, Arc<PdfError>>>,
+    SC: Cache<Result<Arc<[u8]>, Arc<PdfError>>>,
+    L: Log,
+{
+    /// The images of all pages, with the number of the page they are on, counting from 0.
+    pub fn images(&self) -> impl Iterator<Item=Result<(u32, PageImage)>> + '_ {
+        self.pages().enumerate().flat_map(move |(n, page)| {
+            match page.and_then(|page| page.images(&self.resolver())) {
+                Ok(images) => images.into_iter().map(|image| Ok((n as u32, image))).collect(),
+                Err(e) => vec![Err(e)],
+            }
+        })
+    }
+
+    /// Draw `image` on page `n`, counting from 0, stretched to fill `rect`.
+    ///
+    /// The image is drawn on top of the page, in the default user space of the page.
+    pub fn add_image(&mut self, n: u32, image: ImageXObject, rect: Rectangle) -> Result<()> {
+        let page = self.get_page(n)?.get_ref().get_inner();
+        let mut dict = self.resolver().resolve(page)?.into_dictionary()?;
+        let mut resources = match inherited(&self.resolver(), &dict, "Resources")? {
+            Some(p) => resolve_dict(&self.resolver(), &p)?,
+            None => Dictionary::new(),
+        };
+        let mut xobjects = match resources.get("XObject") {
+            Some(p) => resolve_dict(&self.resolver(), p)?,
+            None => Dictionary::new(),
+        };
+        let name = (0 ..).map(|i| format!("Im{}", i)).find(|name| xobjects.get(name.as_str()).is_none()).unwrap();
+
+        let image = self.create(image)?.get_ref().get_inner();
+        xobjects.insert(name.as_str(), Primitive::Reference(image));
+        resources.insert("XObject", xobjects);
+        dict.insert("Resources", resources);
+
+        // keep the graphics state of the old content away from the image
+        let ops = format!("Q\nq\n{} 0 0 {} {} {} cm\n/{} Do\nQ\n",
+            rect.right - rect.left, rect.top - rect.bottom, rect.left, rect.bottom, name);
+        let mut contents = vec![Primitive::Reference(self.create(Stream::new((), &b"q\n"[..]))?.get_ref().get_inner())];
+        match dict.get("Contents") {
+            Some(Primitive::Array(parts)) => contents.extend(parts.iter().cloned()),
+            Some(&Primitive::Reference(r)) => match self.resolver().resolve(r)? {
+                Primitive::Array(parts) => contents.extend(parts),
+                _ => contents.push(Primitive::Reference(r)),
+            },
+            Some(p) => contents.push(p.clone()),
+            None => {}
+        }
+        contents.push(Primitive::Reference(self.create(Stream::new((), ops.into_bytes()))?.get_ref().get_inner()));
+        dict.insert("Contents", contents);
+
+        self.update(page, Primitive::Dictionary(dict))?;
+        self.reload_catalog()
+    }
+
+    /// Show the image XObject `image` on the widget annotation `widget` of a push button.
+    ///
+    /// The image is only referred to, so the widgets of a button can all show the same image,
+    /// e.g. one created with `Updater::create`.
+    ///
+    /// The image becomes the icon of the button, one unit per pixel, and the normal
+    /// appearance draws it into the `/Rect` of the widget as the icon fit dictionary
+    /// `/MK /IF` says. Without one, the image is scaled to fit, keeping its aspect ratio, and
+    /// centered. The button is set to show only the icon. Widgets without any area inside
+    /// their border, like the `/Rect` of a hidden button, can't show the image.
+    pub fn set_button_image(&mut self, widget: PlainRef, image: PlainRef) -> Result<()> {
+        let mut dict = self.resolver().resolve(widget)?.into_dictionary()?;
+        let rect = Rectangle::from_primitive(try_opt!(dict.get("Rect")).clone(), &self.resolver())?;
+        let mut mk = match dict.get("MK") {
+            Some(p) => resolve_dict(&self.resolver(), p)?,
+            None => Dictionary::new(),
+        };
+        let fit = match mk.get("IF") {
+            Some(p) => IconFit::from_dict(&resolve_dict(&self.resolver(), p)?)?,
+            None => IconFit::default(),
+        };
+        // the icon stays inside the border, unless it should fit the bounds
+        let border = match (fit.full_bounds, dict.get("BS")) {
+            (true, _) => 0.,
+            (false, Some(bs)) => resolve_dict(&self.resolver(), bs)?.get("W").map(|w| w.as_number()).transpose()?.unwrap_or(1.),
+            (false, None) => match dict.get("Border") {
+                Some(Primitive::Array(border)) if border.len() >= 3 => border[2].as_number()?,
+                _ => 1.,
+            }
+        };
+        let (width, height) = ((rect.right - rect.left).abs(), (rect.top - rect.bottom).abs());
+        let (iw, ih) = match *self.resolver().get(Ref::<XObject>::new(image))? {
+            XObject::Image(ref image) => (image.width as f32, image.height as f32),
+            _ => bail!("{:?} is not an image XObject", image),
+        };
+        let (aw, ah) = ((width - 2. * border).max(0.), (height - 2. * border).max(0.));
+        let [sx, sy, x, y] = fit.place([iw, ih], [aw, ah])?;
+        let (x, y) = (x + border, y + border);
+
+        let form = |bbox: [f32; 2], name: &str, xobject: PlainRef| {
+            let mut xobjects = Dictionary::new();
+            xobjects.insert(name, Primitive::Reference(xobject));
+            let mut resources = Dictionary::new();
+            resources.insert("XObject", xobjects);
+            let mut info = Dictionary::new();
+            info.insert("Type", Primitive::name("XObject"));
+            info.insert("Subtype", Primitive::name("Form"));
+            info.insert("BBox", vec![0.into(), 0.into(), bbox[0].into(), bbox[1].into()]);
+            info.insert("Resources", resources);
+            info
+        };
+        let ops = format!("q\n{} 0 0 {} 0 0 cm\n/Im0 Do\nQ\n", iw, ih);
+        let icon = self.create(Stream::new(form([iw, ih], "Im0", image), ops.into_bytes()))?.get_ref().get_inner();
+        // an icon that is not scaled down may be too big
+        let ops = format!("q\n{b} {b} {} {} re W n\n{} 0 0 {} {} {} cm\n/Icon Do\nQ\n", aw, ah, sx, sy, x, y, b = border);
+        let appearance = self.create(Stream::new(form([width, height], "Icon", icon), ops.into_bytes()))?.get_ref().get_inner();
+
+        let mut ap = match dict.get("AP") {
+            Some(p) => resolve_dict(&self.resolver(), p)?,
+            None => Dictionary::new(),
+        };
+        ap.insert("N", Primitive::Reference(appearance));
+        // the old down and rollover appearances would show the old image
+        ap.remove("D");
+        ap.remove("R");
+        dict.insert("AP", ap);
+        mk.insert("I", Primitive::Reference(icon));
+        mk.insert("TP", Primitive::Integer(1));
+        dict.insert("MK", mk);
+
+        self.update(widget, Primitive::Dictionary(dict))?;
+        Ok(())
+    }
+}
+
+/// When the icon of a button is scaled to fit the widget, `/SW` in the icon fit dictionary.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum IconScale {
+    Always,
+    Bigger,
+    Smaller,
+    Never,
+}
+
+/// The icon fit dictionary, `/MK /IF`.
+#[derive(Debug, Clone)]
+struct IconFit {
+    scale: IconScale,
+    proportional: bool,
+    /// where the space that is left goes, as the part left of and below the icon
+    align: [f32; 2],
+    full_bounds: bool,
+}
+impl Default for IconFit {
+    fn default() -> IconFit {
+        IconFit { scale: IconScale::Always, proportional: true, align: [0.5, 0.5], full_bounds: false }
+    }
+}
+impl IconFit {
+    fn from_dict(dict: &Dictionary) -> Result<IconFit> {
+        let mut fit = IconFit::default();
+        if let Some(sw) = dict.get("SW") {
+            fit.scale = match sw.as_name()? {
+                "A" => IconScale::Always,
+                "B" => IconScale::Bigger,
+                "S" => IconScale::Smaller,
+                "N" => IconScale::Never,
+                sw => bail!("invalid icon scaling {}", sw),
+            };
+        }
+        if let Some(s) = dict.get("S") {
+            fit.proportional = s.as_name()? != "A";
+        }
+        if let Some(Primitive::Array(align)) = dict.get("A") {
+            if let [x, y] = &align[..] {
+                fit.align = [x.as_number()?, y.as_number()?];
+            }
+        }
+        if let Some(fb) = dict.get("FB") {
+            fit.full_bounds = fb.as_bool()?;
+        }
+        Ok(fit)
+    }
+
+    /// The scale and the position of an icon of size `icon` in the area `area`.
+    /// Both have to be larger than 0 in each direction.
+    fn place(&self, [iw, ih]: [f32; 2], [aw, ah]: [f32; 2]) -> Result<[f32; 4]> {
+        if ![iw, ih, aw, ah].iter().all(|&d| d > 0.) {
+            bail!("can't place an icon of {}x{} in an area of {}x{}", iw, ih, aw, ah);
+        }
+        let scale = match self.scale {
+            IconScale::Always => true,
+            IconScale::Bigger => iw > aw || ih > ah,
+            IconScale::Smaller => iw < aw && ih < ah,
+            IconScale::Never => false,
+        };
+        let (sx, sy) = match (scale, self.proportional) {
+            (false, _) => (1., 1.),
+            (true, true) => {
+                let s = (aw / iw).min(ah / ih);
+                (s, s)
+            }
+            (true, false) => (aw / iw, ah / ih),
+        };
+        Ok([sx, sy, (aw - iw * sx) * self.align[0], (ah - ih * sy) * self.align[1]])
+    }
+}
+
+/// The value of `key` in the page `dict` or the first of its ancestors that has it.
+fn inherited(resolve: &impl Resolve, dict: &Dictionary, key: &str) -> Result<Option<Primitive>> {
+    let mut dict = dict.clone();
+    let mut seen = HashSet::new();
+    loop {
+        if let Some(p) = dict.get(key) {
+            return Ok(Some(p.clone()));
+        }
+        match dict.get("Parent") {
+            Some(&Primitive::Reference(parent)) if seen.insert(parent) => {
+                dict = resolve.resolve(parent)?.into_dictionary()?;
+            }
+            _ => return Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitive::Name;
+
+    fn image(dict: ImageDict, data: &[u8]) -> ImageXObject {
+        ImageXObject { inner: Stream::new(dict, data.to_vec()) }
+    }
+
+    #[test]
+    fn indexed_with_color_key() {
+        // two bits per index, the second row starts at the next byte
+        let palette: Arc<[u8]> = vec![255, 0, 0, 0, 255, 0, 0, 0, 255].into();
+        let dict = ImageDict {
+            width: 3,
+            height: 2,
+            color_space: Some(ColorSpace::Indexed(Box::new(ColorSpace::DeviceRGB), 2, palette)),
+            bits_per_component: Some(2),
+            mask: Some(Primitive::Array(vec![2.into(), 2.into()])),
+            ..Default::default()
+        };
+        let pixels = image(dict, &[0b00_01_10_00, 0b10_10_01_00]).pixels(&NoResolve).unwrap();
+        assert_eq!(pixels.format, PixelFormat::Rgba);
+        assert_eq!(pixels.data, [
+            255, 0, 0, 255,  0, 255, 0, 255,  0, 0, 255, 0,
+            0, 0, 255, 0,  0, 0, 255, 0,  0, 255, 0, 255,
+        ]);
+    }
+
+    #[test]
+    fn decode_and_depth() {
+        let dict = ImageDict {
+            width: 2,
+            height: 1,
+            color_space: Some(ColorSpace::DeviceGray),
+            bits_per_component: Some(16),
+            decode: Some(vec![1., 0.]),
+            ..Default::default()
+        };
+        let pixels = image(dict, &[0, 0, 0xFF, 0xFF]).pixels(&NoResolve).unwrap();
+        assert_eq!(pixels.format, PixelFormat::Gray);
+        assert_eq!(pixels.data, [255, 0]);
+
+        let dict = ImageDict {
+            width: 1,
+            height: 1,
+            color_space: Some(ColorSpace::DeviceCMYK),
+            bits_per_component: Some(8),
+            ..Default::default()
+        };
+        let pixels = image(dict, &[0, 255, 255, 0]).pixels(&NoResolve).unwrap();
+        assert_eq!(pixels.data, [255, 0, 0]);
+    }
+
+    #[test]
+    fn invalid_component_counts() {
+        let image_in = |cs: ColorSpace, data: &[u8]| {
+            let dict = ImageDict { width: 1, height: 1, color_space: Some(cs), bits_per_component: Some(8), ..Default::default() };
+            image(dict, data).pixels(&NoResolve)
+        };
+        let device_n = |n: usize| ColorSpace::DeviceN {
+            names: vec![Name::from("Spot"); n],
+            alt: Box::new(ColorSpace::DeviceGray),
+            tint: Function::Calculator,
+            attr: None,
+        };
+        let icc = |components: u32, alternate: Option<ColorSpace>| {
+            let info = IccInfo { components, alternate: alternate.map(Box::new), range: None, metadata: None };
+            ColorSpace::Icc(RcRef::new(PlainRef { id: 1, gen: 0 }, Stream::new(info, vec![]).into()))
+        };
+        assert!(image_in(device_n(0), &[0; 64]).is_err());
+        assert!(image_in(device_n(33), &[0; 64]).is_err());
+        assert!(image_in(icc(0, None), &[0; 64]).is_err());
+        assert!(image_in(icc(1, Some(ColorSpace::DeviceCMYK)), &[0; 64]).is_err());
+        let pixels = image_in(icc(4, Some(ColorSpace::DeviceCMYK)), &[0, 255, 255, 0]).unwrap();
+        assert_eq!(pixels.data, [255, 0, 0]);
+    }
+
+    #[test]
+    fn icon_fit() {
+        let fit = |entries: &[(&str, Primitive)]| {
+            let mut dict = Dictionary::new();
+            for (key, value) in entries {
+                dict.insert(*key, value.clone());
+            }
+            IconFit::from_dict(&dict)
+        };
+        // scaled proportionally and centered
+        assert_eq!(fit(&[]).unwrap().place([20., 10.], [40., 40.]).unwrap(), [2., 2., 0., 10.]);
+        let anisotropic = fit(&[("S", Primitive::name("A"))]).unwrap();
+        assert_eq!(anisotropic.place([20., 10.], [40., 40.]).unwrap(), [2., 4., 0., 0.]);
+        // only scaled down, in the lower left corner
+        let bigger = fit(&[("SW", Primitive::name("B")), ("A", vec![0.into(), 0.into()].into())]).unwrap();
+        assert_eq!(bigger.place([20., 10.], [40., 40.]).unwrap(), [1., 1., 0., 0.]);
+        assert_eq!(bigger.place([80., 10.], [40., 40.]).unwrap(), [0.5, 0.5, 0., 0.]);
+        let never = fit(&[("SW", Primitive::name("N")), ("A", vec![1.into(), 0.5.into()].into())]).unwrap();
+        assert_eq!(never.place([20., 10.], [40., 40.]).unwrap(), [1., 1., 20., 15.]);
+        assert!(fit(&[("SW", Primitive::name("X"))]).is_err());
+        // a widget without area, or an empty image
+        assert!(fit(&[]).unwrap().place([20., 10.], [0., 40.]).is_err());
+        assert!(never.place([20., 10.], [40., -1.]).is_err());
+        assert!(fit(&[]).unwrap().place([0., 10.], [40., 40.]).is_err());
+        assert!(fit(&[]).unwrap().place([20., f32::NAN], [40., 40.]).is_err());
+    }
+
+    #[test]
+    fn truncated_data() {
+        let gray = |width: u32, height: u32| ImageDict {
+            width,
+            height,
+            color_space: Some(ColorSpace::DeviceGray),
+            bits_per_component: Some(8),
+            ..Default::default()
+        };
+        assert!(image(gray(2, 2), &[0; 3]).pixels(&NoResolve).is_err());
+        assert!(image(gray(100_000, 100_000), &[0; 9]).pixels(&NoResolve).is_err());
+        assert!(image(gray(u32::MAX, u32::MAX), &[0; 9]).pixels(&NoResolve).is_err());
+        assert_eq!(image(gray(2, 2), &[0; 4]).pixels(&NoResolve).unwrap().data, [0; 4]);
+    }
+
+    #[test]
+    fn image_mask() {
+        let dict = ImageDict {
+            width: 3,
+            height: 1,
+            image_mask: true,
+            ..Default::default()
+        };
+        let pixels = image(dict, &[0b010_00000]).pixels(&NoResolve).unwrap();
+        assert_eq!(pixels.data, [0, 0, 0, 255,  0, 0, 0, 0,  0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,608 @@
+//! Walking content streams with their graphics state.
+//!
+//! [`interpret_page`] and [`interpret_form`] run the operators of a content stream and keep
+//! track of the graphics state: the CTM, clipping paths, colors and their color spaces, line
+//! style, and the text state. Form XObjects are followed with their `/Matrix` and resources.
+//! Whatever the content draws is passed to a [`Visitor`], together with the state it is drawn
+//! with, so that text extraction, flattening or redaction don't have to do this themselves.
+//!
+//! Resources that are missing or broken are skipped with a warning, like a viewer would.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::content::*;
+use crate::content::{LineCap, LineJoin};
+use crate::encoding::{BaseEncoding, Encoding};
+use crate::error::*;
+use crate::font::{Font, FontData, FontDescriptor, FontType, ToUnicodeMap, Widths};
+use crate::object::{self, *};
+use crate::primitive::{Name, Primitive};
+
+/// Called for what the content draws. All methods do nothing by default.
+pub trait Visitor {
+    /// A path is painted. The points are in the user space of `state.ctm`.
+    fn path(&mut self, _path: &[PathOp], _paint: Paint, _state: &GraphicsState) -> Result<()> {
+        Ok(())
+    }
+    /// A string is shown with the font in `state.text`.
+    fn text(&mut self, _glyphs: &[Glyph], _state: &GraphicsState) -> Result<()> {
+        Ok(())
+    }
+    /// An image is drawn into the unit square of `state.ctm`. `inline` is set for images
+    /// in the content stream.
+    fn image(&mut self, _image: &ImageXObject, _inline: bool, _state: &GraphicsState) -> Result<()> {
+        Ok(())
+    }
+    /// The clipping area is filled with the shading dictionary `shading`.
+    fn shading(&mut self, _shading: &Primitive, _state: &GraphicsState) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PathOp {
+    MoveTo(Point),
+    LineTo(Point),
+    CurveTo(Point, Point, Point),
+    Rect(ViewRect),
+    Close,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Paint {
+    Stroke,
+    Fill(Winding),
+    FillAndStroke(Winding),
+}
+
+/// A clipping path, with the CTM it was set with.
+#[derive(Debug)]
+pub struct ClipPath {
+    pub path: Vec<PathOp>,
+    pub winding: Winding,
+    pub ctm: Matrix,
+}
+
+/// A glyph of a shown string.
+#[derive(Debug, Clone)]
+pub struct Glyph {
+    pub code: u16,
+    /// `None` if the font doesn't say which text the code stands for
+    pub text: Option<String>,
+    /// Maps glyph space, where the font size is 1, to the user space of the page
+    pub transform: Matrix,
+    /// the advance width in glyph space
+    pub width: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphicsState {
+    pub ctm: Matrix,
+    /// all clipping paths in effect, the area drawn to is the intersection of them
+    pub clip: Vec<Rc<ClipPath>>,
+    pub fill_color: Color,
+    pub fill_space: ColorSpace,
+    pub stroke_color: Color,
+    pub stroke_space: ColorSpace,
+    pub fill_alpha: f32,
+    pub stroke_alpha: f32,
+    pub line_width: f32,
+    pub line_cap: LineCap,
+    pub line_join: LineJoin,
+    pub miter_limit: f32,
+    pub dash: (Vec<f32>, f32),
+    pub text: TextState,
+}
+impl Default for GraphicsState {
+    fn default() -> Self {
+        GraphicsState {
+            ctm: Matrix::default(),
+            clip: vec![],
+            fill_color: Color::Gray(0.),
+            fill_space: ColorSpace::DeviceGray,
+            stroke_color: Color::Gray(0.),
+            stroke_space: ColorSpace::DeviceGray,
+            fill_alpha: 1.,
+            stroke_alpha: 1.,
+            line_width: 1.,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            miter_limit: 10.,
+            dash: (vec![], 0.),
+            text: TextState::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TextState {
+    pub font: Option<Rc<TextFont>>,
+    pub size: f32,
+    pub char_space: f32,
+    pub word_space: f32,
+    /// 1 for 100%
+    pub horiz_scale: f32,
+    pub leading: f32,
+    pub rise: f32,
+    pub mode: TextMode,
+    pub matrix: Matrix,
+    pub line_matrix: Matrix,
+}
+impl Default for TextState {
+    fn default() -> Self {
+        TextState {
+            font: None,
+            size: 0.,
+            char_space: 0.,
+            word_space: 0.,
+            horiz_scale: 1.,
+            leading: 0.,
+            rise: 0.,
+            mode: TextMode::Fill,
+            matrix: Matrix::default(),
+            line_matrix: Matrix::default(),
+        }
+    }
+}
+
+/// A font with what is needed to map the codes of shown strings to text and widths.
+pub struct TextFont {
+    pub font: MaybeRef<Font>,
+    /// in glyph space
+    pub ascent: f32,
+    pub descent: f32,
+    to_unicode: Option<ToUnicodeMap>,
+    encoding: Encoding,
+    widths: Option<Widths>,
+    // codes are two bytes long
+    cid: bool,
+}
+impl std::fmt::Debug for TextFont {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TextFont").field("name", &self.font.name).finish()
+    }
+}
+
+impl TextFont {
+    pub fn new(font: MaybeRef<Font>, resolve: &impl Resolve) -> TextFont {
+        let to_unicode = match font.to_unicode(resolve) {
+            Some(Ok(map)) => Some(map),
+            Some(Err(e)) => {
+                warn!("invalid ToUnicode map of {:?}: {}", font.name, e);
+                None
+            }
+            None => None,
+        };
+        let widths = font.widths(resolve).unwrap_or_else(|e| {
+            warn!("invalid widths of {:?}: {}", font.name, e);
+            None
+        });
+        let mut encoding = font.encoding().cloned().unwrap_or_else(|| Encoding {
+            base: BaseEncoding::None,
+            differences: HashMap::new(),
+        });
+        if encoding.base == BaseEncoding::None {
+            // the built-in encoding of the font, which is most likely one of these
+            encoding.base = match font.subtype {
+                FontType::Type1 | FontType::MMType1 => BaseEncoding::StandardEncoding,
+                _ => BaseEncoding::WinAnsiEncoding,
+            };
+        }
+        let descriptor = match font.data {
+            FontData::Type0(ref t) => t.descendant_fonts.first().and_then(|f| match f.data {
+                FontData::CIDFontType0(ref c) | FontData::CIDFontType2(ref c) => Some(&c.font_descriptor),
+                _ => None,
+            }),
+            _ => font.info().and_then(|info| info.font_descriptor.as_ref()),
+        };
+        let (ascent, descent) = match descriptor {
+            Some(&FontDescriptor { ascent: Some(ascent), descent: Some(descent), .. }) if ascent > descent => (ascent * 0.001, descent * 0.001),
+            _ => (0.8, -0.2),
+        };
+        let cid = font.is_cid();
+        TextFont { font, ascent, descent, to_unicode, encoding, widths, cid }
+    }
+
+    /// The code, text and advance width in glyph space of each glyph in `data`.
+    pub fn decode<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item=(u16, Option<String>, f32)> + 'a {
+        let step = if self.cid { 2 } else { 1 };
+        data.chunks(step).map(move |bytes| {
+            let code = bytes.iter().fold(0, |code, &b| code << 8 | b as u16);
+            let text = match self.to_unicode.as_ref().and_then(|map| map.get(code)) {
+                Some(text) => Some(text.into()),
+                None if !self.cid => self.encoding.decode(code as u8),
+                None => None,
+            };
+            let width = match self.widths {
+                Some(ref widths) => widths.get(code as usize) * 0.001,
+                None => 0.5,
+            };
+            (code, text, width)
+        })
+    }
+
+    fn is_space(&self, code: u16) -> bool {
+        !self.cid && code == 32
+    }
+}
+
+/// Run the content of `page`.
+pub fn interpret_page(page: &Page, resolve: &impl Resolve, visitor: &mut impl Visitor) -> Result<()> {
+    let ops = match page.contents {
+        Some(ref contents) => contents.operations(resolve)?,
+        None => return Ok(()),
+    };
+    let resources = page.resources().ok();
+    let mut interpreter = Interpreter { resolve, visitor, forms: vec![] };
+    interpreter.ops(&ops, resources.map(|r| &**r), GraphicsState::default())
+}
+
+/// Run the content of `form` with `state`, which should already include the `/Matrix` of the form.
+pub fn interpret_form(form: &FormXObject, resolve: &impl Resolve, state: GraphicsState, visitor: &mut impl Visitor) -> Result<()> {
+    // like forms drawn by a page, a form that can't be read draws nothing
+    let ops = match form.operations(resolve) {
+        Ok(ops) => ops,
+        Err(e) => {
+            warn!("can't read the content of the form: {}", e);
+            return Ok(());
+        }
+    };
+    let mut interpreter = Interpreter { resolve, visitor, forms: vec![] };
+    interpreter.ops(&ops, form.dict().resources.as_deref(), state)
+}
+
+struct Interpreter<'a, R, V> {
+    resolve: &'a R,
+    visitor: &'a mut V,
+    // the form XObjects being run
+    forms: Vec<PlainRef>,
+}
+impl<R: Resolve, V: Visitor> Interpreter<'_, R, V> {
+    fn ops(&mut self, ops: &[Op], resources: Option<&Resources>, mut state: GraphicsState) -> Result<()> {
+        let resolve = self.resolve;
+        let mut fonts: HashMap<&Name, Option<Rc<TextFont>>> = HashMap::new();
+        let mut stack = vec![];
+        let mut path = vec![];
+        let mut clip = None;
+
+        for op in ops {
+            match *op {
+                Op::MoveTo { p } => path.push(PathOp::MoveTo(p)),
+                Op::LineTo { p } => path.push(PathOp::LineTo(p)),
+                Op::CurveTo { c1, c2, p } => path.push(PathOp::CurveTo(c1, c2, p)),
+                Op::Rect { rect } => path.push(PathOp::Rect(rect)),
+                Op::Close => path.push(PathOp::Close),
+                Op::Clip { winding } => clip = Some(winding),
+                Op::EndPath | Op::Stroke | Op::Fill { .. } | Op::FillAndStroke { .. } => {
+                    let paint = match *op {
+                        Op::Stroke => Some(Paint::Stroke),
+                        Op::Fill { winding } => Some(Paint::Fill(winding)),
+                        Op::FillAndStroke { winding } => Some(Paint::FillAndStroke(winding)),
+                        _ => None,
+                    };
+                    if let Some(paint) = paint {
+                        self.visitor.path(&path, paint, &state)?;
+                    }
+                    // the clipping path applies after the path is painted
+                    if let Some(winding) = clip.take() {
+                        state.clip.push(Rc::new(ClipPath { path: path.clone(), winding, ctm: state.ctm }));
+                    }
+                    path.clear();
+                }
+                Op::Shade { ref name } => match resources.and_then(|r| r.shadings.get(name)).map(|s| s.clone().resolve(resolve)) {
+                    Some(Ok(shading)) => self.visitor.shading(&shading, &state)?,
+                    Some(Err(e)) => warn!("can't load shading {}: {}", name, e),
+                    None => warn!("missing shading {}", name),
+                },
+
+                Op::Save => stack.push(state.clone()),
+                Op::Restore => if let Some(s) = stack.pop() {
+                    state = s;
+                },
+                Op::Transform { matrix } => state.ctm = matrix * state.ctm,
+                Op::LineWidth { width } => state.line_width = width,
+                Op::Dash { ref pattern, phase } => state.dash = (pattern.clone(), phase),
+                Op::LineJoin { join } => state.line_join = join,
+                Op::LineCap { cap } => state.line_cap = cap,
+                Op::MiterLimit { limit } => state.miter_limit = limit,
+                Op::GraphicsState { ref name } => match resources.and_then(|r| r.graphics_states.get(name)) {
+                    Some(gs) => self.graphics_state(gs, &mut state),
+                    None => warn!("missing graphics state {}", name),
+                },
+                Op::FillColorSpace { ref name } => {
+                    state.fill_space = color_space(name, resources);
+                    state.fill_color = initial_color(&state.fill_space);
+                }
+                Op::StrokeColorSpace { ref name } => {
+                    state.stroke_space = color_space(name, resources);
+                    state.stroke_color = initial_color(&state.stroke_space);
+                }
+                Op::FillColor { ref color } => set_color(color, &mut state.fill_color, &mut state.fill_space),
+                Op::StrokeColor { ref color } => set_color(color, &mut state.stroke_color, &mut state.stroke_space),
+
+                Op::BeginText => {
+                    state.text.matrix = Matrix::default();
+                    state.text.line_matrix = Matrix::default();
+                }
+                Op::CharSpacing { char_space } => state.text.char_space = char_space,
+                Op::WordSpacing { word_space } => state.text.word_space = word_space,
+                Op::TextScaling { horiz_scale } => state.text.horiz_scale = horiz_scale * 0.01,
+                Op::Leading { leading } => state.text.leading = leading,
+                Op::TextRise { rise } => state.text.rise = rise,
+                Op::TextRenderMode { mode } => state.text.mode = mode,
+                Op::TextFont { ref name, size } => {
+                    let font = fonts.entry(name).or_insert_with(|| {
+                        let font = resources.and_then(|r| r.fonts.get(name));
+                        match font.map(|font| font.load(resolve)) {
+                            Some(Ok(font)) => Some(Rc::new(TextFont::new(font, resolve))),
+                            Some(Err(e)) => {
+                                warn!("can't load font {}: {}", name, e);
+                                None
+                            }
+                            None => {
+                                warn!("missing font {}", name);
+                                None
+                            }
+                        }
+                    });
+                    state.text.font = font.clone();
+                    state.text.size = size;
+                }
+                Op::MoveTextPosition { translation } => {
+                    state.text.line_matrix = Matrix::translate(translation.x, translation.y) * state.text.line_matrix;
+                    state.text.matrix = state.text.line_matrix;
+                }
+                Op::SetTextMatrix { matrix } => {
+                    state.text.line_matrix = matrix;
+                    state.text.matrix = matrix;
+                }
+                Op::TextNewline => {
+                    state.text.line_matrix = Matrix::translate(0., -state.text.leading) * state.text.line_matrix;
+                    state.text.matrix = state.text.line_matrix;
+                }
+                Op::TextDraw { ref text } => self.show(text.as_bytes(), &mut state)?,
+                Op::TextDrawAdjusted { ref array } => {
+                    for part in array {
+                        match *part {
+                            TextDrawAdjusted::Text(ref text) => self.show(text.as_bytes(), &mut state)?,
+                            TextDrawAdjusted::Spacing(n) => {
+                                let advance = -n * 0.001 * state.text.size * state.text.horiz_scale;
+                                state.text.matrix = Matrix::translate(advance, 0.) * state.text.matrix;
+                            }
+                        }
+                    }
+                }
+
+                Op::XObject { ref name } => {
+                    let r = match resources.and_then(|r| r.xobjects.get(name)) {
+                        Some(&r) => r,
+                        None => {
+                            warn!("missing XObject {}", name);
+                            continue;
+                        }
+                    };
+                    if self.forms.contains(&r.get_inner()) {
+                        warn!("XObject {} draws itself", name);
+                        continue;
+                    }
+                    let xobject = match resolve.get(r) {
+                        Ok(xobject) => xobject,
+                        Err(e) => {
+                            warn!("can't load XObject {}: {}", name, e);
+                            continue;
+                        }
+                    };
+                    match *xobject {
+                        XObject::Form(ref form) => {
+                            let mut inner = state.clone();
+                            if let Some(ref matrix) = form.dict().matrix {
+                                match Matrix::from_primitive(matrix.clone(), resolve) {
+                                    Ok(matrix) => inner.ctm = matrix * state.ctm,
+                                    Err(e) => {
+                                        warn!("invalid /Matrix of XObject {}: {}", name, e);
+                                        continue;
+                                    }
+                                }
+                            }
+                            let ops = match form.operations(resolve) {
+                                Ok(ops) => ops,
+                                Err(e) => {
+                                    warn!("can't read the content of XObject {}: {}", name, e);
+                                    continue;
+                                }
+                            };
+                            let form_resources = form.dict().resources.as_deref().or(resources);
+                            self.forms.push(r.get_inner());
+                            self.ops(&ops, form_resources, inner)?;
+                            self.forms.pop();
+                        }
+                        XObject::Image(ref image) => self.visitor.image(image, false, &state)?,
+                        XObject::Postscript(_) => {}
+                    }
+                }
+                Op::InlineImage { ref image } => match image.color_space {
+                    // inline images name color spaces of the resources
+                    Some(ColorSpace::Named(ref name)) => {
+                        let mut image = (**image).clone();
+                        image.inner.info.info.color_space = Some(color_space(name, resources));
+                        self.visitor.image(&image, true, &state)?
+                    }
+                    _ => self.visitor.image(image, true, &state)?,
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn show(&mut self, data: &[u8], state: &mut GraphicsState) -> Result<()> {
+        let font = match state.text.font {
+            Some(ref font) => font.clone(),
+            None => return Ok(()),
+        };
+        let text = &mut state.text;
+        let mut glyphs = vec![];
+        for (code, glyph_text, width) in font.decode(data) {
+            let transform = Matrix {
+                a: text.size * text.horiz_scale,
+                d: text.size,
+                f: text.rise,
+                ..Matrix::default()
+            } * text.matrix * state.ctm;
+            glyphs.push(Glyph { code, text: glyph_text, transform, width });
+
+            let mut advance = width * text.size + text.char_space;
+            if font.is_space(code) {
+                advance += text.word_space;
+            }
+            text.matrix = Matrix::translate(advance * text.horiz_scale, 0.) * text.matrix;
+        }
+        self.visitor.text(&glyphs, state)
+    }
+
+    fn graphics_state(&self, gs: &GraphicsStateParameters, state: &mut GraphicsState) {
+        if let Some(width) = gs.line_width {
+            state.line_width = width;
+        }
+        if let Some(cap) = gs.line_cap {
+            state.line_cap = match cap {
+                object::LineCap::Butt => LineCap::Butt,
+                object::LineCap::Round => LineCap::Round,
+                object::LineCap::Square => LineCap::Square,
+            };
+        }
+        if let Some(join) = gs.line_join {
+            state.line_join = match join {
+                object::LineJoin::Miter => LineJoin::Miter,
+                object::LineJoin::Round => LineJoin::Round,
+                object::LineJoin::Bevel => LineJoin::Bevel,
+            };
+        }
+        if let Some(limit) = gs.miter_limit {
+            state.miter_limit = limit;
+        }
+        if let Some(alpha) = gs.fill_alpha {
+            state.fill_alpha = alpha;
+        }
+        if let Some(alpha) = gs.stroke_alpha {
+            state.stroke_alpha = alpha;
+        }
+        if let Some((font, size)) = gs.font {
+            match self.resolve.get(font) {
+                Ok(font) => {
+                    state.text.font = Some(Rc::new(TextFont::new(MaybeRef::Indirect(font), self.resolve)));
+                    state.text.size = size;
+                }
+                Err(e) => warn!("can't load font of graphics state: {}", e),
+            }
+        }
+    }
+}
+
+fn color_space(name: &Name, resources: Option<&Resources>) -> ColorSpace {
+    match resources.and_then(|r| r.color_spaces.get(name)) {
+        Some(cs) => cs.clone(),
+        None => match ColorSpace::from_primitive(Primitive::name(name.as_str()), &NoResolve) {
+            Ok(cs) => cs,
+            Err(_) => ColorSpace::Named(name.clone()),
+        }
+    }
+}
+
+fn initial_color(cs: &ColorSpace) -> Color {
+    match *cs {
+        ColorSpace::DeviceGray | ColorSpace::CalGray(_) => Color::Gray(0.),
+        ColorSpace::DeviceRGB | ColorSpace::CalRGB(_) => Color::Rgb(Rgb { red: 0., green: 0., blue: 0. }),
+        ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_) => Color::Cmyk(Cmyk { cyan: 0., magenta: 0., yellow: 0., key: 1. }),
+        ColorSpace::Pattern => Color::Other(vec![]),
+        _ => Color::Other(vec![Primitive::Integer(0)]),
+    }
+}
+
+/// Set the color, and the color space for `g`, `rg` and `k`.
+/// Colors set with `sc` and `scn` in a device color space are turned into device colors.
+fn set_color(color: &Color, current: &mut Color, cs: &mut ColorSpace) {
+    *current = match *color {
+        Color::Gray(_) => {
+            *cs = ColorSpace::DeviceGray;
+            color.clone()
+        }
+        Color::Rgb(_) => {
+            *cs = ColorSpace::DeviceRGB;
+            color.clone()
+        }
+        Color::Cmyk(_) => {
+            *cs = ColorSpace::DeviceCMYK;
+            color.clone()
+        }
+        Color::Other(ref args) => {
+            let n: Vec<f32> = args.iter().filter_map(|p| p.as_number().ok()).collect();
+            match (&*cs, n.as_slice()) {
+                (ColorSpace::DeviceGray, &[gray]) => Color::Gray(gray),
+                (ColorSpace::DeviceRGB, &[red, green, blue]) => Color::Rgb(Rgb { red, green, blue }),
+                (ColorSpace::DeviceCMYK, &[cyan, magenta, yellow, key]) => Color::Cmyk(Cmyk { cyan, magenta, yellow, key }),
+                _ => color.clone(),
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        paths: Vec<(Vec<PathOp>, Paint, GraphicsState)>,
+        shadings: Vec<GraphicsState>,
+    }
+    impl Visitor for Recorder {
+        fn path(&mut self, path: &[PathOp], paint: Paint, state: &GraphicsState) -> Result<()> {
+            self.paths.push((path.to_vec(), paint, state.clone()));
+            Ok(())
+        }
+        fn shading(&mut self, _shading: &Primitive, state: &GraphicsState) -> Result<()> {
+            self.shadings.push(state.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn graphics_state() {
+        let content = b"
+            q 2 0 0 2 10 10 cm
+            0 0 5 5 re W n
+            /DeviceRGB cs 1 0 0 sc
+            0 0 m 1 1 l S
+            /Sh sh
+            Q
+            0 0 1 1 re f*
+        ";
+        let ops = parse_ops(content, &NoResolve).unwrap();
+        let mut resources = Resources::default();
+        resources.shadings.insert("Sh".into(), Primitive::Dictionary(Default::default()));
+        let mut recorder = Recorder::default();
+        let mut interpreter = Interpreter { resolve: &NoResolve, visitor: &mut recorder, forms: vec![] };
+        interpreter.ops(&ops, Some(&resources), GraphicsState::default()).unwrap();
+
+        // `n` only sets the clipping path
+        assert_eq!(recorder.paths.len(), 2);
+        let (ref path, paint, ref state) = recorder.paths[0];
+        assert_eq!(path.len(), 2);
+        assert_eq!(paint, Paint::Stroke);
+        assert_eq!(state.ctm, Matrix { a: 2., b: 0., c: 0., d: 2., e: 10., f: 10. });
+        assert_eq!(state.clip.len(), 1);
+        assert_eq!(state.clip[0].ctm, state.ctm);
+        assert_eq!(state.stroke_color, Color::Gray(0.));
+        assert_eq!(state.fill_color, Color::Rgb(Rgb { red: 1., green: 0., blue: 0. }));
+        assert_eq!(recorder.shadings.len(), 1);
+        assert_eq!(recorder.shadings[0].clip.len(), 1);
+
+        let (_, paint, ref state) = recorder.paths[1];
+        assert_eq!(paint, Paint::Fill(Winding::EvenOdd));
+        assert_eq!(state.ctm, Matrix::default());
+        assert!(state.clip.is_empty());
+        assert_eq!(state.fill_color, Color::Gray(0.));
+    }
+}
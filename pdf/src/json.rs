@@ -0,0 +1,242 @@
+//! Machine-readable dumps of a document, in the spirit of `qpdf --json`.
+//!
+//! Primitives are written the way qpdf writes them: names as `"/Name"`, references as `"12 0 R"`,
+//! text strings as `"u:text"` and other strings as `"b:"` followed by hex digits.
+//! A stream becomes `{"stream": {"dict": {..}, "data": ".."}}`, with the decoded data only if
+//! requested. Objects that can't be read become `{"error": ".."}`, so one broken object doesn't
+//! hide the rest of the document.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write;
+use std::sync::Arc;
+use serde_json::{json, Map, Value};
+
+use crate::any::AnySync;
+use crate::backend::Backend;
+use crate::error::*;
+use crate::file::{Cache, File, Log};
+use crate::object::*;
+use crate::primitive::{Dictionary, PdfStream, PdfString, Primitive};
+use crate::xref::XRef;
+
+/// What goes into a JSON dump.
+#[derive(Debug, Clone, Default)]
+pub struct JsonOptions {
+    stream_data: bool,
+}
+impl JsonOptions {
+    pub fn new() -> Self {
+        JsonOptions::default()
+    }
+    /// Include the decoded data of streams.
+    pub fn stream_data(mut self, stream_data: bool) -> Self {
+        self.stream_data = stream_data;
+        self
+    }
+}
+
+/// Convert a primitive. References are written as they are, not followed.
+pub fn primitive_to_json(p: &Primitive, resolve: &impl Resolve, options: &JsonOptions) -> Value {
+    match *p {
+        Primitive::Null => Value::Null,
+        Primitive::Integer(i) => json!(i),
+        // go through the shortest decimal form, so 43.472 doesn't become 43.47200012207031
+        Primitive::Number(n) => json!(n.to_string().parse::<f64>().ok()),
+        Primitive::Boolean(b) => json!(b),
+        Primitive::String(ref s) => Value::String(string_to_json(s)),
+        Primitive::Name(ref name) => Value::String(format!("/{}", name)),
+        Primitive::Reference(r) => Value::String(ref_to_json(r)),
+        Primitive::Array(ref parts) => parts.iter().map(|p| primitive_to_json(p, resolve, options)).collect(),
+        Primitive::Dictionary(ref dict) => dict_to_json(dict, resolve, options),
+        Primitive::Stream(ref stream) => stream_to_json(stream, resolve, options),
+    }
+}
+
+fn dict_to_json(dict: &Dictionary, resolve: &impl Resolve, options: &JsonOptions) -> Value {
+    dict.iter().map(|(key, p)| (key.to_string(), primitive_to_json(p, resolve, options))).collect::<Map<_, _>>().into()
+}
+
+fn stream_to_json(stream: &PdfStream, resolve: &impl Resolve, options: &JsonOptions) -> Value {
+    let mut fields = Map::new();
+    fields.insert("dict".into(), dict_to_json(&stream.info, resolve, options));
+    if options.stream_data {
+        match Stream::<()>::from_stream(stream.clone(), resolve).and_then(|s| s.data(resolve)) {
+            Ok(data) => fields.insert("data".into(), Value::String(bytes_to_json(&data))),
+            Err(e) => fields.insert("error".into(), Value::String(e.to_string())),
+        };
+    }
+    json!({ "stream": fields })
+}
+
+fn string_to_json(s: &PdfString) -> String {
+    match s.to_string() {
+        Ok(text) => format!("u:{}", text),
+        Err(_) => hex(s.as_bytes()),
+    }
+}
+
+fn bytes_to_json(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) => format!("u:{}", text),
+        Err(_) => hex(data),
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    let mut out = String::with_capacity(2 + 2 * data.len());
+    out.push_str("b:");
+    for b in data {
+        write!(out, "{:02x}", b).unwrap();
+    }
+    out
+}
+
+fn ref_to_json(r: PlainRef) -> String {
+    format!("{} {} R", r.id, r.gen)
+}
+
+/// Resolve and convert the object `r`, or describe why it can't be read.
+pub fn object_to_json(r: PlainRef, resolve: &impl Resolve, options: &JsonOptions) -> Value {
+    match resolve.resolve(r) {
+        Ok(p) => primitive_to_json(&p, resolve, options),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+fn xref_to_json(entries: impl Iterator<Item=(ObjNr, XRef)>) -> Value {
+    entries.filter_map(|(id, xref)| Some(match xref {
+        XRef::Free { next_obj_nr, gen_nr } => json!({ "object": id, "type": "free", "next": next_obj_nr, "generation": gen_nr }),
+        XRef::Raw { pos, gen_nr } => json!({ "object": id, "type": "uncompressed", "offset": pos, "generation": gen_nr }),
+        XRef::Stream { stream_id, index } => json!({ "object": id, "type": "compressed", "stream": stream_id, "index": index }),
+        XRef::Promised | XRef::Invalid => return None,
+    })).collect()
+}
+
+// The references in `p`, except those from a field or widget to its parent and page,
+// which would pull in the rest of the document.
+fn child_refs(p: &Primitive) -> Vec<PlainRef> {
+    fn collect(p: &Primitive, out: &mut Vec<PlainRef>) {
+        match *p {
+            Primitive::Reference(r) => out.push(r),
+            Primitive::Array(ref parts) => parts.iter().for_each(|p| collect(p, out)),
+            Primitive::Dictionary(ref dict) => dict.iter().for_each(|(_, p)| collect(p, out)),
+            Primitive::Stream(ref stream) => stream.info.iter().for_each(|(_, p)| collect(p, out)),
+            _ => {}
+        }
+    }
+    let mut out = vec![];
+    let dict = match *p {
+        Primitive::Dictionary(ref dict) => dict,
+        Primitive::Stream(ref stream) => &stream.info,
+        _ => {
+            collect(p, &mut out);
+            return out;
+        }
+    };
+    for (key, p) in dict.iter() {
+        if key != "Parent" && key != "P" {
+            collect(p, &mut out);
+        }
+    }
+    out
+}
+
+impl<B, OC, SC, L> File<B, OC, SC, L>
+where
+    B: Backend,
+    OC: Cache<Result<AnySync, Arc<PdfError>>>,
+    SC: Cache<Result<Arc<[u8]>, Arc<PdfError>>>,
+    L: Log,
+{
+    /// The header version, trailer, cross-reference entries and all objects of the document.
+    pub fn to_json(&self, options: &JsonOptions) -> Value {
+        let resolver = self.resolver();
+        let refs = self.xref_table();
+        let mut objects = Map::new();
+        for id in refs.iter().map(ObjNr::from) {
+            let gen = refs.get(id).map(|xref| xref.get_gen_nr()).unwrap_or(0);
+            let r = PlainRef { id, gen };
+            objects.insert(ref_to_json(r), object_to_json(r, &resolver, options));
+        }
+        json!({
+            "version": self.version().ok().map(|v| v.trim_start_matches("PDF-").to_owned()),
+            "trailer": dict_to_json(self.trailer_dict(), &resolver, options),
+            // the table has an extra entry past /Size
+            "xref": xref_to_json(refs.entries().take(self.trailer.size.max(0) as usize)),
+            "objects": objects,
+        })
+    }
+
+    /// The object `r` on its own.
+    pub fn object_to_json(&self, r: PlainRef, options: &JsonOptions) -> Value {
+        object_to_json(r, &self.resolver(), options)
+    }
+
+    /// The form field with the fully qualified name `name`, and all objects it refers to
+    /// except its parent and page: its kids, widgets, appearance streams and so on.
+    pub fn field_to_json(&self, name: &str, options: &JsonOptions) -> Result<Value> {
+        let root = match t!(self.find_field(name)) {
+            Some(r) => r,
+            None => bail!("no field named {}", name),
+        };
+        let resolver = self.resolver();
+        let mut objects = Map::new();
+        let mut seen = HashSet::from([root]);
+        let mut queue = VecDeque::from([root]);
+        while let Some(r) = queue.pop_front() {
+            let value = match resolver.resolve(r) {
+                Ok(p) => {
+                    queue.extend(child_refs(&p).into_iter().filter(|&r| seen.insert(r)));
+                    primitive_to_json(&p, &resolver, options)
+                }
+                Err(e) => json!({ "error": e.to_string() }),
+            };
+            objects.insert(ref_to_json(r), value);
+        }
+        Ok(json!({
+            "field": name,
+            "object": ref_to_json(root),
+            "objects": objects,
+        }))
+    }
+
+    // Look the field up in the raw objects, so it can be found even if the form doesn't parse.
+    fn find_field(&self, name: &str) -> Result<Option<PlainRef>> {
+        let resolver = self.resolver();
+        let catalog = t!(t!(resolver.resolve(self.trailer.root.get_ref().get_inner())).into_dictionary());
+        let form = match catalog.get("AcroForm") {
+            Some(form) => t!(t!(form.clone().resolve(&resolver)).into_dictionary()),
+            None => return Ok(None),
+        };
+        let fields = match form.get("Fields") {
+            Some(fields) => t!(t!(fields.clone().resolve(&resolver)).into_array()),
+            None => return Ok(None),
+        };
+        let mut stack: Vec<_> = fields.into_iter().rev().map(|p| (p, String::new())).collect();
+        let mut seen = HashSet::new();
+        while let Some((p, parent)) = stack.pop() {
+            let r = match p {
+                Primitive::Reference(r) if seen.insert(r) => r,
+                _ => continue,
+            };
+            let dict = match t!(resolver.resolve(r)) {
+                Primitive::Dictionary(dict) => dict,
+                _ => continue,
+            };
+            // widgets without a partial name are part of their parent
+            let full_name = match dict.get("T") {
+                Some(Primitive::String(t)) if parent.is_empty() => t.to_string_lossy(),
+                Some(Primitive::String(t)) => format!("{}.{}", parent, t.to_string_lossy()),
+                _ => parent,
+            };
+            if full_name == name && dict.get("T").is_some() {
+                return Ok(Some(r));
+            }
+            if let Some(kids) = dict.get("Kids") {
+                let kids = t!(t!(kids.clone().resolve(&resolver)).into_array());
+                stack.extend(kids.into_iter().rev().map(|p| (p, full_name.clone())));
+            }
+        }
+        Ok(None)
+    }
+}
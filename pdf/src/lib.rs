@@ -19,6 +19,13 @@ pub mod font;
 pub mod any;
 pub mod encoding;
 pub mod build;
+pub mod repair;
+mod pages;
+pub mod text;
+pub mod interpret;
+pub mod image;
+#[cfg(feature = "json")]
+pub mod json;
 
 // mod content;
 pub mod enc;
@@ -37,6 +37,7 @@ pub struct ParseOptions {
     pub allow_xref_error: bool,
     pub allow_invalid_ops: bool,
     pub allow_missing_endobj: bool,
+    pub limits: Limits,
 }
 impl ParseOptions {
     pub const fn tolerant() -> Self {
@@ -45,6 +46,7 @@ impl ParseOptions {
             allow_xref_error: true,
             allow_invalid_ops: true,
             allow_missing_endobj: true,
+            limits: Limits::none(),
         }
     }
     pub const fn strict() -> Self {
@@ -53,7 +55,71 @@ impl ParseOptions {
             allow_xref_error: false,
             allow_invalid_ops: true,
             allow_missing_endobj: false,
-        }
+            limits: Limits::none(),
+        }
+    }
+    pub const fn limits(self, limits: Limits) -> Self {
+        ParseOptions { limits, .. self }
+    }
+}
+
+/// Upper bounds on the resources a document may use, to reject hostile files before they
+/// expand into gigabytes. `None` is unlimited.
+///
+/// Each limit has its own `PdfError` variant, see `PdfError::is_limit_exceeded`.
+/// ```
+/// # use pdf::object::{Limits, ParseOptions};
+/// let options = ParseOptions::strict().limits(Limits {
+///     max_stream_size: Some(64 << 20),
+///     ..Limits::none()
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Decoded size of a single stream, in bytes.
+    pub max_stream_size: Option<usize>,
+    /// Decoded size of all streams read from the file, in bytes.
+    /// A stream that is decoded again (because it isn't cached) counts again.
+    pub max_decoded_bytes: Option<usize>,
+    /// Objects in use in the xref table, and objects in a single object stream.
+    pub max_objects: Option<usize>,
+    /// Entries in the xref table, used and free, including its `/Size`.
+    pub max_xref_entries: Option<usize>,
+    /// Elements of an array.
+    pub max_array_len: Option<usize>,
+    /// Entries of a dictionary.
+    pub max_dict_len: Option<usize>,
+    /// Bytes of a string.
+    pub max_string_len: Option<usize>,
+}
+impl Limits {
+    pub const fn none() -> Self {
+        Limits {
+            max_stream_size: None,
+            max_decoded_bytes: None,
+            max_objects: None,
+            max_xref_entries: None,
+            max_array_len: None,
+            max_dict_len: None,
+            max_string_len: None,
+        }
+    }
+    /// Limits for files from untrusted sources, generous enough for ordinary documents.
+    pub const fn untrusted() -> Self {
+        Limits {
+            max_stream_size: Some(256 << 20),
+            max_decoded_bytes: Some(1 << 30),
+            max_objects: Some(1_000_000),
+            max_xref_entries: Some(1_000_000),
+            max_array_len: Some(1 << 20),
+            max_dict_len: Some(1 << 16),
+            max_string_len: Some(16 << 20),
+        }
+    }
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::none()
     }
 }
 
@@ -66,6 +132,39 @@ pub trait Resolve: {
     fn options(&self) -> &ParseOptions;
     fn stream_data(&self, id: PlainRef, range: Range<usize>) -> Result<Arc<[u8]>>;
     fn get_data_or_decode(&self, id: PlainRef, range: Range<usize>, filters: &[StreamFilter]) -> Result<Arc<[u8]>>;
+    /// The decoders for JPX, JBIG2 and custom filters of this document.
+    fn decoders(&self) -> &Decoders {
+        &GLOBAL_DECODERS
+    }
+    /// Count `len` bytes decoded outside of `get_data_or_decode`, like image data,
+    /// towards `Limits::max_decoded_bytes`.
+    fn count_decoded(&self, _len: usize) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<R: Resolve> Resolve for &R {
+    fn resolve_flags(&self, r: PlainRef, flags: ParseFlags, depth: usize) -> Result<Primitive> {
+        (**self).resolve_flags(r, flags, depth)
+    }
+    fn get<T: Object+DataSize>(&self, r: Ref<T>) -> Result<RcRef<T>> {
+        (**self).get(r)
+    }
+    fn options(&self) -> &ParseOptions {
+        (**self).options()
+    }
+    fn stream_data(&self, id: PlainRef, range: Range<usize>) -> Result<Arc<[u8]>> {
+        (**self).stream_data(id, range)
+    }
+    fn get_data_or_decode(&self, id: PlainRef, range: Range<usize>, filters: &[StreamFilter]) -> Result<Arc<[u8]>> {
+        (**self).get_data_or_decode(id, range, filters)
+    }
+    fn decoders(&self) -> &Decoders {
+        (**self).decoders()
+    }
+    fn count_decoded(&self, len: usize) -> Result<()> {
+        (**self).count_decoded(len)
+    }
 }
 
 pub struct NoResolve;
@@ -608,7 +707,8 @@ impl<T: Object> Object for Vec<T> {
             Primitive::Array(_) => {
                 p.resolve(r)?.into_array()?
                     .into_iter()
-                    .map(|p| T::from_primitive(p, r))
+                    .enumerate()
+                    .map(|(i, p)| T::from_primitive(p, r).map_err(|e| e.with_index(i)))
                     .collect::<Result<Vec<T>>>()?
             },
             Primitive::Null => {
@@ -706,7 +806,8 @@ impl<V: Object> Object for HashMap<Name, V> {
             Primitive::Dictionary (dict) => {
                 let mut new = Self::new();
                 for (key, val) in dict.iter() {
-                    new.insert(key.clone(), V::from_primitive(val.clone(), resolve)?);
+                    let val = V::from_primitive(val.clone(), resolve).map_err(|e| e.with_key(key))?;
+                    new.insert(key.clone(), val);
                 }
                 Ok(new)
             }
@@ -741,8 +842,7 @@ impl<T: Object> Object for Option<T> {
             p => match T::from_primitive(p, resolve) {
                 Ok(p) => Ok(Some(p)),
                 // References to non-existing objects ought not to be an error
-                Err(PdfError::NullRef {..}) => Ok(None),
-                Err(PdfError::FreeObject {..}) => Ok(None),
+                Err(e) if matches!(e.without_location(), PdfError::NullRef {..} | PdfError::FreeObject {..}) => Ok(None),
                 Err(e) if resolve.options().allow_error_in_option => {
                     warn!("ignoring {:?}", e);
                     Ok(None)
@@ -5,10 +5,11 @@ use crate::object::*;
 use crate::primitive::*;
 use crate::error::*;
 use crate::parser::Lexer;
-use crate::enc::{StreamFilter, decode};
+use crate::enc::{StreamFilter, encode};
 
 use std::ops::{Deref, Range};
 use std::fmt;
+use std::io::Write;
 
 #[derive(Clone)]
 pub (crate) enum StreamData {
@@ -46,6 +47,15 @@ impl<I: Object> Stream<I> {
             inner_data: StreamData::Generated(data.into()),
         }
     }
+    /// the data is not compressed. it is encoded with the specified filters, the first filter
+    /// being the one that is decoded first.
+    pub fn encoded_with_filters(i: I, data: impl Into<Arc<[u8]>>, filters: Vec<StreamFilter>) -> Result<Stream<I>> {
+        let mut data: Arc<[u8]> = data.into();
+        for filter in filters.iter().rev() {
+            data = t!(encode(&data, filter), filter).into();
+        }
+        Ok(Stream::from_compressed(i, data, filters))
+    }
     pub fn new(i: I, data: impl Into<Arc<[u8]>>) -> Stream<I> {
         Stream {
             info: StreamInfo {
@@ -80,7 +90,8 @@ impl<I: Object> Stream<I> {
                     use std::borrow::Cow;
                     let mut data: Cow<[u8]> = (&**data).into();
                     for filter in filters {
-                        data = t!(decode(&data, filter), filter).into();
+                        let limit = resolve.options().limits.max_stream_size.unwrap_or(usize::MAX);
+                        data = t!(resolve.decoders().decode_limited(&data, filter, limit), filter).into();
                     }
                     Ok(data.into())
                 }
@@ -91,6 +102,18 @@ impl<I: Object> Stream<I> {
         }
     }
 
+    /// Encode the stream with `filter`, on top of the filters already applied.
+    pub fn encode(&mut self, filter: StreamFilter, resolve: &impl Resolve) -> Result<()> {
+        let data = match self.inner_data {
+            StreamData::Generated(ref data) => data.clone(),
+            StreamData::Original(ref range, id) => resolve.stream_data(id, range.clone())?,
+        };
+        let data = t!(encode(&data, &filter), filter);
+        self.info.filters.insert(0, filter);
+        self.inner_data = StreamData::Generated(data.into());
+        Ok(())
+    }
+
     pub fn len(&self) -> usize {
         match self.inner_data {
             StreamData::Generated(ref data) => data.len(),
@@ -119,46 +142,47 @@ impl<I: ObjectWrite> Stream<I> {
             Primitive::Null => Dictionary::new(),
             p => bail!("stream info has to be a dictionary (found {:?})", p)
         };
-        let mut params = None;
-        if self.info.filters.len() > 0 {
-            for f in self.info.filters.iter() {
-                if let Some(para) = match f {
-                    StreamFilter::LZWDecode(ref p) => Some(p.to_primitive(update)?),
-                    StreamFilter::FlateDecode(ref p) => Some(p.to_primitive(update)?),
-                    StreamFilter::DCTDecode(ref p) => Some(p.to_primitive(update)?),
-                    StreamFilter::CCITTFaxDecode(ref p) => Some(p.to_primitive(update)?),
-                    StreamFilter::JBIG2Decode(ref p) => Some(p.to_primitive(update)?),
-                    _ => None
-                } {
-                    assert!(params.is_none());
-                    params = Some(para);
-                }
+        let params = self.info.filters.iter().map(|f| Ok(match f {
+            StreamFilter::LZWDecode(ref p) => Some(p.to_primitive(update)?),
+            StreamFilter::FlateDecode(ref p) => Some(p.to_primitive(update)?),
+            StreamFilter::DCTDecode(ref p) => Some(p.to_primitive(update)?),
+            StreamFilter::CCITTFaxDecode(ref p) => Some(p.to_primitive(update)?),
+            StreamFilter::JBIG2Decode(ref p) => Some(p.to_primitive(update)?),
+            StreamFilter::Other(_, ref p) if !p.is_empty() => Some(p.to_primitive(update)?),
+            _ => None
+        })).collect::<Result<Vec<_>>>()?;
+        let mut filters = self.info.filters.iter().map(|filter| match filter {
+            StreamFilter::ASCIIHexDecode => "ASCIIHexDecode",
+            StreamFilter::ASCII85Decode => "ASCII85Decode",
+            StreamFilter::LZWDecode(ref _p) => "LZWDecode",
+            StreamFilter::FlateDecode(ref _p) => "FlateDecode",
+            StreamFilter::JPXDecode => "JPXDecode",
+            StreamFilter::DCTDecode(ref _p) => "DCTDecode",
+            StreamFilter::CCITTFaxDecode(ref _p) => "CCITTFaxDecode",
+            StreamFilter::JBIG2Decode(ref _p) => "JBIG2Decode",
+            StreamFilter::Crypt => "Crypt",
+            StreamFilter::RunLengthDecode => "RunLengthDecode",
+            StreamFilter::Other(ref name, _) => name,
+        })
+        .map(|s| Primitive::Name(s.into()));
+        match self.info.filters.len() {
+            0 => {},
+            1 => {
+                info.insert("Filter", filters.next().unwrap());
             }
-            let mut filters = self.info.filters.iter().map(|filter| match filter {
-                StreamFilter::ASCIIHexDecode => "ASCIIHexDecode",
-                StreamFilter::ASCII85Decode => "ASCII85Decode",
-                StreamFilter::LZWDecode(ref _p) => "LZWDecode",
-                StreamFilter::FlateDecode(ref _p) => "FlateDecode",
-                StreamFilter::JPXDecode => "JPXDecode",
-                StreamFilter::DCTDecode(ref _p) => "DCTDecode",
-                StreamFilter::CCITTFaxDecode(ref _p) => "CCITTFaxDecode",
-                StreamFilter::JBIG2Decode(ref _p) => "JBIG2Decode",
-                StreamFilter::Crypt => "Crypt",
-                StreamFilter::RunLengthDecode => "RunLengthDecode",
-            })
-            .map(|s| Primitive::Name(s.into()));
-            match self.info.filters.len() {
-                0 => {},
-                1 => {
-                    info.insert("Filter", filters.next().unwrap().to_primitive(update)?);
-                }
-                _ => {
-                    info.insert("Filter", Primitive::array::<Primitive, _, _, _>(filters, update)?);
-                }
+            _ => {
+                info.insert("Filter", Primitive::Array(filters.collect()));
             }
         }
-        if let Some(para) = params {
-            info.insert("DecodeParms", para);
+        match params.len() {
+            _ if params.iter().all(Option::is_none) => {}
+            1 => {
+                info.insert("DecodeParms", params.into_iter().next().unwrap().unwrap());
+            }
+            // one entry per filter
+            _ => {
+                info.insert("DecodeParms", Primitive::Array(params.into_iter().map(|p| p.unwrap_or(Primitive::Null)).collect()));
+            }
         }
 
         let inner = match self.inner_data {
@@ -246,14 +270,6 @@ impl<I: Default> Default for StreamInfo<I> {
     }
 }
 impl<T> StreamInfo<T> {
-/*
-    /// If the stream is not encoded, this is a no-op. `decode()` should be called whenever it's uncertain
-    /// whether the stream is encoded.
-    pub fn encode(&mut self, _filter: StreamFilter) {
-        // TODO this should add the filter to `self.filters` and encode the data with the given
-        // filter
-        unimplemented!();
-    }*/
     pub fn get_filters(&self) -> &[StreamFilter] {
         &self.filters
     }
@@ -316,7 +332,7 @@ impl<T: Object> Object for StreamInfo<T> {
     }
 }
 
-#[derive(Object, Default, Debug, DataSize)]
+#[derive(Object, ObjectWrite, Default, Debug, DataSize)]
 #[pdf(Type = "ObjStm")]
 pub struct ObjStmInfo {
     #[pdf(key = "N")]
@@ -334,6 +350,8 @@ pub struct ObjStmInfo {
 
 #[derive(DataSize)]
 pub struct ObjectStream {
+    /// Object number of each object.
+    ids:        Vec<ObjNr>,
     /// Byte offset of each object. Index is the object number.
     offsets:    Vec<usize>,
     /// The object number of this object.
@@ -346,19 +364,27 @@ impl Object for ObjectStream {
     fn from_primitive(p: Primitive, resolve: &impl Resolve) -> Result<ObjectStream> {
         let stream: Stream<ObjStmInfo> = Stream::from_primitive(p, resolve)?;
 
+        let mut ids = Vec::new();
         let mut offsets = Vec::new();
         {
             debug!("parsing stream");
             let data = stream.data(resolve)?;
             let mut lexer = Lexer::new(&data);
+            if let Some(max) = resolve.options().limits.max_objects {
+                if stream.info.num_objects > max {
+                    return Err(PdfError::TooManyObjects { count: stream.info.num_objects, max });
+                }
+            }
             for _ in 0..(stream.info.num_objects as ObjNr) {
-                let _obj_nr = lexer.next()?.to::<ObjNr>()?;
+                let obj_nr = lexer.next()?.to::<ObjNr>()?;
                 let offset = lexer.next()?.to::<usize>()?;
+                ids.push(obj_nr);
                 offsets.push(offset);
             }
         }
 
         Ok(ObjectStream {
+            ids,
             offsets,
             _id: 0, // TODO
             inner: stream
@@ -367,6 +393,32 @@ impl Object for ObjectStream {
 }
 
 impl ObjectStream {
+    /// Serialize `objects` into a new Flate compressed object stream.
+    ///
+    /// Streams can't be stored in object streams and the generation number of the
+    /// contained objects is implicitly zero.
+    pub fn pack<'a>(objects: impl IntoIterator<Item=(ObjNr, &'a Primitive)>) -> Result<Stream<ObjStmInfo>> {
+        let mut header = Vec::new();
+        let mut body = Vec::new();
+        let mut num_objects = 0;
+        for (id, primitive) in objects {
+            if let Primitive::Stream(_) = *primitive {
+                bail!("object {} is a stream and can't be put in an object stream", id);
+            }
+            write!(header, "{} {} ", id, body.len())?;
+            primitive.serialize(&mut body)?;
+            body.push(b'\n');
+            num_objects += 1;
+        }
+        header.push(b'\n');
+        let first = header.len();
+        header.extend_from_slice(&body);
+
+        let filter = StreamFilter::FlateDecode(Default::default());
+        let data = encode(&header, &filter)?;
+        let info = ObjStmInfo { num_objects, first, extends: None };
+        Ok(Stream::from_compressed(info, data, vec![filter]))
+    }
     pub fn get_object_slice(&self, index: usize, resolve: &impl Resolve) -> Result<(Arc<[u8]>, Range<usize>)> {
         if index >= self.offsets.len() {
             err!(PdfError::ObjStmOutOfBounds {index, max: self.offsets.len()});
@@ -385,6 +437,10 @@ impl ObjectStream {
     pub fn n_objects(&self) -> usize {
         self.offsets.len()
     }
+    /// The object numbers of the contained objects, by index.
+    pub fn object_ids(&self) -> &[ObjNr] {
+        &self.ids
+    }
     pub fn _data(&self, resolve: &impl Resolve) -> Result<Arc<[u8]>> {
         self.inner.data(resolve)
     }
@@ -379,7 +379,9 @@ pub struct Resources {
     #[pdf(key="Pattern")]
     pub pattern: HashMap<Name, Ref<Pattern>>,
 
-    // shading: Option<Shading>,
+    #[pdf(key="Shading")]
+    pub shadings: HashMap<Name, Primitive>,
+
     #[pdf(key="XObject")]
     pub xobjects: HashMap<Name, Ref<XObject>>,
     // /XObject is a dictionary that map arbitrary names to XObjects
@@ -450,7 +452,7 @@ impl ObjectWrite for Pattern {
             Pattern::Dict(ref d) => d.to_primitive(update),
             Pattern::Stream(ref d, ref ops) => {
                 let data = serialize_ops(ops)?;
-                let stream = Stream::new_with_filters(d.clone(), data, vec![]);
+                let stream = Stream::new(d.clone(), data);
                 stream.to_primitive(update)
             }
         }
@@ -641,7 +643,8 @@ impl ImageXObject {
                     [StreamFilter::CCITTFaxDecode(_)] |
                     [StreamFilter::JPXDecode] |
                     [StreamFilter::FlateDecode(_)] |
-                    [StreamFilter::JBIG2Decode(_)] => Ok((data, Some(&image_filters[0]))),
+                    [StreamFilter::JBIG2Decode(_)] |
+                    [StreamFilter::Other(..)] => Ok((data, Some(&image_filters[0]))),
                     _ => bail!("??? filters={:?}", image_filters)
                 }
             }
@@ -654,27 +657,34 @@ impl ImageXObject {
             Some(f) => f,
             None => return Ok(data)
         };
+        let limit = resolve.options().limits.max_stream_size.unwrap_or(usize::MAX);
         let mut data = match filter {
             StreamFilter::CCITTFaxDecode(ref params) => {
                 if self.inner.info.width != params.columns {
                     bail!("image width mismatch {} != {}", self.inner.info.width, params.columns);
                 }
-                let mut data = fax_decode(&data, params)?;
+                let mut data = resolve.decoders().decode_limited(&data, filter, limit)?;
                 if params.rows == 0 {
                     // adjust size
                     data.truncate(self.inner.info.height as usize * self.inner.info.width as usize);
                 }
                 data
             }
-            StreamFilter::DCTDecode(ref p) => dct_decode(&data, p)?,
-            StreamFilter::JPXDecode => jpx_decode(&data)?,
             StreamFilter::JBIG2Decode(ref p) => {
                 let global_data = p.globals.as_ref().map(|s| s.data(resolve)).transpose()?;
-                jbig2_decode(&data, global_data.as_deref().unwrap_or_default())?
+                let data = resolve.decoders().jbig2_decode(&data, global_data.as_deref().unwrap_or_default())?;
+                if data.len() > limit {
+                    return Err(PdfError::StreamTooLarge { max: limit });
+                }
+                data
             },
-            StreamFilter::FlateDecode(ref p) => flate_decode(&data, p)?,
+            StreamFilter::DCTDecode(_) |
+            StreamFilter::JPXDecode |
+            StreamFilter::FlateDecode(_) |
+            StreamFilter::Other(..) => resolve.decoders().decode_limited(&data, filter, limit)?,
             _ => unreachable!()
         };
+        resolve.count_decoded(data.len())?;
         if let Some(ref decode) = self.decode {
             if decode == &[1.0, 0.0] && self.bits_per_component == Some(1) {
                 data.iter_mut().for_each(|b| *b = !*b);
@@ -0,0 +1,309 @@
+//! Deleting, moving, rotating and inserting pages.
+//!
+//! The operations flatten the page tree: all pages become kids of the root `/Pages` node,
+//! and the attributes they inherited from the nodes in between are copied into them.
+//! Pages whose parent already is the root are left as they are.
+
+use crate::any::AnySync;
+use crate::build::{leaf_pages, refs, resolve_dict, Importer};
+use crate::error::*;
+use crate::file::{Cache, File, Log};
+use crate::object::*;
+use crate::primitive::{Dictionary, Primitive};
+use crate::backend::Backend;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+const INHERITED: &[&str] = &["Resources", "MediaBox", "CropBox", "Rotate"];
+
+fn is_widget(dict: &Dictionary) -> bool {
+    matches!(dict.get("Subtype"), Some(Primitive::Name(n)) if n == "Widget")
+}
+
+impl<B, OC, SC, L> File<B, OC, SC, L>
+where
+    B: Backend,
+    OC: Cache<Result<AnySync, Arc<PdfError>>>,
+    SC: Cache<Result<Arc<[u8]>, Arc<PdfError>>>,
+    L: Log,
+{
+    /// Remove page `n`, counting from 0.
+    ///
+    /// The widget annotations of the page are removed from the form, and so are the
+    /// fields that are left without widgets.
+    pub fn delete_page(&mut self, n: u32) -> Result<()> {
+        let (root, mut pages) = self.flatten_pages()?;
+        let page = page_index(&pages, n)?;
+        let page = pages.remove(page);
+
+        let dict = self.resolver().resolve(page)?.into_dictionary()?;
+        let annots = refs(&self.resolver(), dict.get("Annots"))?;
+        for annot in annots {
+            if is_widget(&self.resolver().resolve(annot)?.into_dictionary()?) {
+                self.remove_field_node(annot)?;
+            }
+        }
+        self.set_pages(root, pages)
+    }
+
+    /// Move page `from` so that it becomes page `to`, counting from 0.
+    pub fn move_page(&mut self, from: u32, to: u32) -> Result<()> {
+        let (root, mut pages) = self.flatten_pages()?;
+        let page = pages.remove(page_index(&pages, from)?);
+        if to as usize > pages.len() {
+            return Err(PdfError::PageOutOfBounds { page_nr: to, max: pages.len() as u32 });
+        }
+        pages.insert(to as usize, page);
+        self.set_pages(root, pages)
+    }
+
+    /// Rotate page `n` clockwise by `degrees`, which has to be a multiple of 90.
+    pub fn rotate_page(&mut self, n: u32, degrees: i32) -> Result<()> {
+        if degrees % 90 != 0 {
+            bail!("pages can only be rotated by multiples of 90 degrees, not {}", degrees);
+        }
+        let (root, pages) = self.flatten_pages()?;
+        let page = pages[page_index(&pages, n)?];
+
+        let mut dict = self.resolver().resolve(page)?.into_dictionary()?;
+        let rotate = match dict.get("Rotate") {
+            Some(p) => p.as_integer()?,
+            None => match self.resolver().resolve(root)?.into_dictionary()?.get("Rotate") {
+                Some(p) => p.as_integer()?,
+                None => 0,
+            }
+        };
+        dict.insert("Rotate", Primitive::Integer((rotate + degrees).rem_euclid(360)));
+        self.update(page, Primitive::Dictionary(dict))?;
+        self.reload_catalog()
+    }
+
+    /// Insert page `n` of the document with the catalog `root`, so that it becomes page `at`.
+    ///
+    /// The widget annotations of the page are imported together with their fields, which
+    /// keep their names, and the fonts of the default resources of the form that this
+    /// document doesn't have yet. Use `build::FormMerger` to combine forms with fields of
+    /// the same name.
+    pub fn insert_page(&mut self, at: u32, resolve: impl Resolve, root: &RcRef<Catalog>, n: u32) -> Result<()> {
+        let (pages_root, mut pages) = self.flatten_pages()?;
+        if at as usize > pages.len() {
+            return Err(PdfError::PageOutOfBounds { page_nr: at, max: pages.len() as u32 });
+        }
+
+        let catalog = resolve.resolve(root.get_ref().get_inner())?.into_dictionary()?;
+        let src_pages = leaf_pages(&resolve, try_opt!(catalog.get("Pages")).clone().into_reference()?)?;
+        let (old_page, mut page) = match src_pages.into_iter().nth(n as usize) {
+            Some(page) => page,
+            None => return Err(PdfError::PageOutOfBounds { page_nr: n, max: root.pages.count }),
+        };
+        let src_form = match catalog.get("AcroForm") {
+            Some(p) => resolve_dict(&resolve, p)?,
+            None => Dictionary::new(),
+        };
+
+        // the fields of the widgets on the page, without widgets on other pages
+        let mut nodes = vec![];
+        for annot in refs(&resolve, page.get("Annots"))? {
+            let mut r = annot;
+            loop {
+                let dict = resolve.resolve(r)?.into_dictionary()?;
+                if r == annot && !is_widget(&dict) || nodes.contains(&r) {
+                    break;
+                }
+                nodes.push(r);
+                match dict.get("Parent") {
+                    Some(&Primitive::Reference(parent)) => r = parent,
+                    _ => break,
+                }
+            }
+        }
+
+        let mut form = self.form()?;
+        let mut dr = match form.get("DR") {
+            Some(p) => resolve_dict(&self.resolver(), p)?,
+            None => Dictionary::new(),
+        };
+        let mut fonts = match dr.get("Font") {
+            Some(p) => resolve_dict(&self.resolver(), p)?,
+            None => Dictionary::new(),
+        };
+
+        let mut importer = Importer::new(resolve, self);
+        let promise = importer.promise::<Primitive>();
+        let new_page = promise.get_inner();
+        importer.redirect(old_page, new_page);
+        let mut new_nodes = Vec::with_capacity(nodes.len());
+        for &old in nodes.iter() {
+            let new = importer.promise::<Primitive>();
+            importer.redirect(old, new.get_inner());
+            new_nodes.push(new);
+        }
+
+        page.insert("Parent", Primitive::Null);
+        let mut page = page.deep_clone(&mut importer)?;
+        page.insert("Parent", Primitive::Reference(pages_root));
+        importer.fulfill(promise, Primitive::Dictionary(page))?;
+
+        let mut top_fields = vec![];
+        for (&old, new) in nodes.iter().zip(new_nodes) {
+            let mut dict = importer.resolve(old)?.into_dictionary()?;
+            if let Some(kids) = dict.get("Kids") {
+                let kids: Vec<_> = refs(&importer, Some(kids))?.into_iter()
+                    .filter(|kid| nodes.contains(kid))
+                    .map(Primitive::Reference)
+                    .collect();
+                dict.insert("Kids", kids);
+            }
+            if !matches!(dict.get("Parent"), Some(Primitive::Reference(_))) {
+                top_fields.push(Primitive::Reference(new.get_inner()));
+            }
+            let dict = dict.deep_clone(&mut importer)?;
+            importer.fulfill(new, Primitive::Dictionary(dict))?;
+        }
+
+        if let Some(p) = src_form.get("DR") {
+            if let Some(src_fonts) = resolve_dict(&importer, p)?.get("Font") {
+                for (name, font) in resolve_dict(&importer, src_fonts)?.iter() {
+                    if fonts.get(name).is_none() {
+                        fonts.insert(name.clone(), font.deep_clone(&mut importer)?);
+                    }
+                }
+            }
+        }
+
+        if !top_fields.is_empty() {
+            let mut fields = match form.get("Fields") {
+                Some(Primitive::Reference(r)) => self.resolver().resolve(*r)?.into_array()?,
+                Some(p) => p.clone().into_array()?,
+                None => vec![],
+            };
+            fields.extend(top_fields);
+            form.insert("Fields", fields);
+            if !fonts.is_empty() {
+                dr.insert("Font", fonts);
+                form.insert("DR", dr);
+            }
+            self.set_form(form)?;
+        }
+
+        pages.insert(at as usize, new_page);
+        self.set_pages(pages_root, pages)
+    }
+
+    /// Make all pages kids of the root of the page tree and return the root and the pages.
+    fn flatten_pages(&mut self) -> Result<(PlainRef, Vec<PlainRef>)> {
+        let resolver = self.resolver();
+        let catalog = resolver.resolve(self.trailer.root.get_ref().get_inner())?.into_dictionary()?;
+        let root = try_opt!(catalog.get("Pages")).clone().into_reference()?;
+
+        let mut pages = vec![];
+        let mut moved = vec![];
+        let mut seen = HashSet::new();
+        // node, attributes inherited from nodes below the root
+        let mut stack = vec![(root, Dictionary::new())];
+        while let Some((r, mut inherited)) = stack.pop() {
+            if !seen.insert(r) {
+                bail!("page tree contains {:?} twice", r);
+            }
+            let mut dict = resolver.resolve(r)?.into_dictionary()?;
+            let is_page = matches!(dict.get("Type"), Some(Primitive::Name(n)) if n == "Page");
+            match dict.get("Kids") {
+                Some(kids) if !is_page => {
+                    if r != root {
+                        for &key in INHERITED {
+                            if let Some(value) = dict.get(key) {
+                                inherited.insert(key, value.clone());
+                            }
+                        }
+                    }
+                    let kids = refs(&resolver, Some(kids))?;
+                    stack.extend(kids.into_iter().rev().map(|kid| (kid, inherited.clone())));
+                }
+                _ => {
+                    if dict.get("Parent") != Some(&Primitive::Reference(root)) {
+                        for (key, value) in inherited.iter() {
+                            if dict.get(key).is_none() {
+                                dict.insert(key.clone(), value.clone());
+                            }
+                        }
+                        dict.insert("Parent", Primitive::Reference(root));
+                        moved.push((r, dict));
+                    }
+                    pages.push(r);
+                }
+            }
+        }
+        drop(resolver);
+
+        for (r, dict) in moved {
+            self.update(r, Primitive::Dictionary(dict))?;
+        }
+        Ok((root, pages))
+    }
+
+    fn set_pages(&mut self, root: PlainRef, pages: Vec<PlainRef>) -> Result<()> {
+        let mut dict = self.resolver().resolve(root)?.into_dictionary()?;
+        dict.insert("Count", Primitive::Integer(pages.len() as i32));
+        dict.insert("Kids", pages.into_iter().map(Primitive::Reference).collect::<Vec<_>>());
+        self.update(root, Primitive::Dictionary(dict))?;
+        self.reload_catalog()
+    }
+
+    /// The interactive form dictionary, empty if there is none.
+    fn form(&self) -> Result<Dictionary> {
+        let resolver = self.resolver();
+        let catalog = resolver.resolve(self.trailer.root.get_ref().get_inner())?.into_dictionary()?;
+        match catalog.get("AcroForm") {
+            Some(p) => resolve_dict(&resolver, p),
+            None => Ok(Dictionary::new()),
+        }
+    }
+
+    fn set_form(&mut self, form: Dictionary) -> Result<()> {
+        let catalog_ref = self.trailer.root.get_ref().get_inner();
+        let mut catalog = self.resolver().resolve(catalog_ref)?.into_dictionary()?;
+        match catalog.get("AcroForm") {
+            Some(&Primitive::Reference(r)) => {
+                self.update(r, Primitive::Dictionary(form))?;
+            }
+            _ => {
+                catalog.insert("AcroForm", form);
+                self.update(catalog_ref, Primitive::Dictionary(catalog))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove a field or widget from its parent, and the parent if it has no kids left.
+    fn remove_field_node(&mut self, r: PlainRef) -> Result<()> {
+        let dict = self.resolver().resolve(r)?.into_dictionary()?;
+        match dict.get("Parent") {
+            Some(&Primitive::Reference(parent)) => {
+                let mut parent_dict = self.resolver().resolve(parent)?.into_dictionary()?;
+                let kids: Vec<_> = refs(&self.resolver(), parent_dict.get("Kids"))?.into_iter().filter(|&kid| kid != r).collect();
+                if kids.is_empty() {
+                    return self.remove_field_node(parent);
+                }
+                parent_dict.insert("Kids", kids.into_iter().map(Primitive::Reference).collect::<Vec<_>>());
+                self.update(parent, Primitive::Dictionary(parent_dict))?;
+            }
+            _ => {
+                let mut form = self.form()?;
+                let fields = refs(&self.resolver(), form.get("Fields"))?;
+                if fields.contains(&r) {
+                    let fields: Vec<_> = fields.into_iter().filter(|&f| f != r).map(Primitive::Reference).collect();
+                    form.insert("Fields", fields);
+                    self.set_form(form)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn page_index(pages: &[PlainRef], n: u32) -> Result<usize> {
+    match (n as usize) < pages.len() {
+        true => Ok(n as usize),
+        false => Err(PdfError::PageOutOfBounds { page_nr: n, max: pages.len() as u32 }),
+    }
+}
@@ -223,6 +223,12 @@ impl<'a> Lexer<'a> {
         self.pos
     }
 
+    /// The position in the file, for a lexer created with `with_offset`.
+    #[inline]
+    pub fn get_file_pos(&self) -> usize {
+        self.file_offset + self.pos
+    }
+
     #[inline]
     pub fn new_substr(&self, mut range: Range<usize>) -> Substr<'a> {
         // if the range is backward, fix it
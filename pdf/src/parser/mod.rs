@@ -80,6 +80,11 @@ fn parse_dictionary_object(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Con
             let key = token.reslice(1..).to_name()?;
             let obj = t!(parse_with_lexer_ctx(lexer, r, ctx, ParseFlags::ANY, max_depth));
             dict.insert(key, obj);
+            if let Some(max) = r.options().limits.max_dict_len {
+                if dict.len() > max {
+                    return Err(PdfError::DictionaryTooLong { max });
+                }
+            }
         } else if token.equals(b">>") {
             break;
         } else {
@@ -117,6 +122,14 @@ fn parse_stream_object(dict: Dictionary, lexer: &mut Lexer, r: &impl Resolve, ct
     })
 }
 
+#[inline]
+fn check_string_len(len: usize, r: &impl Resolve) -> Result<()> {
+    match r.options().limits.max_string_len {
+        Some(max) if len > max => Err(PdfError::StringTooLong { max }),
+        _ => Ok(())
+    }
+}
+
 #[inline]
 fn check(flags: ParseFlags, allowed: ParseFlags) -> Result<(), PdfError> {
     if !flags.intersects(allowed) {
@@ -129,11 +142,13 @@ fn check(flags: ParseFlags, allowed: ParseFlags) -> Result<(), PdfError> {
 /// Use `parse_stream` if this is not sufficient.
 pub fn parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Context>, flags: ParseFlags, max_depth: usize) -> Result<Primitive> {
     let pos = lexer.get_pos();
+    let offset = lexer.get_file_pos();
     match _parse_with_lexer_ctx(lexer, r, ctx, flags, max_depth) {
         Ok(r) => Ok(r),
         Err(e) => {
             lexer.set_pos(pos);
-            Err(e)
+            // the innermost primitive that failed to parse starts here
+            Err(e.at_offset(offset))
         }
     }
 }
@@ -230,6 +245,11 @@ fn _parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Conte
 
             let element = t!(parse_with_lexer_ctx(lexer, r, ctx, ParseFlags::ANY, max_depth-1));
             array.push(element);
+            if let Some(max) = r.options().limits.max_array_len {
+                if array.len() > max {
+                    return Err(PdfError::ArrayTooLong { max });
+                }
+            }
         }
         t!(lexer.next()); // Move beyond closing delimiter
 
@@ -242,6 +262,7 @@ fn _parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Conte
             let mut string_lexer = StringLexer::new(lexer.get_remaining_slice());
             for character in string_lexer.iter() {
                 string.push(t!(character));
+                check_string_len(string.len(), r)?;
             }
             string_lexer.get_offset()
         };
@@ -260,6 +281,7 @@ fn _parse_with_lexer_ctx(lexer: &mut Lexer, r: &impl Resolve, ctx: Option<&Conte
             let mut hex_string_lexer = HexStringLexer::new(lexer.get_remaining_slice());
             for byte in hex_string_lexer.iter() {
                 string.push(t!(byte));
+                check_string_len(string.len(), r)?;
             }
             hex_string_lexer.get_offset()
         };
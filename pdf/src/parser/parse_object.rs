@@ -3,7 +3,7 @@
 
 use crate::parser::{lexer::*, MAX_DEPTH};
 use crate::error::*;
-use crate::primitive::{Primitive, PdfStream};
+use crate::primitive::{Primitive, PdfStream, PdfString, Dictionary};
 use crate::parser::{parse_with_lexer_ctx, parse_stream_with_lexer, Context, ParseFlags};
 use crate::object::*;
 use crate::crypt::Decoder;
@@ -19,11 +19,17 @@ pub fn parse_indirect_object(lexer: &mut Lexer, r: &impl Resolve, decoder: Optio
     };
     lexer.next_expect("obj")?;
 
-    let ctx = Context {
-        decoder,
+    // strings are decrypted after reading the object, since those of cross-reference streams are not encrypted
+    let plain = Context {
+        decoder: None,
         id,
     };
-    let obj = t!(parse_with_lexer_ctx(lexer, r, Some(&ctx), flags, MAX_DEPTH));
+    let obj = t!(parse_with_lexer_ctx(lexer, r, Some(&plain), flags, MAX_DEPTH));
+    let obj = match obj {
+        Primitive::Stream(ref s) if matches!(s.info.get("Type"), Some(Primitive::Name(n)) if n == "XRef") => obj,
+        obj if decoder.is_some() => t!(decrypt_strings(obj, &Context { decoder, id })),
+        obj => obj,
+    };
 
     if r.options().allow_missing_endobj {
         let pos = lexer.get_pos();
@@ -37,6 +43,30 @@ pub fn parse_indirect_object(lexer: &mut Lexer, r: &impl Resolve, decoder: Optio
 
     Ok((id, obj))
 }
+// Decrypt the strings of an object that was read without decryption.
+fn decrypt_strings(p: Primitive, ctx: &Context) -> Result<Primitive> {
+    Ok(match p {
+        Primitive::String(s) => {
+            let mut data = s.as_bytes().to_vec();
+            Primitive::String(PdfString::new(t!(ctx.decrypt(&mut data)).into()))
+        }
+        Primitive::Array(parts) => Primitive::Array(parts.into_iter().map(|p| decrypt_strings(p, ctx)).collect::<Result<_>>()?),
+        Primitive::Dictionary(dict) => Primitive::Dictionary(t!(decrypt_dict(dict, ctx))),
+        Primitive::Stream(mut stream) => {
+            stream.info = t!(decrypt_dict(stream.info, ctx));
+            Primitive::Stream(stream)
+        }
+        p => p
+    })
+}
+fn decrypt_dict(dict: Dictionary, ctx: &Context) -> Result<Dictionary> {
+    let mut new = Dictionary::new();
+    for (key, val) in dict {
+        new.insert(key, t!(decrypt_strings(val, ctx)));
+    }
+    Ok(new)
+}
+
 pub fn parse_indirect_stream(lexer: &mut Lexer, r: &impl Resolve, decoder: Option<&Decoder>) -> Result<(PlainRef, PdfStream)> {
     let id = PlainRef {
         id: t!(lexer.next()).to::<ObjNr>()?,
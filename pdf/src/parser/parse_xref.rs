@@ -12,6 +12,7 @@ use std::convert::TryInto;
 fn parse_xref_section_from_stream(first_id: u32, mut num_entries: usize, width: &[usize], data: &mut &[u8], resolve: &impl Resolve) -> Result<XRefSection> {
     let mut entries = Vec::new();
     let [w0, w1, w2]: [usize; 3] = width.try_into().map_err(|_| other!("invalid xref length array"))?;
+    check_xref_size(num_entries, resolve)?;
     if num_entries * (w0 + w1 + w2) > data.len() {
         if resolve.options().allow_xref_error {
             warn!("not enough xref data. truncating.");
@@ -45,6 +46,14 @@ fn parse_xref_section_from_stream(first_id: u32, mut num_entries: usize, width:
         entries,
     })
 }
+/// Check the number of entries of the xref table (or a section of it) against the limit.
+pub(crate) fn check_xref_size(size: usize, resolve: &impl Resolve) -> Result<()> {
+    match resolve.options().limits.max_xref_entries {
+        Some(max) if size > max => Err(PdfError::XRefTooLarge { size, max }),
+        _ => Ok(())
+    }
+}
+
 /// Helper to read an integer with a certain amount of bytes `width` from stream.
 fn read_u64_from_stream(width: usize, data: &mut &[u8]) -> Result<u64> {
     if width > std::mem::size_of::<u64>() {
@@ -103,6 +112,7 @@ pub fn parse_xref_table_and_trailer(lexer: &mut Lexer, resolve: &impl Resolve) -
     while lexer.peek()? != "trailer" {
         let start_id = t!(lexer.next_as::<u32>());
         let num_ids = t!(lexer.next_as::<u32>());
+        check_xref_size(num_ids as usize, resolve)?;
 
         let mut section = XRefSection::new(start_id);
 
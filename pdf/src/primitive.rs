@@ -272,6 +272,10 @@ impl ObjectWrite for PdfStream {
     }
 }
 impl PdfStream {
+    /// A stream that is not in a file yet, with `data` already encoded according to `info`.
+    pub fn new(info: Dictionary, data: impl Into<Arc<[u8]>>) -> Self {
+        PdfStream { info, inner: StreamInner::Pending { data: data.into() } }
+    }
     pub fn serialize(&self, out: &mut impl io::Write) -> Result<()> {
         self.info.serialize(out)?;
 
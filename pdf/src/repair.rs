@@ -1,42 +1,208 @@
+//! Rebuilding the cross-reference table of a damaged file.
+//!
+//! When the xref table or stream can't be read, the whole file is scanned for `N G obj`
+//! headers instead. If the same object occurs several times, the last one wins, like it
+//! would with incremental updates. Objects in object streams are added once the streams
+//! can be decoded, and the trailer is taken from the file or built around the catalog.
 
-fn build_xref_table() {
-    warn!("can't read xref table: {:?}", e);
-    let start_offset = t!(backend.locate_start_offset());
-    let mut lexer = Lexer::new(t!(backend.read(..)));
-    let mut objects = Vec::new();
+use crate::error::*;
+use crate::object::*;
+use crate::parser::{Lexer, parse_with_lexer, ParseFlags};
+use crate::primitive::{Primitive, Dictionary};
+use crate::xref::{XRef, XRefTable};
+use crate::backend::MAX_ID;
+use std::collections::HashMap;
 
-    (|| -> Result<()> { loop {
-        let offset = lexer.get_pos();
-        let w1 = t!(lexer.next());
-        let w2 = t!(lexer.next());
-        let w3 = t!(lexer.next_expect("obj"));
-        try_opt!(lexer.seek_substr("endobj"));
-
-        objects.push((t!(w1.to::<ObjNr>()), t!(w2.to::<GenNr>()), offset));
-    }})();
-
-    objects.sort_unstable();
-    let mut first_id = objects.first().map(|&(n, _, _)| n).unwrap_or(0);
-    let mut last_id = objects.last().map(|&(n, _, _)| n).unwrap_or(0);
-    let mut xref = XRefTable::new(1 + last_id - first_id);
-    for &(obj_nr, gen_nr, offset) in objects.iter() {
-        for n in first_id + 1 .. obj_nr {
-            xref.push(XRef::Free { next_obj_nr: obj_nr, gen_nr: 0 });
-        }
-        if obj_nr == last_id {
-            warn!("duplicate obj_nr {}", obj_nr);
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\r' | b'\n' | b'\x0c' | b'\0')
+}
+fn is_delimiter(b: u8) -> bool {
+    matches!(b, b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%')
+}
+
+/// Find all `N G obj` headers in `data`, in file order, as `(N, G, position of N)`.
+pub fn scan_objects(data: &[u8]) -> Vec<(ObjNr, GenNr, usize)> {
+    // the number before `end` going backwards, and where it starts
+    fn number_before(data: &[u8], end: usize) -> Option<(u64, usize)> {
+        let start = data[.. end].iter().rposition(|b| !b.is_ascii_digit()).map(|i| i + 1).unwrap_or(0);
+        if start == end || end - start > 10 {
+            return None;
+        }
+        let n = std::str::from_utf8(&data[start .. end]).ok()?.parse().ok()?;
+        Some((n, start))
+    }
+    fn whitespace_before(data: &[u8], end: usize) -> Option<usize> {
+        let start = data[.. end].iter().rposition(|&b| !is_whitespace(b)).map(|i| i + 1).unwrap_or(0);
+        (start < end).then_some(start)
+    }
+
+    let mut objects = Vec::new();
+    for pos in data.windows(3).enumerate().filter(|(_, w)| *w == b"obj").map(|(i, _)| i) {
+        if data.get(pos + 3).is_some_and(|&b| !is_whitespace(b) && !is_delimiter(b)) {
             continue;
         }
-        xref.push(XRef::Raw {
-            pos: offset - start_offset,
-            gen_nr
-        });
-        last_id = obj_nr;
+        let header = (|| {
+            let end = whitespace_before(data, pos)?;
+            let (gen, end) = number_before(data, end)?;
+            let end = whitespace_before(data, end)?;
+            let (id, start) = number_before(data, end)?;
+            match start.checked_sub(1).map(|i| data[i]) {
+                Some(b) if !is_whitespace(b) && !is_delimiter(b) => None,
+                _ => Some((id, gen, start))
+            }
+        })();
+        match header {
+            Some((id, gen, start)) if id > 0 && id <= MAX_ID as u64 && gen <= 0xffff => objects.push((id, gen, start)),
+            _ => {}
+        }
+    }
+    objects
+}
+
+/// Build an xref table from the object headers in `data`, which starts at the `%PDF-` header.
+pub fn build_xref_table(data: &[u8]) -> Result<XRefTable> {
+    let objects = scan_objects(data);
+    let size = match objects.iter().map(|&(id, _, _)| id).max() {
+        Some(id) => id + 1,
+        None => bail!("no objects found")
+    };
+    let mut refs = XRefTable::new(0);
+    for _ in 1 .. size {
+        refs.push(XRef::Free { next_obj_nr: 0, gen_nr: 0 });
     }
+    for (id, gen_nr, pos) in objects {
+        refs.set(id, XRef::Raw { pos, gen_nr });
+    }
+    Ok(refs)
+}
 
-    return t!(Err(e));
+fn objects<'a>(refs: &'a XRefTable, resolve: &'a impl Resolve) -> impl Iterator<Item=(usize, PlainRef, Primitive)> + 'a {
+    (1 .. refs.len() as ObjNr).filter_map(move |id| match refs.get(id) {
+        Ok(XRef::Raw { pos, gen_nr }) => {
+            let r = PlainRef { id, gen: gen_nr };
+            match resolve.resolve(r) {
+                Ok(p) => Some((pos, r, p)),
+                Err(e) => {
+                    debug!("skipping object {:?}: {}", r, e);
+                    None
+                }
+            }
+        }
+        _ => None
+    })
 }
 
-fn build_catalog() {
-    
+fn type_of(p: &Primitive) -> Option<&str> {
+    let dict = match *p {
+        Primitive::Dictionary(ref dict) => dict,
+        Primitive::Stream(ref stream) => &stream.info,
+        _ => return None
+    };
+    match dict.get("Type") {
+        Some(Primitive::Name(name)) => Some(name.as_str()),
+        _ => None
+    }
+}
+
+/// Find the trailer for the rebuilt `refs`.
+///
+/// The last `trailer` dictionary or xref stream of `data` whose `/Root` is a catalog is used.
+/// Without one, a trailer is built for the last catalog object, keeping `/Info`, `/ID` and
+/// `/Encrypt` of the last trailer that was found.
+pub fn build_trailer(data: &[u8], refs: &XRefTable, resolve: &impl Resolve) -> Result<Dictionary> {
+    let mut trailers = Vec::new();
+    let mut catalog = None;
+    for (pos, r, p) in objects(refs, resolve) {
+        match type_of(&p) {
+            Some("Catalog") => catalog = Some(r),
+            Some("XRef") => if let Primitive::Stream(stream) = p {
+                trailers.push((pos, stream.info));
+            },
+            _ => {}
+        }
+    }
+    let mut start = 0;
+    while let Some(i) = data[start ..].windows(7).position(|w| w == b"trailer") {
+        let pos = start + i;
+        let mut lexer = Lexer::with_offset(&data[pos + 7 ..], pos + 7);
+        if let Ok(Primitive::Dictionary(dict)) = parse_with_lexer(&mut lexer, &NoResolve, ParseFlags::DICT) {
+            trailers.push((pos, dict));
+        }
+        start = pos + 7;
+    }
+    trailers.sort_by_key(|&(pos, _)| pos);
+
+    let is_catalog = |p: Option<&Primitive>| match p {
+        Some(&Primitive::Reference(r)) => resolve.resolve(r).is_ok_and(|p| type_of(&p) == Some("Catalog")),
+        _ => false
+    };
+    let exists = |p: Option<&Primitive>| match p {
+        Some(&Primitive::Reference(r)) => resolve.resolve(r).is_ok(),
+        Some(_) => true,
+        None => false
+    };
+
+    let mut trailer = match (trailers.iter().rev().find(|(_, dict)| is_catalog(dict.get("Root"))), catalog) {
+        (Some((_, dict)), _) => dict.clone(),
+        (None, Some(root)) => {
+            warn!("no usable trailer found, using the catalog {:?}", root);
+            let mut trailer = Dictionary::new();
+            trailer.insert("Root", Primitive::Reference(root));
+            if let Some((_, last)) = trailers.last() {
+                for key in ["Info", "ID", "Encrypt"] {
+                    if let Some(p) = last.get(key).filter(|p| exists(Some(p))) {
+                        trailer.insert(key, p.clone());
+                    }
+                }
+            }
+            trailer
+        }
+        // the catalog may be in an object stream that can't be read before decryption
+        (None, None) => match trailers.iter().rev().find(|(_, dict)| matches!(dict.get("Root"), Some(Primitive::Reference(_)))) {
+            Some((_, dict)) => dict.clone(),
+            None => bail!("can't find the document catalog")
+        }
+    };
+    // the entries of an xref stream don't belong in the trailer
+    for key in ["Type", "Length", "Filter", "DecodeParms", "W", "Index", "Prev", "XRefStm"] {
+        trailer.remove(key);
+    }
+    trailer.insert("Size", Primitive::Integer(refs.len() as _));
+    Ok(trailer)
+}
+
+/// The entries of objects in the object streams of `refs`.
+///
+/// Objects in object streams replace entries of objects that occur before the object stream.
+/// The table may have to grow to hold the returned entries.
+pub fn object_stream_entries(refs: &XRefTable, resolve: &impl Resolve) -> Result<Vec<(ObjNr, XRef)>> {
+    let mut streams: Vec<_> = objects(refs, resolve)
+        .filter(|(_, _, p)| matches!(p, Primitive::Stream(_)) && type_of(p) == Some("ObjStm"))
+        .map(|(pos, r, _)| (pos, r))
+        .collect();
+    streams.sort_unstable_by_key(|&(pos, _)| pos);
+
+    let position = |id: ObjNr| match refs.get(id) {
+        Ok(XRef::Raw { pos, .. }) => Some(pos),
+        _ => None
+    };
+    let mut entries = HashMap::new();
+    for (stream_pos, r) in streams {
+        let stream = match resolve.get::<ObjectStream>(Ref::new(r)) {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("can't read object stream {:?}: {}", r, e);
+                continue;
+            }
+        };
+        for (index, &id) in stream.object_ids().iter().enumerate() {
+            if id == 0 || id > MAX_ID as u64 || position(id).is_some_and(|pos| pos > stream_pos) {
+                continue;
+            }
+            entries.insert(id, XRef::Stream { stream_id: r.id, index });
+        }
+    }
+    let mut entries: Vec<_> = entries.into_iter().collect();
+    entries.sort_unstable_by_key(|&(id, _)| id);
+    Ok(entries)
 }
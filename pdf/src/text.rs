@@ -0,0 +1,178 @@
+//! Extracting the text of pages.
+//!
+//! The content stream is run with `interpret` to know the font, size and position of each
+//! glyph. Glyph codes are mapped to unicode through the `/ToUnicode` map of the font, then the
+//! differences of its `/Encoding` and then the base encoding. The glyphs are sorted into lines
+//! from the top of the page to the bottom, and from left to right within a line.
+//!
+//! `Page::text_runs` keeps the positions, as words with their font, size, color and bounds.
+
+use std::rc::Rc;
+
+use crate::content::{Color, Point};
+use crate::error::*;
+use crate::interpret::{interpret_page, Glyph, GraphicsState, TextFont, Visitor};
+use crate::object::*;
+use crate::primitive::Name;
+
+/// A word, or another run of text without white space, drawn with one font, size and color.
+#[derive(Debug, Clone)]
+pub struct TextRun {
+    pub text: String,
+    /// the `/BaseFont` of the font
+    pub font: Option<Name>,
+    /// the font size in user space
+    pub size: f32,
+    pub color: Color,
+    /// the bounds of the glyphs in the user space of the page
+    pub bbox: Rectangle,
+}
+
+/// A glyph with its text, in the user space of the page.
+struct TextChar {
+    text: String,
+    origin: Point,
+    end: Point,
+    size: f32,
+    font: Rc<TextFont>,
+    color: Color,
+    bbox: Rectangle,
+}
+
+impl Page {
+    /// The text of the page, in reading order, with one line of text per line.
+    pub fn extract_text(&self, resolve: &impl Resolve) -> Result<String> {
+        Ok(layout(self.text_chars(resolve)?))
+    }
+
+    /// The words of the page and where they are, in the order they are drawn.
+    ///
+    /// Text in form XObjects is included. Glyphs without a unicode mapping are left out.
+    pub fn text_runs(&self, resolve: &impl Resolve) -> Result<Vec<TextRun>> {
+        let mut runs: Vec<TextRun> = vec![];
+        let mut last: Option<TextChar> = None;
+        for c in self.text_chars(resolve)? {
+            if c.text.trim().is_empty() {
+                last = None;
+                continue;
+            }
+            let continues = last.as_ref().map(|last| {
+                let gap = ((c.origin.x - last.end.x).powi(2) + (c.origin.y - last.end.y).powi(2)).sqrt();
+                Rc::ptr_eq(&c.font, &last.font) && c.size == last.size && c.color == last.color && gap < 0.2 * c.size
+            }).unwrap_or(false);
+            match runs.last_mut() {
+                Some(run) if continues => {
+                    run.text.push_str(&c.text);
+                    run.bbox = Rectangle {
+                        left: run.bbox.left.min(c.bbox.left),
+                        bottom: run.bbox.bottom.min(c.bbox.bottom),
+                        right: run.bbox.right.max(c.bbox.right),
+                        top: run.bbox.top.max(c.bbox.top),
+                    };
+                }
+                _ => runs.push(TextRun {
+                    text: c.text.clone(),
+                    font: c.font.font.name.clone(),
+                    size: c.size,
+                    color: c.color.clone(),
+                    bbox: c.bbox,
+                }),
+            }
+            last = Some(c);
+        }
+        Ok(runs)
+    }
+
+    fn text_chars(&self, resolve: &impl Resolve) -> Result<Vec<TextChar>> {
+        let mut collector = TextCollector { chars: vec![] };
+        interpret_page(self, resolve, &mut collector)?;
+        Ok(collector.chars)
+    }
+}
+
+struct TextCollector {
+    chars: Vec<TextChar>,
+}
+impl Visitor for TextCollector {
+    fn text(&mut self, glyphs: &[Glyph], state: &GraphicsState) -> Result<()> {
+        let font = match state.text.font {
+            Some(ref font) => font,
+            None => return Ok(()),
+        };
+        for glyph in glyphs {
+            let text = match glyph.text {
+                Some(ref text) => text.clone(),
+                None => continue,
+            };
+            let m = glyph.transform;
+            let corners = [(0., font.descent), (glyph.width, font.descent), (0., font.ascent), (glyph.width, font.ascent)]
+                .map(|(x, y)| m.transform(Point { x, y }));
+            self.chars.push(TextChar {
+                text,
+                origin: m.transform(Point::default()),
+                end: m.transform(Point { x: glyph.width, y: 0. }),
+                size: (m.c * m.c + m.d * m.d).sqrt(),
+                font: font.clone(),
+                color: state.fill_color.clone(),
+                bbox: Rectangle {
+                    left: corners.iter().map(|p| p.x).fold(f32::INFINITY, f32::min),
+                    bottom: corners.iter().map(|p| p.y).fold(f32::INFINITY, f32::min),
+                    right: corners.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max),
+                    top: corners.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max),
+                },
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Sort the glyphs into lines and join them, with spaces where there are gaps between them.
+fn layout(chars: Vec<TextChar>) -> String {
+    struct Line {
+        y: f32,
+        size: f32,
+        chars: Vec<TextChar>,
+    }
+    let mut lines: Vec<Line> = vec![];
+    for c in chars {
+        // most of the time the glyph goes on the line of the previous one
+        let line = lines.iter_mut().rev().find(|line| (line.y - c.origin.y).abs() < 0.5 * line.size.max(c.size));
+        match line {
+            Some(line) => {
+                line.size = line.size.max(c.size);
+                line.chars.push(c);
+            }
+            None => lines.push(Line { y: c.origin.y, size: c.size, chars: vec![c] }),
+        }
+    }
+    lines.sort_by(|a, b| b.y.total_cmp(&a.y));
+
+    let mut out = String::new();
+    for mut line in lines {
+        line.chars.sort_by(|a, b| a.origin.x.total_cmp(&b.origin.x));
+        let mut text = String::new();
+        let mut last: Option<&TextChar> = None;
+        for c in line.chars.iter() {
+            if let Some(last) = last {
+                // the same glyph drawn again, for example to make it look bold
+                if c.text == last.text && (c.origin.x - last.origin.x).abs() < 0.1 * c.size {
+                    continue;
+                }
+                let gap = c.origin.x - last.end.x;
+                if gap > 0.2 * c.size.max(last.size) && !text.ends_with(char::is_whitespace) && !c.text.starts_with(char::is_whitespace) {
+                    text.push(' ');
+                }
+            }
+            text.push_str(&c.text);
+            last = Some(c);
+        }
+        let text = text.trim_end();
+        if !text.is_empty() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(text);
+        }
+    }
+    out
+}
@@ -69,6 +69,11 @@ impl XRefTable {
             .map(|(i, _)| i as u32)
     }
 
+    /// All entries with their object numbers, including free and invalid ones.
+    pub fn entries(&self) -> impl Iterator<Item=(ObjNr, XRef)> + '_ {
+        self.entries.iter().enumerate().map(|(i, &xref)| (i as ObjNr, xref))
+    }
+
     pub fn get(&self, id: ObjNr) -> Result<XRef> {
         match self.entries.get(id as usize) {
             Some(&entry) => Ok(entry),
@@ -87,6 +92,9 @@ impl XRefTable {
     pub fn push(&mut self, new_entry: XRef) {
         self.entries.push(new_entry);
     }
+    pub(crate) fn truncate(&mut self, len: usize) {
+        self.entries.truncate(len);
+    }
     pub fn num_entries(&self) -> usize {
         self.entries.len()
     }
@@ -152,6 +160,24 @@ impl XRefTable {
         
         Ok(Stream::new(info, data))
     }
+
+    /// Write the first `size` entries as a classic cross-reference table, starting with the
+    /// `xref` keyword. Objects in object streams can't be referred to by such a table.
+    pub fn write_table(&self, size: usize, out: &mut impl std::io::Write) -> Result<()> {
+        write!(out, "xref\n0 {}\n", size)?;
+        for (id, &x) in self.entries.iter().enumerate().take(size) {
+            let (a, b, kind) = match x {
+                XRef::Free { next_obj_nr, gen_nr } => (next_obj_nr, gen_nr, 'f'),
+                XRef::Raw { pos, gen_nr } => (pos as u64, gen_nr, 'n'),
+                XRef::Stream { .. } => bail!("object {} is in an object stream, which a classic xref table can't refer to", id),
+                // Treat Invalid and Promised entries as free entries
+                XRef::Invalid | XRef::Promised => (0, 0, 'f'),
+            };
+            // each entry is exactly 20 bytes long
+            write!(out, "{:010} {:05} {}\r\n", a, b, kind)?;
+        }
+        Ok(())
+    }
 }
 
 fn byte_len(n: u64) -> usize {
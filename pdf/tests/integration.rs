@@ -93,14 +93,15 @@ fn invalid_pdfs() {
         let path = path.to_str().unwrap();
         println!("\n\n == Now testing `{}` ==\n", path);
 
-        match FileOptions::cached().open(path) {
-            Ok(file) => {
-                for i in 0 .. file.num_pages() {
-                    let _ = file.get_page(i);
-                }
+        if let Ok(file) = FileOptions::cached().open(path) {
+            for i in 0 .. file.num_pages() {
+                let _ = file.get_page(i);
             }
-            Err(_) => {
-                continue;
+        }
+        // the xref table may be rebuilt
+        if let Ok(file) = FileOptions::cached().parse_options(ParseOptions::tolerant()).open(path) {
+            for i in 0 .. file.num_pages() {
+                let _ = file.get_page(i);
             }
         }
     }
@@ -124,3 +125,1236 @@ fn parse_objects_from_stream() {
 }
 
 // TODO test decoding
+
+#[test]
+fn password_errors() {
+    use pdf::error::PdfError;
+    for path in dir_pdfs(file_path("password_protected")) {
+        match FileOptions::uncached().open(&path) {
+            Err(PdfError::PasswordRequired) => {}
+            Err(e) => panic!("{}: expected PasswordRequired, got {}", path.display(), e),
+            Ok(_) => panic!("{}: opened without a password", path.display()),
+        }
+        match FileOptions::uncached().password(b"wrong").open(&path) {
+            Err(PdfError::InvalidPassword) => {}
+            Err(e) => panic!("{}: expected InvalidPassword, got {}", path.display(), e),
+            Ok(_) => panic!("{}: opened with a wrong password", path.display()),
+        }
+    }
+}
+
+#[test]
+fn save_encrypted() {
+    use pdf::object::Updater;
+    use pdf::primitive::{PdfString, Primitive};
+    for path in dir_pdfs(file_path("password_protected")) {
+        let mut file = run!(FileOptions::cached().password(b"userpassword").open(&path));
+        let secret = Primitive::String(PdfString::from("some secret text"));
+        let r = run!(file.create(secret.clone())).get_ref();
+        let data = run!(file.save());
+        assert!(!data.windows(16).any(|w| w == b"some secret text"), "{}: plaintext in output", path.display());
+
+        let file = run!(FileOptions::cached().password(b"userpassword").load(data));
+        let resolved = run!(file.resolver().resolve(r.get_inner()));
+        assert_eq!(resolved, secret, "{}", path.display());
+    }
+}
+
+#[test]
+fn decrypt_and_save() {
+    use pdf::crypt::Encryption;
+    use pdf::error::PdfError;
+
+    for path in dir_pdfs(file_path("password_protected")) {
+        // the user password doesn't allow lifting the restrictions
+        let file = run!(FileOptions::cached().password(b"userpassword").open(&path));
+        assert!(!file.is_owner(), "{}", path.display());
+        assert!(matches!(file.save_decrypted(), Err(PdfError::OwnerPasswordRequired)), "{}", path.display());
+        assert!(matches!(file.save_encrypted(&Encryption::new(b"", b"")), Err(PdfError::OwnerPasswordRequired)), "{}", path.display());
+
+        let file = run!(FileOptions::cached().password(b"ownerpassword").open(&path));
+        assert!(file.is_owner(), "{}", path.display());
+        let data = run!(file.save_decrypted());
+
+        let decrypted = run!(FileOptions::cached().load(data));
+        assert!(decrypted.trailer.encrypt_dict.is_none(), "{}", path.display());
+        assert_eq!(decrypted.num_pages(), file.num_pages());
+        for (a, b) in file.pages().zip(decrypted.pages()) {
+            let (a, b) = (run!(a), run!(b));
+            let a = run!(a.contents.as_ref().unwrap().operations(&file.resolver()));
+            let b = run!(b.contents.as_ref().unwrap().operations(&decrypted.resolver()));
+            assert_eq!(format!("{:?}", a), format!("{:?}", b), "{}", path.display());
+        }
+    }
+}
+
+#[test]
+fn change_password() {
+    use pdf::crypt::{Encryption, Permissions, SecurityRevision};
+    use pdf::error::PdfError;
+
+    let revisions = [SecurityRevision::Rc4_40, SecurityRevision::Rc4_128, SecurityRevision::Aes128, SecurityRevision::Aes256];
+    let file = run!(FileOptions::cached().password(b"ownerpassword").open(file_path("password_protected/passwords_aes_256.pdf")));
+    for revision in revisions {
+        let permissions = Permissions::PRINT | Permissions::FILL_FORMS;
+        let encryption = Encryption::new(b"new user", b"new owner").revision(revision).permissions(permissions);
+        let data = run!(file.save_encrypted(&encryption));
+
+        for password in [&b"new user"[..], b"new owner"] {
+            let reencrypted = run!(FileOptions::cached().password(password).load(data.clone()));
+            assert_eq!(reencrypted.permissions(), permissions, "{:?}", revision);
+            assert_eq!(reencrypted.num_pages(), file.num_pages());
+            for page in reencrypted.pages() {
+                let page = run!(page);
+                run!(page.contents.as_ref().unwrap().operations(&reencrypted.resolver()));
+            }
+        }
+        assert!(matches!(FileOptions::cached().password(b"userpassword").load(data.clone()), Err(PdfError::InvalidPassword)), "{:?}", revision);
+        assert!(matches!(FileOptions::cached().load(data), Err(PdfError::PasswordRequired)), "{:?}", revision);
+    }
+}
+
+#[test]
+fn encrypted_xref_stream() {
+    use pdf::crypt::{Encryption, SecurityRevision};
+    use pdf::primitive::Primitive;
+
+    // RC4 doesn't fail on strings that were never encrypted, it turns them into garbage
+    let file = run!(FileOptions::cached().open(file_path("xelatex.pdf")));
+    let data = run!(file.save_encrypted(&Encryption::new(b"secret", b"").revision(SecurityRevision::Rc4_128)));
+    let encrypted = run!(FileOptions::cached().password(b"secret").load(data));
+    let resolver = encrypted.resolver();
+    let xref_stream = encrypted.xref_table().iter()
+        .filter_map(|id| resolver.resolve(PlainRef { id: id.into(), gen: 0 }).ok())
+        .find_map(|p| match p {
+            Primitive::Stream(s) if s.info.get("Type") == Some(&Primitive::Name("XRef".into())) => Some(s),
+            _ => None
+        })
+        .expect("no xref stream");
+    let id: Vec<_> = encrypted.trailer.id.iter().cloned().map(Primitive::String).collect();
+    assert_eq!(xref_stream.info.get("ID"), Some(&Primitive::Array(id)));
+}
+
+#[test]
+fn encrypt_object_streams() {
+    use pdf::crypt::Encryption;
+    let file = run!(FileOptions::cached().open(file_path("xelatex.pdf")));
+    let data = run!(file.save_encrypted(&Encryption::new(b"secret", b"")));
+
+    // the xref stream of the encrypted file has to be readable as well
+    let encrypted = run!(FileOptions::cached().password(b"secret").load(data));
+    let data = run!(encrypted.save_decrypted());
+    let decrypted = run!(FileOptions::cached().load(data));
+    assert_eq!(decrypted.num_pages(), file.num_pages());
+    for page in decrypted.pages() {
+        let page = run!(page);
+        run!(page.contents.as_ref().unwrap().operations(&decrypted.resolver()));
+    }
+}
+
+#[cfg(feature = "pubsec")]
+#[test]
+fn pubsec_recipient() {
+    use pdf::crypt::Permissions;
+    use pdf::error::PdfError;
+    use pdf::primitive::PdfString;
+
+    let key = std::fs::read(file_path("pubsec/key.pem")).unwrap();
+    let cert = std::fs::read(file_path("pubsec/cert.der")).unwrap();
+    let other_key = std::fs::read(file_path("pubsec/other_key.pem")).unwrap();
+    let other_cert = std::fs::read(file_path("pubsec/other_cert.pem")).unwrap();
+    let stranger_key = std::fs::read(file_path("pubsec/stranger_key.pem")).unwrap();
+    let stranger_cert = std::fs::read(file_path("pubsec/stranger_cert.pem")).unwrap();
+
+    for path in dir_pdfs(file_path("pubsec")) {
+        println!("\n == Now testing `{}` ==", path.display());
+        assert!(matches!(FileOptions::cached().open(&path), Err(PdfError::RecipientRequired)));
+        assert!(matches!(FileOptions::cached().recipient(&stranger_key, &stranger_cert).open(&path), Err(PdfError::NotARecipient)));
+
+        for (key, cert) in [(&key, &cert), (&other_key, &other_cert)] {
+            let file = run!(FileOptions::cached().recipient(key, cert).open(&path));
+            assert_eq!(file.permissions(), Permissions::from_p((1 << 2) | (1 << 8) | -0x1000 | 0xC0));
+            let info = file.trailer.info_dict.as_ref().unwrap();
+            assert_eq!(info.title, Some(PdfString::from("Certificate encrypted")));
+
+            let page = run!(file.get_page(0));
+            let ops = run!(page.contents.as_ref().unwrap().operations(&file.resolver()));
+            assert!(format!("{:?}", ops).contains("Hello recipient"), "{:?}", ops);
+        }
+    }
+}
+
+#[test]
+fn save_object_streams() {
+    use pdf::file::SaveOptions;
+    use pdf::primitive::Primitive;
+
+    // rewrite every object that isn't a stream, like filling a form pulls them out of object streams
+    let open = || {
+        let mut file = run!(FileOptions::cached().open(file_path("xelatex.pdf")));
+        let objects: Vec<_> = (1 .. file.trailer.size as u64)
+            .map(|id| PlainRef { id, gen: 0 })
+            .filter_map(|r| file.resolver().resolve(r).ok().map(|p| (r, p)))
+            .filter(|(_, p)| !matches!(p, Primitive::Stream(_)))
+            .collect();
+        for (r, p) in objects.iter() {
+            run!(file.update(*r, p.clone()));
+        }
+        (file, objects)
+    };
+    let (mut file, objects) = open();
+    let plain = run!(file.save());
+    let (mut file, _) = open();
+    let packed = run!(file.save_with(&SaveOptions::new().object_streams(10)));
+    assert!(packed.len() < plain.len(), "{} >= {}", packed.len(), plain.len());
+
+    let saved = run!(FileOptions::cached().load(packed));
+    for (r, p) in objects.iter() {
+        assert_eq!(format!("{:?}", run!(saved.resolver().resolve(*r))), format!("{:?}", p));
+    }
+    for page in saved.pages() {
+        let page = run!(page);
+        run!(page.contents.as_ref().unwrap().operations(&saved.resolver()));
+    }
+
+    // the object streams of an encrypted file are encrypted as a whole
+    let data = run!(saved.save_encrypted(&pdf::crypt::Encryption::new(b"secret", b"")));
+    let mut encrypted = run!(FileOptions::cached().password(b"secret").load(data));
+    for (r, p) in objects.iter() {
+        run!(encrypted.update(*r, p.clone()));
+    }
+    let data = run!(encrypted.save_with(&SaveOptions::new().object_streams(10)));
+    let encrypted = run!(FileOptions::cached().password(b"secret").load(data));
+    for (r, p) in objects.iter() {
+        assert_eq!(format!("{:?}", run!(encrypted.resolver().resolve(*r))), format!("{:?}", p));
+    }
+    assert_eq!(encrypted.num_pages(), file.num_pages());
+}
+
+#[test]
+fn save_compact() {
+    use pdf::file::SaveOptions;
+    use pdf::primitive::{Primitive, PdfString};
+
+    for path in dir_pdfs(file_path("password_protected")).chain([file_path("xelatex.pdf")]) {
+        let mut file = run!(FileOptions::cached().password(b"userpassword").open(&path));
+        run!(file.create(Primitive::String(PdfString::new(b"unreachable".as_slice().into()))));
+        let page = run!(file.get_page(0)).get_ref().get_inner();
+        let mut saved = Vec::new();
+        for _ in 0 .. 3 {
+            let p = run!(file.resolver().resolve(page));
+            run!(file.update(page, p));
+            saved = run!(file.save());
+        }
+
+        let data = run!(file.save_compact(&SaveOptions::new().object_streams(50)));
+        assert!(data.len() < saved.len(), "{}", path.display());
+        let compact = run!(FileOptions::cached().password(b"userpassword").load(data));
+        assert_eq!(compact.trailer.encrypt_dict.is_some(), file.trailer.encrypt_dict.is_some());
+        assert!(compact.trailer.size < file.trailer.size, "{}", path.display());
+        for id in 1 .. compact.trailer.size as u64 {
+            let p = compact.resolver().resolve(PlainRef { id, gen: 0 });
+            assert!(!matches!(p, Ok(Primitive::String(ref s)) if s.as_bytes() == b"unreachable"));
+        }
+
+        assert_eq!(compact.num_pages(), file.num_pages());
+        for (a, b) in file.pages().zip(compact.pages()) {
+            let (a, b) = (run!(a), run!(b));
+            let a = run!(a.contents.as_ref().unwrap().operations(&file.resolver()));
+            let b = run!(b.contents.as_ref().unwrap().operations(&compact.resolver()));
+            assert_eq!(format!("{:?}", a), format!("{:?}", b), "{}", path.display());
+        }
+    }
+}
+
+#[test]
+fn save_xref_table() {
+    use pdf::file::SaveOptions;
+    let options = SaveOptions::new().xref_table(true);
+
+    let mut file = run!(FileOptions::cached().open(file_path("libreoffice.pdf")));
+    let page = run!(file.get_page(0)).get_ref().get_inner();
+    let p = run!(file.resolver().resolve(page));
+    run!(file.update(page, p));
+    let data = run!(file.save_with(&options));
+    assert!(data.starts_with(b"%PDF-1.4"));
+    let tail = &data[data.len() - 1000 ..];
+    assert!(tail.windows(8).any(|w| w == b"trailer\n"));
+    let saved = run!(FileOptions::cached().load(data));
+    assert_eq!(saved.num_pages(), file.num_pages());
+
+    // objects in object streams have to be written on their own
+    let mut file = run!(FileOptions::cached().open(file_path("xelatex.pdf")));
+    assert!(file.save_with(&options).is_err());
+    assert!(file.save_compact(&options.clone().object_streams(10)).is_err());
+    let data = run!(file.save_compact(&options));
+    assert!(!data.windows(7).any(|w| w == b"/ObjStm"));
+    assert!(!data.windows(5).any(|w| w == b"/XRef"));
+    let saved = run!(FileOptions::cached().load(data));
+    assert_eq!(saved.num_pages(), file.num_pages());
+    for page in saved.pages() {
+        let page = run!(page);
+        run!(page.contents.as_ref().unwrap().operations(&saved.resolver()));
+    }
+
+    // the header is lowered to 1.4 unless the encryption needs more
+    let file = run!(FileOptions::cached().open(file_path("example.pdf")));
+    assert!(run!(file.save_compact(&options)).starts_with(b"%PDF-1.4\n"));
+    assert!(run!(file.save_compact(&SaveOptions::new())).starts_with(b"%PDF-1.7\n"));
+    let file = run!(FileOptions::cached().password(b"userpassword").open(file_path("password_protected/passwords_aes_128.pdf")));
+    assert!(run!(file.save_compact(&options)).starts_with(b"%PDF-1.6\n"));
+
+    // an update with a classic table can't follow an xref stream
+    let data = run!(file.save_compact(&SaveOptions::new()));
+    let mut file = run!(FileOptions::cached().password(b"userpassword").load(data));
+    let page = run!(file.get_page(0)).get_ref().get_inner();
+    let p = run!(file.resolver().resolve(page));
+    run!(file.update(page, p));
+    assert!(file.save_with(&options).is_err());
+    run!(file.save_with(&SaveOptions::new()));
+}
+
+#[test]
+fn repair_xref() {
+    fn assert_same_pages(original: &[u8], damaged: Vec<u8>, name: &str) {
+        use pdf::object::ParseOptions;
+        assert!(FileOptions::cached().load(damaged.clone()).is_err(), "{}", name);
+        let repaired = run!(FileOptions::cached().parse_options(ParseOptions::tolerant()).load(damaged));
+        let file = run!(FileOptions::cached().load(original));
+        assert_eq!(repaired.num_pages(), file.num_pages(), "{}", name);
+        for (a, b) in file.pages().zip(repaired.pages()) {
+            let (a, b) = (run!(a), run!(b));
+            let a = run!(a.contents.as_ref().unwrap().operations(&file.resolver()));
+            let b = run!(b.contents.as_ref().unwrap().operations(&repaired.resolver()));
+            assert_eq!(format!("{:?}", a), format!("{:?}", b), "{}", name);
+        }
+    }
+    fn rfind(data: &[u8], needle: &[u8]) -> usize {
+        data.windows(needle.len()).rposition(|w| w == needle).unwrap()
+    }
+
+    // startxref points nowhere, the trailer or xref stream can still be found
+    for name in ["libreoffice.pdf", "xelatex.pdf", "pdf-sample.pdf", "encrypted_aes_128.pdf"] {
+        let data = std::fs::read(file_path(name)).unwrap();
+        let mut damaged = data[.. rfind(&data, b"startxref")].to_vec();
+        damaged.extend_from_slice(b"startxref\n999999999\n%%EOF");
+        assert_same_pages(&data, damaged, name);
+    }
+
+    // encrypted object streams are read once the trailer is found
+    let file = run!(FileOptions::cached().open(file_path("xelatex.pdf")));
+    let data = run!(file.save_encrypted(&pdf::crypt::Encryption::new(b"", b"owner")));
+    let file = run!(FileOptions::cached().load(data));
+    let data = run!(file.save_compact(&pdf::file::SaveOptions::new().object_streams(10)));
+    let mut damaged = data[.. rfind(&data, b"startxref")].to_vec();
+    damaged.extend_from_slice(b"startxref\n999999999\n%%EOF");
+    assert_same_pages(&data, damaged, "encrypted object streams");
+
+    // without xref table and trailer, the trailer is built around the catalog
+    let data = std::fs::read(file_path("libreoffice.pdf")).unwrap();
+    let damaged = data[.. rfind(&data, b"\nxref")].to_vec();
+    assert_same_pages(&data, damaged, "libreoffice.pdf");
+}
+
+#[test]
+fn lex_at_window() {
+    use std::borrow::Cow;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use pdf::backend::{Backend, IndexRange};
+
+    // remembers the largest read
+    struct Counting(Vec<u8>, Rc<Cell<usize>>);
+    impl Backend for Counting {
+        fn read<T: IndexRange>(&self, range: T) -> pdf::error::Result<Cow<'_, [u8]>> {
+            let range = range.to_range(self.0.len())?;
+            self.1.set(self.1.get().max(range.len()));
+            Ok(Cow::Borrowed(&self.0[range]))
+        }
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    // a malformed page followed by a large stream
+    let mut stream = b"<< /Length 1000000 >>\nstream\n".to_vec();
+    stream.resize(stream.len() + 1_000_000, b' ');
+    stream.extend_from_slice(b"\nendstream");
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R /Rotate ) >>".to_vec(),
+        stream,
+    ];
+    let largest_read = Rc::new(Cell::new(0));
+    let file = run!(FileOptions::cached().load(Counting(build_pdf(&objects), largest_read.clone())));
+    assert!(file.get_page(0).is_err());
+    assert!(largest_read.get() <= 64 * 1024, "read {} bytes", largest_read.get());
+
+    // objects larger than the window are still read
+    let stream = run!(file.resolver().resolve(PlainRef { id: 4, gen: 0 }));
+    assert!(largest_read.get() > 1_000_000);
+    assert!(matches!(stream, pdf::primitive::Primitive::Stream(_)));
+}
+
+#[test]
+fn reader_backend() {
+    use pdf::backend::ReaderBackend;
+    use pdf::file::SaveOptions;
+    use pdf::primitive::Primitive;
+
+    // a tiny cache, so that objects span blocks and blocks get evicted
+    let open = |path: &Path| {
+        let reader = std::fs::File::open(path).unwrap();
+        FileOptions::cached().load(run!(ReaderBackend::with_cache_size(reader, 256, 4)))
+    };
+    for path in dir_pdfs(files()) {
+        let Ok(file) = FileOptions::cached().open(&path) else { continue };
+        let streamed = run!(open(&path));
+        assert_eq!(streamed.num_pages(), file.num_pages());
+        for (a, b) in file.pages().zip(streamed.pages()) {
+            let (a, b) = (run!(a), run!(b));
+            if let (Some(a), Some(b)) = (a.contents.as_ref(), b.contents.as_ref()) {
+                assert_eq!(
+                    format!("{:?}", a.operations(&file.resolver()).ok()),
+                    format!("{:?}", b.operations(&streamed.resolver()).ok()),
+                    "{:?}", path
+                );
+            }
+        }
+    }
+
+    // the update written after the copied source is the same as the one appended in memory
+    let mut file = run!(FileOptions::cached().open(file_path("xelatex.pdf")));
+    let changes: Vec<_> = (1 .. 20).map(|id| PlainRef { id, gen: 0 })
+        .filter_map(|r| file.resolver().resolve(r).ok().map(|p| (r, p)))
+        .filter(|(_, p)| !matches!(p, Primitive::Stream(_)))
+        .collect();
+    for (r, p) in changes.iter() {
+        run!(file.update(*r, p.clone()));
+    }
+    let saved = run!(file.save());
+
+    let mut streamed = run!(open(&file_path("xelatex.pdf")));
+    for (r, p) in changes.iter() {
+        run!(streamed.update(*r, p.clone()));
+    }
+    let mut written = Vec::new();
+    run!(streamed.save_to_writer(&SaveOptions::default(), &mut written));
+    assert_eq!(written, saved);
+    let reloaded = run!(FileOptions::cached().load(written));
+    assert_eq!(reloaded.num_pages(), file.num_pages());
+}
+
+#[test]
+fn page_operations() {
+    // some identity of each page
+    fn contents(file: &pdf::file::File<Vec<u8>, impl pdf::file::Cache<Result<pdf::any::AnySync, std::sync::Arc<pdf::PdfError>>>, impl pdf::file::Cache<Result<std::sync::Arc<[u8]>, std::sync::Arc<pdf::PdfError>>>, impl pdf::file::Log>) -> Vec<String> {
+        file.pages().map(|page| {
+            let page = run!(page);
+            format!("{:?}", page.contents.as_ref().map(|c| c.operations(&file.resolver()).unwrap().len()))
+        }).collect()
+    }
+
+    let mut file = run!(FileOptions::cached().open(file_path("ep.pdf")));
+    let mut expected = contents(&file);
+    let n = file.num_pages();
+
+    run!(file.delete_page(0));
+    expected.remove(0);
+    run!(file.move_page(0, n - 2));
+    let first = expected.remove(0);
+    expected.push(first);
+    run!(file.rotate_page(1, -90));
+    run!(file.rotate_page(1, 180));
+    assert!(file.rotate_page(1, 45).is_err());
+    assert!(file.delete_page(n).is_err());
+
+    let other = run!(FileOptions::cached().open(file_path("libreoffice.pdf")));
+    run!(file.insert_page(2, other.resolver(), &other.trailer.root, 1));
+    expected.insert(2, contents(&other)[1].clone());
+    assert_eq!(contents(&file), expected);
+
+    let file = run!(FileOptions::cached().load(run!(file.save())));
+    assert_eq!(file.num_pages(), n);
+    assert_eq!(contents(&file), expected);
+    assert_eq!(run!(file.get_page(1)).rotate, 90);
+    for page in file.pages() {
+        let page = run!(page);
+        run!(page.media_box());
+        run!(page.resources());
+    }
+}
+
+#[test]
+fn page_operations_with_widgets() {
+    use pdf::primitive::{Dictionary, Primitive};
+
+    // widget, its /P and the page it is on
+    type PageWidget = (PlainRef, Option<PlainRef>, PlainRef);
+
+    // the widgets in the field tree, and those on the pages
+    fn widgets(data: Vec<u8>) -> (Vec<PlainRef>, Vec<PageWidget>) {
+        let file = run!(FileOptions::cached().load(data));
+        let resolver = file.resolver();
+        let dict = |r: PlainRef| -> Dictionary { run!(run!(resolver.resolve(r)).into_dictionary()) };
+        let refs = |p: Option<&Primitive>| -> Vec<PlainRef> {
+            p.map(|p| run!(p.clone().resolve(&resolver)).into_array().unwrap()).unwrap_or_default()
+                .into_iter().filter_map(|p| p.into_reference().ok()).collect()
+        };
+        let is_widget = |d: &Dictionary| d.get("Subtype").map(|s| s.as_name().unwrap() == "Widget").unwrap_or(false);
+
+        let catalog = dict(file.trailer.root.get_ref().get_inner());
+        let form = run!(catalog.get("AcroForm").unwrap().clone().resolve(&resolver)).into_dictionary().unwrap();
+        let mut tree = vec![];
+        let mut queue = refs(form.get("Fields"));
+        while let Some(r) = queue.pop() {
+            let d = dict(r);
+            if is_widget(&d) {
+                tree.push(r);
+            }
+            queue.extend(refs(d.get("Kids")));
+        }
+        let mut on_pages = vec![];
+        for page in file.pages() {
+            let page = run!(page).get_ref().get_inner();
+            for annot in refs(dict(page).get("Annots")) {
+                let d = dict(annot);
+                if is_widget(&d) {
+                    on_pages.push((annot, d.get("P").map(|p| p.clone().into_reference().unwrap()), page));
+                }
+            }
+        }
+        (tree, on_pages)
+    }
+    fn check(data: Vec<u8>) -> usize {
+        let (tree, on_pages) = widgets(data);
+        // af8.pdf also has widgets that are not in the form
+        for widget in tree.iter() {
+            assert!(on_pages.iter().any(|(w, _, _)| w == widget));
+        }
+        for (_, p, page) in on_pages {
+            assert!(p.is_none() || p == Some(page));
+        }
+        tree.len()
+    }
+
+    // two pages below an intermediate node, a field with a widget on each page,
+    // and a field with a widget on the first page only
+    let data = {
+        use pdf::file::Trailer;
+        let mut storage = FileOptions::uncached().storage();
+        let name = |s: &str| Primitive::name(s);
+        let dict = |entries: Vec<(&str, Primitive)>| {
+            let mut d = Dictionary::new();
+            for (k, v) in entries {
+                d.insert(k, v);
+            }
+            Primitive::Dictionary(d)
+        };
+        let [root, node, page0, page1, shared, single, w0, w1, w2] = [(); 9].map(|_| storage.promise::<Primitive>().get_inner());
+        let r = |r: &PlainRef| Primitive::Reference(*r);
+        let rect = Primitive::Array(vec![0.into(), 0.into(), 10.into(), 10.into()]);
+        let widget = |page: &PlainRef, parent: &PlainRef| dict(vec![
+            ("Type", name("Annot")), ("Subtype", name("Widget")), ("Rect", rect.clone()), ("P", r(page)), ("Parent", r(parent)),
+        ]);
+        let objects = vec![
+            (w0, widget(&page0, &shared)),
+            (w1, widget(&page1, &shared)),
+            (w2, widget(&page0, &single)),
+            (shared, dict(vec![("FT", name("Tx")), ("T", Primitive::String("shared".into())), ("Kids", vec![r(&w0), r(&w1)].into())])),
+            (single, dict(vec![("FT", name("Tx")), ("T", Primitive::String("single".into())), ("Kids", vec![r(&w2)].into())])),
+            (page0, dict(vec![("Type", name("Page")), ("Parent", r(&node)), ("Annots", vec![r(&w0), r(&w2)].into())])),
+            (page1, dict(vec![("Type", name("Page")), ("Parent", r(&node)), ("Annots", vec![r(&w1)].into())])),
+            (node, dict(vec![("Type", name("Pages")), ("Parent", r(&root)), ("Kids", vec![r(&page0), r(&page1)].into()), ("Count", 2.into()), ("MediaBox", rect.clone())])),
+            (root, dict(vec![("Type", name("Pages")), ("Kids", vec![r(&node)].into()), ("Count", 2.into())])),
+        ];
+        let fields = vec![r(&shared), r(&single)];
+        let root_ref = r(&root);
+        for (r, object) in objects {
+            run!(storage.update(r, object));
+        }
+        let form = run!(storage.create(dict(vec![("Fields", fields.into())])));
+        let catalog = run!(storage.create(dict(vec![
+            ("Type", name("Catalog")), ("Pages", root_ref), ("AcroForm", Primitive::Reference(form.get_ref().get_inner())),
+        ])));
+        let mut trailer = Trailer {
+            size: 0,
+            prev_trailer_pos: None,
+            root: run!(storage.resolver().get(Ref::new(catalog.get_ref().get_inner()))),
+            encrypt_dict: None,
+            info_dict: None,
+            id: vec![],
+        };
+        run!(storage.save(&mut trailer)).to_vec()
+    };
+    assert_eq!(check(data.clone()), 3);
+
+    let mut file = run!(FileOptions::cached().load(data.clone()));
+    run!(file.delete_page(0));
+    let saved = run!(file.save());
+    assert_eq!(check(saved.clone()), 1);
+    let saved_after_delete = saved.clone();
+    let file = run!(FileOptions::cached().load(saved));
+    let form = file.get_root().forms.as_ref().unwrap();
+    assert_eq!(form.fields.len(), 1);
+    assert_eq!(form.fields[0].kids.len(), 1);
+    assert!(run!(file.get_page(0)).media_box().is_ok());
+
+    let original = run!(FileOptions::cached().load(data));
+    let mut file = run!(FileOptions::cached().load(saved_after_delete));
+    run!(file.insert_page(0, original.resolver(), &original.trailer.root, 0));
+    assert_eq!(check(run!(file.save())), 3);
+}
+
+#[test]
+fn split_pages() {
+    use pdf::build::PageSplitter;
+    use pdf::file::Trailer;
+    use pdf::primitive::{Dictionary, Primitive};
+
+    let name = |s: &str| Primitive::name(s);
+    let dict = |entries: Vec<(&str, Primitive)>| {
+        let mut d = Dictionary::new();
+        for (k, v) in entries {
+            d.insert(k, v);
+        }
+        d
+    };
+    let r = |r: &PlainRef| Primitive::Reference(*r);
+
+    // three pages, a field with widgets on the first and last page, an outline
+    // with an item for each page below a common parent, a named destination per
+    // page and roman page numbers followed by arabic ones from the second page on
+    let data = {
+        let mut storage = FileOptions::uncached().storage();
+        let [root, p0, p1, p2, field, w0, w2, outlines, top, o0, o1, o2] = [(); 12].map(|_| storage.promise::<Primitive>().get_inner());
+        let rect = Primitive::Array(vec![0.into(), 0.into(), 10.into(), 10.into()]);
+        let page = |annots: Vec<Primitive>| dict(vec![("Type", name("Page")), ("Parent", r(&root)), ("Annots", annots.into())]);
+        let widget = |page: &PlainRef| dict(vec![
+            ("Type", name("Annot")), ("Subtype", name("Widget")), ("Rect", rect.clone()), ("P", r(page)), ("Parent", r(&field)),
+        ]);
+        let dest = |page: &PlainRef| Primitive::Array(vec![r(page), name("Fit")]);
+        let item = |title: &str, page: &PlainRef, prev: Option<&PlainRef>, next: Option<&PlainRef>| {
+            let mut d = dict(vec![("Title", Primitive::String(title.into())), ("Parent", r(&top)), ("Dest", dest(page))]);
+            if let Some(prev) = prev {
+                d.insert("Prev", r(prev));
+            }
+            if let Some(next) = next {
+                d.insert("Next", r(next));
+            }
+            d
+        };
+        let objects = vec![
+            (root, dict(vec![("Type", name("Pages")), ("Kids", vec![r(&p0), r(&p1), r(&p2)].into()), ("Count", 3.into()), ("MediaBox", rect.clone())])),
+            (p0, page(vec![r(&w0)])),
+            (p1, page(vec![])),
+            (p2, page(vec![r(&w2)])),
+            (w0, widget(&p0)),
+            (w2, widget(&p2)),
+            (field, dict(vec![("FT", name("Tx")), ("T", Primitive::String("name".into())), ("Kids", vec![r(&w0), r(&w2)].into())])),
+            (outlines, dict(vec![("Type", name("Outlines")), ("First", r(&top)), ("Last", r(&top)), ("Count", 4.into())])),
+            (top, dict(vec![
+                ("Title", Primitive::String("Chapter".into())), ("Parent", r(&outlines)),
+                ("First", r(&o0)), ("Last", r(&o2)), ("Count", 3.into()),
+            ])),
+            (o0, item("one", &p0, None, Some(&o1))),
+            (o1, item("two", &p1, Some(&o0), Some(&o2))),
+            (o2, item("three", &p2, Some(&o1), None)),
+        ];
+        for (r, object) in objects {
+            run!(storage.update(r, Primitive::Dictionary(object)));
+        }
+        let form = run!(storage.create(Primitive::Dictionary(dict(vec![("Fields", vec![r(&field)].into())]))));
+        let names = dict(vec![("Dests", Primitive::Dictionary(dict(vec![("Names", vec![
+            Primitive::String("one".into()), dest(&p0),
+            Primitive::String("three".into()), dest(&p2),
+            Primitive::String("two".into()), dest(&p1),
+        ].into())])))]);
+        let labels = dict(vec![("Nums", vec![
+            0.into(), Primitive::Dictionary(dict(vec![("S", name("r"))])),
+            1.into(), Primitive::Dictionary(dict(vec![("S", name("D")), ("St", 5.into())])),
+        ].into())]);
+        let catalog = run!(storage.create(Primitive::Dictionary(dict(vec![
+            ("Type", name("Catalog")), ("Pages", r(&root)), ("AcroForm", Primitive::Reference(form.get_ref().get_inner())),
+            ("Outlines", r(&outlines)), ("Names", names.into()), ("PageLabels", labels.into()),
+        ]))));
+        let mut trailer = Trailer {
+            size: 0,
+            prev_trailer_pos: None,
+            root: run!(storage.resolver().get(Ref::new(catalog.get_ref().get_inner()))),
+            encrypt_dict: None,
+            info_dict: None,
+            id: vec![],
+        };
+        run!(storage.save(&mut trailer)).to_vec()
+    };
+
+    let file = run!(FileOptions::cached().load(data));
+    let splitter = run!(PageSplitter::new(file.resolver(), &file.trailer.root));
+    assert_eq!(splitter.num_pages(), 3);
+    assert!(splitter.extract(2..4).is_err());
+    assert!(splitter.extract(1..1).is_err());
+    let parts = run!(splitter.split([0..1, 1..3]));
+
+    let first = run!(FileOptions::cached().load(parts[0].clone()));
+    let second = run!(FileOptions::cached().load(parts[1].clone()));
+    assert_eq!(first.num_pages(), 1);
+    assert_eq!(second.num_pages(), 2);
+    for page in first.pages().chain(second.pages()) {
+        assert!(run!(run!(page).media_box()).right == 10.);
+    }
+
+    // the field keeps the widgets on the pages that were copied
+    let form = second.get_root().forms.as_ref().unwrap();
+    assert_eq!(form.fields.len(), 1);
+    assert_eq!(form.fields[0].kids.len(), 1);
+
+    let resolver = second.resolver();
+    let get = |p: &Primitive| run!(run!(p.clone().resolve(&resolver)).into_dictionary());
+    let catalog = get(&Primitive::Reference(second.trailer.root.get_ref().get_inner()));
+
+    let outlines = get(catalog.get("Outlines").unwrap());
+    assert_eq!(outlines.get("Count"), Some(&Primitive::Integer(3)));
+    let top = get(outlines.get("First").unwrap());
+    assert_eq!(top.get("Count"), Some(&Primitive::Integer(2)));
+    let two = get(top.get("First").unwrap());
+    assert_eq!(two.get("Title"), Some(&Primitive::String("two".into())));
+    assert!(two.get("Prev").is_none());
+    let three = get(two.get("Next").unwrap());
+    assert_eq!(top.get("Last"), two.get("Next"));
+    assert_eq!(three.get("Prev"), top.get("First"));
+
+    let names = get(catalog.get("Names").unwrap());
+    let dests = run!(get(names.get("Dests").unwrap()).get("Names").unwrap().clone().into_array());
+    assert_eq!(dests.len(), 4);
+    assert_eq!(dests[0], Primitive::String("three".into()));
+
+    // the arabic numbering of the second page carries on with 5
+    let labels = get(catalog.get("PageLabels").unwrap());
+    let nums = run!(labels.get("Nums").unwrap().clone().into_array());
+    assert_eq!(nums.len(), 2);
+    assert_eq!(nums[0], Primitive::Integer(0));
+    assert_eq!(get(&nums[1]).get("St"), Some(&Primitive::Integer(5)));
+
+    let resolver = first.resolver();
+    let get = |p: &Primitive| run!(run!(p.clone().resolve(&resolver)).into_dictionary());
+    let catalog = get(&Primitive::Reference(first.trailer.root.get_ref().get_inner()));
+    let nums = run!(get(catalog.get("PageLabels").unwrap()).get("Nums").unwrap().clone().into_array());
+    assert_eq!(get(&nums[1]).get("St"), Some(&Primitive::Integer(1)));
+    assert!(catalog.get("Outlines").is_some());
+    assert!(first.get_root().forms.as_ref().unwrap().fields[0].kids.len() == 1);
+}
+
+#[test]
+fn extract_text() {
+    let text = |name: &str, n: u32| {
+        let file = run!(FileOptions::cached().open(file_path(name)));
+        let page = run!(file.get_page(n));
+        let text = run!(page.extract_text(&file.resolver()));
+        text
+    };
+    assert_eq!(text("example.pdf", 0), "Hello World!");
+    assert_eq!(text("libreoffice.pdf", 1), "Contents of page 2");
+
+    let sample = text("pdf-sample.pdf", 0);
+    let lines: Vec<_> = sample.lines().collect();
+    assert_eq!(lines[0], "Adobe Acrobat PDF Files");
+    // WinAnsi bullets and the registered sign
+    assert!(lines[1].starts_with("Adobe® Portable Document Format (PDF) is a universal file format"));
+    assert!(lines.contains(&"• PDF files always print correctly on any printing device."));
+
+    // ligatures from the ToUnicode map of a TeX font
+    assert!(text("xelatex.pdf", 1).contains("And the eﬃciencies are (given by speedup/num processes):"));
+}
+
+#[test]
+fn extract_text_past_broken_objects() {
+    use pdf::content::{Content, Op};
+    use pdf::primitive::{Dictionary, PdfStream, Primitive};
+
+    // an XObject that isn't one, a form whose content can't be decoded and a missing shading
+    let mut file = run!(FileOptions::cached().open(file_path("example.pdf")));
+    let page_ref = run!(file.get_page(0)).get_ref().get_inner();
+    let not_xobject = run!(file.create(Primitive::Integer(1))).get_ref().get_inner();
+    let mut info = Dictionary::new();
+    info.insert("Subtype", Primitive::name("Form"));
+    info.insert("BBox", vec![Primitive::Integer(0), Primitive::Integer(0), Primitive::Integer(1), Primitive::Integer(1)]);
+    info.insert("Filter", Primitive::name("FlateDecode"));
+    let bad_form = run!(file.create(Primitive::Stream(PdfStream::new(info, &b"not deflated"[..])))).get_ref().get_inner();
+
+    let mut page = run!(run!(file.resolver().resolve(page_ref)).into_dictionary());
+    let mut resources = match page.get("Resources") {
+        Some(p) => run!(run!(p.clone().resolve(&file.resolver())).into_dictionary()),
+        None => Dictionary::new(),
+    };
+    let mut xobjects = Dictionary::new();
+    xobjects.insert("NotXObject", Primitive::Reference(not_xobject));
+    xobjects.insert("BadForm", Primitive::Reference(bad_form));
+    resources.insert("XObject", xobjects);
+    let mut shadings = Dictionary::new();
+    shadings.insert("Missing", Primitive::Reference(PlainRef { id: 9999, gen: 0 }));
+    resources.insert("Shading", shadings);
+    page.insert("Resources", resources);
+
+    let old = run!(file.get_page(0));
+    let mut ops = vec![
+        Op::XObject { name: "NotXObject".into() },
+        Op::XObject { name: "BadForm".into() },
+        Op::Shade { name: "Missing".into() },
+    ];
+    ops.extend(run!(old.contents.as_ref().unwrap().operations(&file.resolver())));
+    page.insert("Contents", run!(Content::from_ops(ops).to_primitive(&mut file)));
+    run!(file.update(page_ref, Primitive::Dictionary(page)));
+
+    let file = run!(FileOptions::cached().load(run!(file.save())));
+    let page = run!(file.get_page(0));
+    assert_eq!(run!(page.extract_text(&file.resolver())), "Hello World!");
+}
+
+#[test]
+fn text_runs() {
+    use pdf::content::{Color, Rgb};
+
+    // the text is drawn by a form XObject
+    let file = run!(FileOptions::cached().open(file_path("formxobject.pdf")));
+    let page = run!(file.get_page(0));
+    let runs = run!(page.text_runs(&file.resolver()));
+    let words: Vec<_> = runs.iter().map(|run| run.text.as_str()).collect();
+    assert_eq!(words, ["Hello", "World!"]);
+    for run in runs.iter() {
+        assert_eq!(run.font.as_deref(), Some("Times-Roman"));
+        assert_eq!(run.size, 24.);
+        assert_eq!(run.color, Color::Rgb(Rgb { red: 0., green: 0., blue: 0. }));
+        assert!(run.bbox.top - run.bbox.bottom >= 20.);
+    }
+    assert!(runs[0].bbox.right < runs[1].bbox.left);
+    assert_eq!(runs[0].bbox.bottom, runs[1].bbox.bottom);
+}
+
+#[test]
+fn image_pixels() {
+    use pdf::image::PixelFormat;
+
+    for name in ["jpeg.pdf", "lossless.pdf"] {
+        let file = run!(FileOptions::cached().open(file_path(name)));
+        let images: Vec<_> = run!(file.images().collect::<Result<Vec<_>, _>>());
+        assert_eq!(images.len(), 1);
+        let (page, ref image) = images[0];
+        assert_eq!(page, 0);
+        assert!(!image.inline);
+        assert_eq!(image.transform.a, image.transform.d);
+        let pixels = run!(image.image.pixels(&file.resolver()));
+        assert_eq!((pixels.width, pixels.height, pixels.format), (512, 512, PixelFormat::Rgb));
+        assert_eq!(pixels.data.len(), 512 * 512 * 3);
+    }
+
+    // the image has a soft mask
+    let file = run!(FileOptions::cached().open(file_path("ep.pdf")));
+    let page = run!(file.get_page(0));
+    let images = run!(page.images(&file.resolver()));
+    let pixels = run!(images[0].image.pixels(&file.resolver()));
+    assert_eq!(pixels.format, PixelFormat::Rgba);
+    assert_eq!(pixels.data.len(), pixels.width as usize * pixels.height as usize * 4);
+    assert!(pixels.data.chunks(4).any(|p| p[3] == 0) && pixels.data.chunks(4).any(|p| p[3] == 255));
+}
+
+#[test]
+fn add_images() {
+    use pdf::enc::{encode, LZWFlateParams, StreamFilter};
+    use pdf::image::PixelFormat;
+    use pdf::primitive::Primitive;
+
+    // a PNG file, with the CRCs left at 0
+    fn png(width: u32, depth: u8, color_type: u8, rows: &[&[u8]], chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+        fn chunk(out: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            out.extend_from_slice(kind);
+            out.extend_from_slice(data);
+            out.extend_from_slice(&[0; 4]);
+        }
+        let mut header = vec![];
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&(rows.len() as u32).to_be_bytes());
+        header.extend_from_slice(&[depth, color_type, 0, 0, 0]);
+        // each row without a predictor
+        let data: Vec<u8> = rows.iter().flat_map(|row| [&[0][..], row].concat()).collect();
+        let data = run!(encode(&data, &StreamFilter::FlateDecode(LZWFlateParams::default())));
+
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut out, b"IHDR", &header);
+        for &(kind, data) in chunks {
+            chunk(&mut out, kind, data);
+        }
+        chunk(&mut out, b"IDAT", &data);
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    let jpeg_file = run!(FileOptions::cached().open(file_path("jpeg.pdf")));
+    let (_, jpeg_image) = run!(jpeg_file.images().next().unwrap());
+    let jpeg = run!(jpeg_image.image.raw_image_data(&jpeg_file.resolver())).0;
+    let jpeg_pixels = run!(jpeg_image.image.pixels(&jpeg_file.resolver()));
+
+    let rgba: &[&[u8]] = &[&[255, 0, 0, 255, 0, 255, 0, 128], &[0, 0, 255, 0, 9, 9, 9, 255]];
+    let rgba_png = png(2, 8, 6, rgba, &[]);
+    // 2-bit indices into a palette with a transparent last color
+    let palette_png = png(3, 2, 3, &[&[0b00_01_10_00], &[0b10_01_00_00]], &[
+        (b"PLTE", &[255, 255, 255, 0, 0, 0, 255, 0, 0]),
+        (b"tRNS", &[255, 255, 0]),
+    ]);
+
+    let mut file = run!(FileOptions::cached().open(file_path("example.pdf")));
+    let image = run!(ImageXObject::from_jpeg(&jpeg));
+    assert_eq!((image.width, image.height), (512, 512));
+    run!(file.add_image(0, image, Rectangle { left: 10., bottom: 20., right: 110., top: 120. }));
+    let image = run!(ImageXObject::from_png(&rgba_png, &mut file));
+    run!(file.add_image(0, image, Rectangle { left: 0., bottom: 0., right: 20., top: 20. }));
+    let image = run!(ImageXObject::from_png(&palette_png, &mut file));
+    run!(file.add_image(0, image, Rectangle { left: 0., bottom: 0., right: 30., top: 20. }));
+    assert!(ImageXObject::from_png(&jpeg, &mut file).is_err());
+    // a few bytes of data for a huge image
+    for size in [[0, 0, 0, 3, 0x7f, 0xff, 0xff, 0xff], [0x7f, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff]] {
+        let mut huge_png = palette_png.clone();
+        huge_png[16 .. 24].copy_from_slice(&size);
+        assert!(ImageXObject::from_png(&huge_png, &mut file).is_err());
+    }
+    let mut short_png = rgba_png.clone();
+    short_png[20 .. 24].copy_from_slice(&3u32.to_be_bytes());
+    assert!(ImageXObject::from_png(&short_png, &mut file).is_err());
+
+    let file = run!(FileOptions::cached().load(run!(file.save())));
+    let page = run!(file.get_page(0));
+    run!(page.extract_text(&file.resolver()));
+    let images = run!(page.images(&file.resolver()));
+    assert_eq!(images.len(), 3);
+    assert_eq!((images[0].transform.a, images[0].transform.e, images[0].transform.f), (100., 10., 20.));
+
+    let pixels = run!(images[0].image.pixels(&file.resolver()));
+    assert_eq!(pixels.data, jpeg_pixels.data);
+    let pixels = run!(images[1].image.pixels(&file.resolver()));
+    assert_eq!(pixels.format, PixelFormat::Rgba);
+    assert_eq!(pixels.data, rgba.concat());
+    let pixels = run!(images[2].image.pixels(&file.resolver()));
+    assert_eq!(pixels.format, PixelFormat::Rgba);
+    assert_eq!(pixels.data, [
+        255, 255, 255, 255,  0, 0, 0, 255,  255, 0, 0, 0,
+        255, 0, 0, 0,  0, 0, 0, 255,  255, 255, 255, 255,
+    ]);
+
+    // the icon of a button
+    let mut file = run!(FileOptions::cached().open(Path::new(env!("CARGO_MANIFEST_DIR")).join("../acroform_files/af8.pdf")));
+    let page = run!(file.get_page(0));
+    let widget = page.annotations.load(&file.resolver()).unwrap().data().iter()
+        .find(|annot| annot.subtype.as_str() == "Widget")
+        .and_then(|annot| annot.as_ref())
+        .unwrap()
+        .get_inner();
+    let image = run!(ImageXObject::from_png(&rgba_png, &mut file));
+    let image = run!(file.create(image)).get_ref().get_inner();
+    run!(file.set_button_image(widget, image));
+    let file = run!(FileOptions::cached().load(run!(file.save())));
+    let resolver = file.resolver();
+    let dict = run!(run!(resolver.resolve(widget)).into_dictionary());
+    let mk = run!(run!(dict.get("MK").unwrap().clone().resolve(&resolver)).into_dictionary());
+    assert_eq!(mk.get("TP"), Some(&Primitive::Integer(1)));
+    let ap = run!(run!(dict.get("AP").unwrap().clone().resolve(&resolver)).into_dictionary());
+    // the appearance draws the icon, which draws the image
+    let xobjects = |p: &Primitive| {
+        let form = run!(pdf::content::FormXObject::from_primitive(p.clone(), &resolver));
+        let xobjects = form.dict().resources.as_ref().unwrap().xobjects.clone();
+        xobjects.into_values().map(|r| run!(resolver.get(r))).collect::<Vec<_>>()
+    };
+    let icon = xobjects(ap.get("N").unwrap());
+    assert_eq!(icon[0].get_ref().get_inner(), mk.get("I").unwrap().clone().into_reference().unwrap());
+    assert!(matches!(*xobjects(mk.get("I").unwrap())[0], XObject::Image(ref image) if image.width == 2));
+}
+
+#[test]
+fn self_referencing_smask() {
+    // the soft mask of the image is its own soft mask
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R >>".to_vec(),
+        b"<< /Type /XObject /Subtype /Image /Width 2 /Height 1 /ColorSpace /DeviceGray /BitsPerComponent 8 \
+            /SMask 5 0 R /Length 2 >>\nstream\n\x10\x20\nendstream".to_vec(),
+        b"<< /Type /XObject /Subtype /Image /Width 2 /Height 1 /ColorSpace /DeviceGray /BitsPerComponent 8 \
+            /SMask 5 0 R /Mask 4 0 R /Length 2 >>\nstream\n\xff\x00\nendstream".to_vec(),
+    ];
+    let file = run!(FileOptions::cached().load(build_pdf(&objects)));
+    let resolver = file.resolver();
+    let image = match *run!(resolver.get(Ref::<XObject>::new(PlainRef { id: 4, gen: 0 }))) {
+        XObject::Image(ref image) => image.clone(),
+        _ => panic!("not an image"),
+    };
+    let pixels = run!(image.pixels(&resolver));
+    assert_eq!(pixels.data, [0x10, 0x10, 0x10, 0xff,  0x20, 0x20, 0x20, 0x00]);
+}
+
+/// A file with the given objects, numbered from 1, and object 1 as the catalog
+fn build_pdf(objects: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = b"%PDF-1.7\n".to_vec();
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(data.len());
+        data.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        data.extend_from_slice(object);
+        data.extend_from_slice(b"\nendobj\n");
+    }
+    let xref = data.len();
+    data.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        data.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    data.extend_from_slice(format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref).as_bytes());
+    data
+}
+
+#[test]
+fn custom_decoders() {
+    use pdf::enc::Decoders;
+
+    // the page content is "0 0 m" with every byte increased by one
+    let content: Vec<u8> = b"0 0 m".iter().map(|b| b + 1).collect();
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R /Contents 4 0 R >>".to_vec(),
+        [format!("<< /Length {} /Filter /ShiftDecode /DecodeParms << /Shift 1 >> >>\nstream\n", content.len()).as_bytes(), &content, b"\nendstream"].concat(),
+        // a 2x1 gray image, also shifted
+        b"<< /Type /XObject /Subtype /Image /Width 2 /Height 1 /ColorSpace /DeviceGray /BitsPerComponent 8 /Length 2 \
+            /Filter /ShiftDecode /DecodeParms << /Shift 1 >> >>\nstream\n\x11\xf1\nendstream".to_vec(),
+    ];
+    let data = build_pdf(&objects);
+
+    let operations = |decoders: Decoders| {
+        let file = run!(FileOptions::cached().decoders(decoders).load(data.clone()));
+        let page = run!(file.get_page(0));
+        let resolver = file.resolver();
+        let operations = page.contents.as_ref().unwrap().operations(&resolver);
+        operations.map(|ops| format!("{:?}", ops))
+    };
+    let shift = |data: &[u8], params: &pdf::primitive::Dictionary| {
+        let shift = params.get("Shift").map(|p| p.as_integer()).transpose()?.unwrap_or(0) as u8;
+        Ok(data.iter().map(|b| b.wrapping_sub(shift)).collect())
+    };
+
+    // each file has its own decoders
+    assert!(operations(Decoders::new()).is_err());
+    let shifted = run!(operations(Decoders::new().filter("ShiftDecode", shift)));
+    assert!(shifted.contains("MoveTo"), "{}", shifted);
+    let unshifted = operations(Decoders::new().filter("ShiftDecode", |data, _| Ok(data.to_vec())));
+    assert_ne!(unshifted.ok(), Some(shifted));
+
+    let file = run!(FileOptions::cached().decoders(Decoders::new().filter("ShiftDecode", shift)).load(data.clone()));
+    let resolver = file.resolver();
+    let image = match *run!(resolver.get(Ref::<XObject>::new(PlainRef { id: 5, gen: 0 }))) {
+        XObject::Image(ref image) => image.clone(),
+        _ => panic!("not an image"),
+    };
+    assert_eq!(&*run!(image.image_data(&resolver)), &[0x10, 0xf0]);
+    assert_eq!(run!(image.pixels(&resolver)).data, [0x10, 0xf0]);
+}
+
+#[test]
+fn limits() {
+    use pdf::enc::{encode, StreamFilter};
+    use pdf::object::{Limits, ParseOptions};
+
+    let stream = |data: &[u8]| {
+        let filter = StreamFilter::FlateDecode(Default::default());
+        let data = encode(data, &filter).unwrap();
+        [format!("<< /Length {} /Filter /FlateDecode >>\nstream\n", data.len()).as_bytes(), &data, b"\nendstream"].concat()
+    };
+    let content = [&b"0 0 m "[..]; 100].concat();
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R /XTest [(a) (bb) (ccc) <6464646464>] >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R /Contents [4 0 R 5 0 R] >>".to_vec(),
+        stream(&content),
+        stream(&content),
+    ];
+    let data = build_pdf(&objects);
+    let load = |limits: Limits| FileOptions::cached().parse_options(ParseOptions::strict().limits(limits)).load(data.clone());
+    let operations = |limits: Limits| {
+        let file = load(limits)?;
+        let page = file.get_page(0)?;
+        let resolver = file.resolver();
+        let operations = page.contents.as_ref().unwrap().operations(&resolver);
+        operations.map(|ops| ops.len())
+    };
+    let assert_exceeds = |result: pdf::error::Result<usize>, message: &str| {
+        let e = result.unwrap_err();
+        assert!(e.is_limit_exceeded(), "{}", e);
+        assert!(e.to_string().contains(message), "{}", e);
+    };
+
+    assert_eq!(run!(operations(Limits::untrusted())), 200);
+    let exact = Limits {
+        max_stream_size: Some(content.len()),
+        max_decoded_bytes: Some(2 * content.len()),
+        max_objects: Some(5),
+        max_xref_entries: Some(6),
+        max_array_len: Some(4),
+        max_dict_len: Some(4),
+        max_string_len: Some(5),
+    };
+    assert_eq!(run!(operations(exact)), 200);
+
+    assert_exceeds(operations(Limits { max_stream_size: Some(content.len() - 1), ..exact }), "Decoded stream exceeds");
+    assert_exceeds(operations(Limits { max_decoded_bytes: Some(2 * content.len() - 1), ..exact }), "for the document");
+    assert_exceeds(operations(Limits { max_objects: Some(4), ..exact }), "5 objects exceed");
+    assert_exceeds(operations(Limits { max_xref_entries: Some(5), ..exact }), "Xref table of 6 entries");
+    assert_exceeds(operations(Limits { max_array_len: Some(3), ..exact }), "Array exceeds");
+    assert_exceeds(operations(Limits { max_dict_len: Some(3), ..exact }), "Dictionary exceeds");
+    assert_exceeds(operations(Limits { max_string_len: Some(4), ..exact }), "String exceeds");
+}
+
+#[test]
+fn image_limits() {
+    use pdf::enc::{encode, StreamFilter};
+    use pdf::object::{Limits, ParseOptions};
+
+    // three 10x10 gray images, compressed with an image filter
+    let pixels = [7u8; 100];
+    let data = encode(&pixels, &StreamFilter::FlateDecode(Default::default())).unwrap();
+    let image = [
+        format!("<< /Type /XObject /Subtype /Image /Width 10 /Height 10 /ColorSpace /DeviceGray \
+            /BitsPerComponent 8 /Length {} /Filter /FlateDecode >>\nstream\n", data.len()).as_bytes(),
+        &data,
+        b"\nendstream"
+    ].concat();
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R >>".to_vec(),
+        image.clone(),
+        image.clone(),
+        image,
+    ];
+    let data = build_pdf(&objects);
+    let decode_all = |max_decoded_bytes: usize| -> pdf::error::Result<usize> {
+        let limits = Limits { max_decoded_bytes: Some(max_decoded_bytes), ..Limits::none() };
+        let file = FileOptions::cached().parse_options(ParseOptions::tolerant().limits(limits)).load(data.clone())?;
+        let resolver = file.resolver();
+        let mut total = 0;
+        for id in 4 ..= 6 {
+            let image = match *resolver.get(Ref::<XObject>::new(PlainRef { id, gen: 0 }))? {
+                XObject::Image(ref image) => image.clone(),
+                _ => panic!("not an image"),
+            };
+            total += image.image_data(&resolver)?.len();
+        }
+        Ok(total)
+    };
+
+    assert_eq!(run!(decode_all(300)), 300);
+    let e = decode_all(299).unwrap_err();
+    assert!(e.is_limit_exceeded(), "{}", e);
+    assert!(e.to_string().contains("for the document"), "{}", e);
+
+    // an image mask of 2000x2000 pixels decodes to 500 kB, but has 16 MB of RGBA pixels,
+    // and a gray image of 100000x100000 pixels has far too little data
+    let mask = encode(&[0u8; 2000 * 250], &StreamFilter::FlateDecode(Default::default())).unwrap();
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R >>".to_vec(),
+        [
+            format!("<< /Type /XObject /Subtype /Image /Width 2000 /Height 2000 /ImageMask true \
+                /Length {} /Filter /FlateDecode >>\nstream\n", mask.len()).as_bytes(),
+            &mask,
+            b"\nendstream"
+        ].concat(),
+        b"<< /Type /XObject /Subtype /Image /Width 100000 /Height 100000 /ColorSpace /DeviceGray \
+            /BitsPerComponent 8 /Length 9 >>\nstream\n\0\0\0\0\0\0\0\0\0\nendstream".to_vec(),
+    ];
+    let data = build_pdf(&objects);
+    let limits = Limits { max_stream_size: Some(1 << 20), ..Limits::none() };
+    let file = run!(FileOptions::cached().parse_options(ParseOptions::tolerant().limits(limits)).load(data));
+    let resolver = file.resolver();
+    let image = |id| match *run!(resolver.get(Ref::<XObject>::new(PlainRef { id, gen: 0 }))) {
+        XObject::Image(ref image) => image.clone(),
+        _ => panic!("not an image"),
+    };
+    assert_eq!(run!(image(4).image_data(&resolver)).len(), 2000 * 250);
+    match image(4).pixels(&resolver) {
+        Ok(pixels) => panic!("{} bytes of pixels", pixels.data.len()),
+        Err(e) => assert!(e.is_limit_exceeded(), "{}", e),
+    }
+    assert!(image(5).pixels(&resolver).is_err());
+}
+
+#[test]
+fn error_locations() {
+    use pdf::error::{Location, PathSegment};
+
+    // the field in object 5 has a name for /Ff
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R /AcroForm << /Fields [4 0 R 5 0 R] >> >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R >>".to_vec(),
+        b"<< /T (a) >>".to_vec(),
+        b"<< /T (b) /Ff /Bad >>".to_vec(),
+    ];
+    let e = FileOptions::cached().load(build_pdf(&objects)).err().unwrap();
+    let path = ["Root", "AcroForm", "Fields"].map(|key| PathSegment::Key(key.into())).into_iter()
+        .chain([PathSegment::Index(1), PathSegment::Key("Ff".into())])
+        .collect();
+    assert_eq!(e.location(), Some(Location { object: Some(PlainRef { id: 5, gen: 0 }), offset: None, path }));
+    assert!(e.to_string().contains("Error at Ff in object 5 0 R"), "{}", e);
+
+    // a syntax error in the page
+    let mut objects = objects;
+    objects[2] = b"<< /Type /Page /Parent 2 0 R /Rotate ) >>".to_vec();
+    objects[4] = b"<< /T (b) >>".to_vec();
+    let data = build_pdf(&objects);
+    let file = run!(FileOptions::cached().load(data.clone()));
+    let e = file.get_page(0).err().unwrap();
+    let location = e.location().unwrap();
+    assert_eq!(location.object, Some(PlainRef { id: 3, gen: 0 }));
+    // the value of /Rotate starts after the key
+    let pos = data.windows(9).position(|w| w == b"/Rotate )").unwrap() + 7;
+    assert_eq!(location.offset, Some(pos));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn json_dump() {
+    use pdf::json::JsonOptions;
+
+    let objects = [
+        b"<< /Type /Catalog /Pages 2 0 R /AcroForm << /Fields [4 0 R] >> >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 /MediaBox [0 0 200 200] >>".to_vec(),
+        b"<< /Type /Page /Parent 2 0 R /Annots [5 0 R] >>".to_vec(),
+        b"<< /T (address) /Kids [5 0 R] >>".to_vec(),
+        b"<< /Type /Annot /Subtype /Widget /Parent 4 0 R /P 3 0 R /T (street) /FT /Tx /V <FEFF00E6> /AP << /N 6 0 R >> >>".to_vec(),
+        b"<< /Length 12 >>\nstream\n0 0 10 10 re\nendstream".to_vec(),
+    ];
+    let file = run!(FileOptions::cached().load(build_pdf(&objects)));
+    let options = JsonOptions::new();
+
+    let json = file.to_json(&options);
+    assert_eq!(json["version"], "1.7");
+    assert_eq!(json["trailer"]["/Root"], "1 0 R");
+    assert_eq!(json["xref"].as_array().unwrap().len(), 7);
+    assert_eq!(json["xref"][0]["type"], "free");
+    assert_eq!(json["xref"][1]["type"], "uncompressed");
+    assert_eq!(json["xref"][1]["offset"], 9);
+    assert_eq!(json["objects"]["2 0 R"]["/MediaBox"], serde_json::json!([0, 0, 200, 200]));
+    assert_eq!(json["objects"]["5 0 R"]["/V"], "u:æ");
+    assert_eq!(json["objects"]["6 0 R"]["stream"]["dict"]["/Length"], 12);
+    assert!(json["objects"]["6 0 R"]["stream"].get("data").is_none());
+
+    let options = options.stream_data(true);
+    let json = file.object_to_json(PlainRef { id: 6, gen: 0 }, &options);
+    assert_eq!(json["stream"]["data"], "u:0 0 10 10 re");
+
+    // the field and what it refers to, but not the page or its parent
+    let json = run!(file.field_to_json("address.street", &options));
+    assert_eq!(json["object"], "5 0 R");
+    let objects: Vec<_> = json["objects"].as_object().unwrap().keys().cloned().collect();
+    assert_eq!(objects, ["5 0 R", "6 0 R"]);
+    assert!(file.field_to_json("address.city", &options).is_err());
+}
@@ -616,7 +616,7 @@ fn impl_object_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream {
                             pdf::error::PdfError::FromPrimitive {
                                 typ: #typ,
                                 field: stringify!(#name),
-                                source: Box::new(e)
+                                source: Box::new(e.with_key(#key))
                             })?,
                         None => #default,
                     };
@@ -633,7 +633,7 @@ fn impl_object_for_struct(ast: &DeriveInput, fields: &Fields) -> SynStream {
                                 Err(e) => return Err(pdf::error::PdfError::FromPrimitive {
                                     typ: stringify!(#ty),
                                     field: stringify!(#name),
-                                    source: Box::new(e)
+                                    source: Box::new(e.with_key(#key))
                                 })
                             }
                         None =>  // Try to construct T from Primitive::Null